@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+
+use crate::*;
+
+/// Loads the words of an AFL/libFuzzer style dictionary file.
+/// Words with a level above max_level are skipped.
+pub fn load_dict(path: &Path, max_level: u32) -> Result<Vec<Vec<u8>>> {
+    let contents = fs::read_to_string(path)?;
+    let mut words = Vec::new();
+    for (line_num, line) in contents.lines().enumerate() {
+        match parse_dict_line(line, max_level) {
+            Ok(Some(word)) => words.push(word),
+            Ok(None) => {}
+            Err(e) => {
+                return Err(From::from(format!(
+                    "{}:{} : {}",
+                    path.to_string_lossy(),
+                    line_num + 1,
+                    e
+                )))
+            }
+        }
+    }
+    Ok(words)
+}
+
+/// Parses a dictionary line with the format : [name[@level]=]"value"
+pub fn parse_dict_line(line: &str, max_level: u32) -> std::result::Result<Option<Vec<u8>>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    // Skip the optional name
+    let (name, value) = match line.find('"') {
+        Some(idx) if line.ends_with('"') && idx < line.len() - 1 => {
+            (&line[..idx], &line[idx + 1..line.len() - 1])
+        }
+        _ => return Err("Malformed name=\"value\" pair".to_string()),
+    };
+
+    // Only keep the words of the requested level
+    if let Some((_, level)) = name.trim_end().trim_end_matches('=').rsplit_once('@') {
+        match level.trim().parse::<u32>() {
+            Ok(l) if l > max_level => return Ok(None),
+            Ok(_) => {}
+            Err(_) => return Err(format!("Invalid level '{}'", level)),
+        }
+    }
+
+    let mut word = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next() {
+                Some(b'\\') => word.push(b'\\'),
+                Some(b'"') => word.push(b'"'),
+                Some(b'x') => {
+                    let hex = [bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+                    let hex = std::str::from_utf8(&hex).unwrap_or("");
+                    match u8::from_str_radix(hex, 16) {
+                        Ok(v) => word.push(v),
+                        Err(_) => return Err("Invalid escaping (not \\xNN)".to_string()),
+                    }
+                }
+                _ => return Err("Invalid escaping".to_string()),
+            },
+            b if !(32..128).contains(&b) => {
+                return Err("Non-printable characters in line".to_string())
+            }
+            b => word.push(b),
+        }
+    }
+
+    if word.is_empty() {
+        return Err("Empty keyword".to_string());
+    }
+
+    Ok(Some(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_and_escapes() {
+        assert_eq!(parse_dict_line("\"abc\"", 0), Ok(Some(b"abc".to_vec())));
+        assert_eq!(
+            parse_dict_line("  kw1=\"a\\\"b\\\\\\x00\\xFF\"  ", 0),
+            Ok(Some(b"a\"b\\\x00\xff".to_vec()))
+        );
+        assert_eq!(parse_dict_line("# comment", 0), Ok(None));
+        assert_eq!(parse_dict_line("", 0), Ok(None));
+    }
+
+    #[test]
+    fn skips_higher_levels() {
+        assert_eq!(parse_dict_line("kw@1=\"a\"", 0), Ok(None));
+        assert_eq!(parse_dict_line("kw@1=\"a\"", 1), Ok(Some(b"a".to_vec())));
+        assert!(parse_dict_line("kw@x=\"a\"", 0).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in ["abc", "\"abc", "\"\"", "\"\\q\"", "\"\\xZZ\"", "\"a\tb\""] {
+            assert!(parse_dict_line(line, 0).is_err(), "{}", line);
+        }
+    }
+}
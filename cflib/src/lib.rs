@@ -9,6 +9,10 @@ mod input;
 pub use input::*;
 mod store;
 pub use store::*;
+mod regions;
+pub use regions::*;
+mod dict;
+pub use dict::*;
 
 #[allow(improper_ctypes_definitions)]
 mod core;
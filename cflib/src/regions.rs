@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+/// Extension of the sidecar file holding the mutable regions of an input
+pub const REGIONS_EXT: &str = "regions";
+
+/// Byte ranges of an input that mutators are allowed to change
+#[derive(Debug, Default, Clone)]
+pub struct MutableRegions {
+    /// [start, end[ ranges, a missing end means the end of the input
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+fn parse_offset(s: &str) -> Result<usize> {
+    let res = if let Some(hex) = s.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else {
+        s.parse::<usize>()
+    };
+    res.map_err(|e| From::from(format!("Invalid offset '{}' : {}", s, e)))
+}
+
+impl MutableRegions {
+    /// Parses ranges separated by ',' or new lines (`start..end`, `start..`, `..end`).
+    /// Lines starting with '#' are ignored.
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for line in src.lines().map(|l| l.trim()) {
+            if line.starts_with('#') {
+                continue;
+            }
+            for range in line.split(',').map(|r| r.trim()).filter(|r| !r.is_empty()) {
+                let (start, end) = match range.split_once("..") {
+                    Some(v) => v,
+                    None => {
+                        return Err(From::from(format!(
+                            "Expected start..end but got '{}'",
+                            range
+                        )))
+                    }
+                };
+                let start = match start.trim() {
+                    "" => 0,
+                    s => parse_offset(s)?,
+                };
+                let end = match end.trim() {
+                    "" => None,
+                    e => Some(parse_offset(e)?),
+                };
+                if let Some(e) = end {
+                    if e <= start {
+                        return Err(From::from(format!("Range '{}' is empty", range)));
+                    }
+                }
+                ranges.push((start, end));
+            }
+        }
+        Ok(Self { ranges })
+    }
+
+    /// Path of the `<input>.regions` sidecar file
+    pub fn sidecar_path(input_path: &Path) -> PathBuf {
+        let mut path = input_path.as_os_str().to_owned();
+        path.push(".");
+        path.push(REGIONS_EXT);
+        PathBuf::from(path)
+    }
+
+    /// Loads the `<input>.regions` sidecar file if it exists
+    pub fn from_sidecar(input_path: &Path) -> Result<Option<Self>> {
+        let path = Self::sidecar_path(input_path);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(Self::parse(&fs::read_to_string(path)?)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Sorted and merged mutable ranges for an input of len bytes
+    pub fn resolve(&self, len: usize) -> Vec<Range<usize>> {
+        let mut res: Vec<Range<usize>> = self
+            .ranges
+            .iter()
+            .map(|(s, e)| *s..std::cmp::min(e.unwrap_or(len), len))
+            .filter(|r| !r.is_empty())
+            .collect();
+        res.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(res.len());
+        for r in res {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = std::cmp::max(last.end, r.end),
+                _ => merged.push(r),
+            }
+        }
+        merged
+    }
+}
+
+/// Mutable regions of the inputs, from the `mutate_regions` config or sidecar files.
+/// fs_store copies the sidecar of the selected input next to the new inputs it saves.
+#[derive(Debug, Default)]
+pub struct InputRegions {
+    /// Regions of inputs without a sidecar file
+    global: Option<MutableRegions>,
+    /// Sidecar regions of the inputs we have seen, by index in INPUT_LIST
+    sidecars: HashMap<usize, Option<MutableRegions>>,
+}
+
+impl InputRegions {
+    pub fn from_conf(plugin_conf: &HashMap<String, String>) -> Result<Self> {
+        let global = match plugin_conf.get("mutate_regions") {
+            Some(v) => Some(MutableRegions::parse(v)?),
+            None => None,
+        };
+        Ok(Self {
+            global,
+            sidecars: HashMap::new(),
+        })
+    }
+
+    /// Regions that apply to an input, None when it can be mutated freely.
+    /// Errors are only returned the first time the sidecar is read.
+    pub fn get(&mut self, idx: usize, info: &CfInputInfo) -> Result<Option<&MutableRegions>> {
+        let global = self.global.as_ref();
+        let mut err = None;
+        let sidecar = self.sidecars.entry(idx).or_insert_with(|| match info.path {
+            Some(ref p) => match MutableRegions::from_sidecar(p) {
+                Ok(r) => r,
+                Err(e) => {
+                    err = Some(e);
+                    None
+                }
+            },
+            None => None,
+        });
+        if let Some(e) = err {
+            return Err(e);
+        }
+        Ok(sidecar.as_ref().or(global).filter(|r| !r.is_empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        let r = MutableRegions::parse("# header\n0x10..0x20, 4..8\n\n..2,100..").unwrap();
+        assert_eq!(
+            r.ranges,
+            vec![(16, Some(32)), (4, Some(8)), (0, Some(2)), (100, None)]
+        );
+        assert!(MutableRegions::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for src in ["4", "8..4", "4..4", "x..4", "0x..4"] {
+            assert!(MutableRegions::parse(src).is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn resolves_against_input_len() {
+        let r = MutableRegions::parse("10..20, 0..4, 2..6, 18..30, 50..").unwrap();
+        assert_eq!(r.resolve(25), vec![0..6, 10..25]);
+        assert_eq!(r.resolve(60), vec![0..6, 10..30, 50..60]);
+        assert_eq!(r.resolve(0), vec![]);
+    }
+
+    #[test]
+    fn sidecar_is_next_to_the_input() {
+        assert_eq!(
+            MutableRegions::sidecar_path(Path::new("queue/id_1.bin")),
+            PathBuf::from("queue/id_1.bin.regions")
+        );
+    }
+}
@@ -55,6 +55,8 @@ pub(crate) const TAG_POSTFIXES: &[&str] = &[
 pub const STAT_TARGET_EXEC_TIME: &str = "avg_target_exec_time_ns";
 /// Number crashes
 pub const STAT_NUM_CRASHES: &str = "total_crashes_res";
+/// Number of crashes after deduplication
+pub const STAT_NUM_UNIQUE_CRASHES: &str = "total_unique_crashes_res";
 /// Number of timeouts
 pub const STAT_NUM_TIMEOUTS: &str = "total_timeouts_res";
 
@@ -57,6 +57,8 @@ pub const STORE_RESULTS_DIR: &str = "results_dir";
 pub const STORE_TARGET_BIN: &str = "target_bin";
 /// (*const Vec<String>) List of arguments passed to the target
 pub const STORE_TARGET_ARGS: &str = "target_args";
+/// (*const u32) CRC32 of the target binary, same as the target_crc stat
+pub const STORE_TARGET_CRC: &str = "target_crc";
 /// (*const String) Current working directory
 pub const STORE_CWD: &str = "cwd";
 /// (*const String) Name of the current fuzzer
@@ -93,14 +95,32 @@ pub const STORE_INPUT_BYTES: &str = "input_bytes";
 pub const STORE_RESTORE_INPUT: &str = "restore_input";
 /// (*mut BinaryHeap<InputPriority>) Holds a priority queue of input indexes
 pub const STORE_INPUT_PRIORITY: &str = "input_priority";
+/// (*mut Vec<u64>) Number of new inputs/results credited to each input of INPUT_LIST
+pub const STORE_INPUT_REWARDS: &str = "input_rewards";
 
 /* Target exec */
+/// (*mut CfInput) Bytes to execute when they differ from INPUT_BYTES (e.g. encoded).
+/// The first plugin to transform the input copies INPUT_BYTES in it, the others rewrite it.
+pub const STORE_EXEC_BYTES: &str = "exec_bytes";
+/// (*mut bool) Whether the current input should not be run (e.g. rejected by a post processor)
+pub const STORE_SKIP_EXEC: &str = "skip_exec";
 /// (*mut TargetExitStatus) The exit status for the last run
 pub const STORE_EXIT_STATUS: &str = "exit_status";
 /// (*const u64) Number of nanoseconds the target took to run the last input
 pub const STORE_TARGET_EXEC_TIME: &str = "exec_time";
 /// (*const u64) Average nanoseconds the target takes to run
 pub const STORE_AVG_TARGET_EXEC_TIME: &str = "avg_exec_time";
+/// (*const TargetOutput) Captured stdout of the last run
+pub const STORE_TARGET_STDOUT: &str = "target_stdout";
+/// (*const TargetOutput) Captured stderr of the last run
+pub const STORE_TARGET_STDERR: &str = "target_stderr";
+/// (*const Option<SanitizerReport>) Sanitizer report of the last run, None if there was none
+pub const STORE_SANITIZER_REPORT: &str = "sanitizer_report";
+/// (*const u64) Peak resident set size of the last run in KB
+pub const STORE_TARGET_PEAK_RSS: &str = "target_peak_rss";
+/// (*const Option<TargetLimit>) Resource limit that stopped the last run, None if there was none.
+/// These runs are not crashes of the target.
+pub const STORE_TARGET_LIMIT_HIT: &str = "target_limit_hit";
 
 #[derive(SpRead, SpWrite)]
 #[sp(id_type = "u8")]
@@ -113,6 +133,36 @@ pub enum TargetExitStatus {
     Crash(i32),
 }
 
+/// Output of a target stream, bounded to a maximum size
+#[derive(Debug, Default)]
+pub struct TargetOutput {
+    pub data: Vec<u8>,
+    /// Whether the target wrote more than what was kept
+    pub truncated: bool,
+}
+
+/// Resource limit a target ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetLimit {
+    Memory,
+    FileSize,
+}
+
+/// Bug reported by ASan, UBSan, MSan...
+#[derive(Debug, Default, Clone)]
+pub struct SanitizerReport {
+    /// e.g. AddressSanitizer
+    pub sanitizer: String,
+    /// e.g. heap-buffer-overflow
+    pub bug_type: String,
+    /// e.g. "READ of size 4"
+    pub access: Option<String>,
+    /// Top frames of the stack trace
+    pub frames: Vec<String>,
+    /// Exit code of targets that exited instead of aborting after the report
+    pub exit_code: Option<i32>,
+}
+
 fn get_valid_ptr(store: &CfStore, key: &str) -> Result<*mut u8> {
     if let Some(v) = store.get(key) {
         if v.is_null() {
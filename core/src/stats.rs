@@ -176,12 +176,14 @@ impl<'b> CfCore<'b> {
 
         tag.clear();
         let _ = write!(&mut tag, "target_crc{}", cflib::TAG_POSTFIX_HEX);
+        // Also shared with the plugins through the store
+        self.store.target_crc =
+            crc::crc32::checksum_ieee(&std::fs::read(&*self.config.target).unwrap());
         self.stats.target_hash = match self.ctx.stats.new_stat(
             &tag,
             NewStat::Bytes {
                 max_size: 4,
-                init_val: &crc::crc32::checksum_ieee(&std::fs::read(&*self.config.target).unwrap())
-                    .to_le_bytes(),
+                init_val: &self.store.target_crc.to_le_bytes(),
             },
         ) {
             Ok(StatVal::Bytes(v)) => v,
@@ -5,6 +5,7 @@ use std::collections::HashMap;
 
 pub struct Store {
     pub avg_denominator: u64,
+    pub target_crc: u32,
     pub no_mutate: bool,
     pub no_select: bool,
     pub content: CfStore,
@@ -14,6 +15,7 @@ impl Default for Store {
     fn default() -> Self {
         Self {
             avg_denominator: 0,
+            target_crc: 0,
             no_mutate: false,
             no_select: false,
             content: HashMap::new(),
@@ -30,6 +32,7 @@ impl<'a> CfCore<'a> {
         let _ = store.insert_exclusive(STORE_RESULTS_DIR, &self.config.results, None);
         let _ = store.insert_exclusive(STORE_TARGET_BIN, &self.config.target, None);
         let _ = store.insert_exclusive(STORE_TARGET_ARGS, &self.config.target_args, None);
+        let _ = store.insert_exclusive(STORE_TARGET_CRC, &self.store.target_crc, None);
         let _ = store.insert_exclusive(STORE_CWD, &self.config.cwd, None);
         let _ = store.insert_exclusive(STORE_FUZZER_NAME, &self.config.prefix, None);
         let _ = store.insert_exclusive(STORE_FUZZER_ID, &self.config.instance_id, None);
@@ -49,6 +52,7 @@ impl<'a> CfCore<'a> {
         store.remove(STORE_RESULTS_DIR).unwrap();
         store.remove(STORE_TARGET_BIN).unwrap();
         store.remove(STORE_TARGET_ARGS).unwrap();
+        store.remove(STORE_TARGET_CRC).unwrap();
         store.remove(STORE_CWD).unwrap();
         store.remove(STORE_FUZZER_NAME).unwrap();
         store.remove(STORE_FUZZER_ID).unwrap();
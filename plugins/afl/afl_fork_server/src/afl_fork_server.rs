@@ -39,6 +39,8 @@ pub struct State {
     pub avg_exec_time: StatNum,
     pub avg_denominator: &'static u64,
     pub cur_input: &'static CfInput,
+    /// Set when the current input should not be run
+    pub skip_exec: Option<&'static bool>,
 }
 
 // Initialize our plugin
@@ -66,6 +68,7 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
             target_bin: store.as_ref(STORE_TARGET_BIN, Some(core))?,
             // Plugin store values
             cur_input: MaybeUninit::zeroed().assume_init(),
+            skip_exec: None,
         }
     });
 
@@ -130,8 +133,12 @@ fn validate(
 
     unsafe {
         s.afl = store.as_mutref(STORE_AFL_GLOBALS, Some(core))?;
-        // Make sure someone is providing us input bytes
-        s.cur_input = store.as_ref(STORE_INPUT_BYTES, Some(core))?;
+        // Make sure someone is providing us input bytes, prefer the ones meant for execution
+        s.cur_input = match store.as_ref(STORE_EXEC_BYTES, None) {
+            Ok(v) => v,
+            Err(_) => store.as_ref(STORE_INPUT_BYTES, Some(core))?,
+        };
+        s.skip_exec = store.as_ref(STORE_SKIP_EXEC, None).ok();
     }
 
     s.ctx = os::State::new(s, core, store)?;
@@ -147,6 +154,14 @@ fn run_target(
 ) -> Result<()> {
     let s = box_ref!(plugin_ctx, State);
 
+    // Report a clean run without executing rejected inputs, the trace bits
+    // are left untouched from the previous run
+    if matches!(s.skip_exec, Some(true)) {
+        s.exit_status = TargetExitStatus::Normal(0);
+        s.exec_time = 0;
+        return Ok(());
+    }
+
     // Update file on disk
     if let Some(ref mut f) = s.input_file {
         let _ = (f.set_len(0), f.seek(std::io::SeekFrom::Start(0)));
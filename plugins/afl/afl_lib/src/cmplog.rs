@@ -0,0 +1,84 @@
+/* Layout of the AFL++ (4.x) cmplog shared map */
+
+pub const CMP_MAP_W: usize = 65536;
+pub const CMP_MAP_H: usize = 32;
+pub const CMP_MAP_RTN_H: usize = CMP_MAP_H / 2;
+pub const CMP_TYPE_INS: u8 = 0;
+pub const CMP_TYPE_RTN: u8 = 1;
+/// Env variable the cmplog runtime gets its shared memory id from
+pub const CMPLOG_SHM_ENV_VAR: &str = "__AFL_CMPLOG_SHM_ID";
+
+/// Packed `hits:6 shape:5 type:1 attribute:4` bitfield
+#[derive(Copy, Clone, Default)]
+#[repr(transparent)]
+pub struct CmpHeader(u16);
+impl CmpHeader {
+    /// Number of times the comparison was logged
+    pub fn hits(self) -> usize {
+        (self.0 & 0x3F) as usize
+    }
+    /// Size of the operands in bytes
+    pub fn size(self) -> usize {
+        ((self.0 >> 6) & 0x1F) as usize + 1
+    }
+    /// CMP_TYPE_INS or CMP_TYPE_RTN
+    pub fn kind(self) -> u8 {
+        ((self.0 >> 11) & 1) as u8
+    }
+    pub fn attribute(self) -> u8 {
+        (self.0 >> 12) as u8
+    }
+}
+
+/// Operands of a comparison instruction
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct CmpOperands {
+    pub v0: u64,
+    pub v0_128: u64,
+    pub v0_256: u64,
+    pub v0_384: u64,
+    pub v1: u64,
+    pub v1_128: u64,
+    pub v1_256: u64,
+    pub v1_384: u64,
+    unused: [u8; 8],
+}
+
+/// Operands of a call to a comparison routine (memcmp, strcmp, ...)
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct CmpFnOperands {
+    v0: [u8; 32],
+    v1: [u8; 32],
+    v0_len: u8,
+    v1_len: u8,
+    unused: [u8; 6],
+}
+impl CmpFnOperands {
+    pub fn v0(&self) -> &[u8] {
+        &self.v0[..std::cmp::min(self.v0_len as usize, self.v0.len())]
+    }
+    pub fn v1(&self) -> &[u8] {
+        &self.v1[..std::cmp::min(self.v1_len as usize, self.v1.len())]
+    }
+}
+
+#[repr(C)]
+pub struct CmpMap {
+    pub headers: [CmpHeader; CMP_MAP_W],
+    pub log: [[CmpOperands; CMP_MAP_H]; CMP_MAP_W],
+}
+impl CmpMap {
+    /// Resets the hit counts before running the cmplog target
+    pub fn clear(&mut self) {
+        for h in self.headers.iter_mut() {
+            *h = CmpHeader::default();
+        }
+    }
+
+    /// Routine operands logged by comparison site `idx`
+    pub fn fn_log(&self, idx: usize) -> &[CmpFnOperands; CMP_MAP_RTN_H] {
+        unsafe { &*(self.log[idx].as_ptr() as *const [CmpFnOperands; CMP_MAP_RTN_H]) }
+    }
+}
@@ -14,4 +14,50 @@ pub const CAL_CYCLES: u8 = 8;
 pub const CAL_CYCLES_LONG: u8 = 40;
 pub const MAP_SIZE_POW2: usize = 16;
 pub const MAP_SIZE: usize = 1 << MAP_SIZE_POW2;
-pub const HASH_CONST: u32 = 0xa5b35705;
\ No newline at end of file
+pub const HASH_CONST: u32 = 0xa5b35705;
+pub const MAX_DICT_FILE: usize = 128;
+pub const MAX_DET_EXTRAS: usize = 200;
+pub const MIN_AUTO_EXTRA: usize = 3;
+pub const MAX_AUTO_EXTRA: usize = 32;
+pub const USE_AUTO_EXTRAS: usize = 50;
+pub const MAX_AUTO_EXTRAS: usize = USE_AUTO_EXTRAS * 10;
+pub const EFF_MAP_SCALE2: usize = 3;
+pub const EFF_MIN_LEN: usize = 128;
+pub const EFF_MAX_PERC: usize = 90;
+pub const SPLICE_CYCLES: u32 = 15;
+pub const SPLICE_HAVOC: u32 = 32;
+pub const MAX_CMPLOG_PAIRS: usize = 4096;
+
+pub const INTERESTING_8: &[u8] = &[
+    0,   /*                                         */
+    1,   /*                                         */
+    16,  /* One-off with common buffer size         */
+    32,  /* One-off with common buffer size         */
+    64,  /* One-off with common buffer size         */
+    100, /* One-off with common buffer size         */
+    127, /*                                         */
+    128, /* Overflow signed 8-bit when decremented  */
+    255, /* u8::MAX                                 */
+];
+pub const INTERESTING_16: &[u16] = &[
+    0, 1, 16, 32, 64, 100, 127, 128, 128, 255,   /* Overflow signed 8-bit                   */
+    256,   /* Overflow unsig 8-bit                    */
+    512,   /* One-off with common buffer size         */
+    1000,  /* One-off with common buffer size         */
+    1024,  /* One-off with common buffer size         */
+    4096,  /* One-off with common buffer size         */
+    32767, /* Overflow signed 16-bit when incremented */
+    32768, /* Overflow signed 16-bit when decremented */
+    65407, /* Overflow signed 8-bit                   */
+    65535, /* u16::MAX                                */
+];
+pub const INTERESTING_32: &[u32] = &[
+    0, 1, 16, 32, 64, 100, 127, 128, 128, 255, 256, 512, 1000, 1024, 4096, 32767, 32768, 65407,
+    65535, 65536,      /* Overflow unsig 16 bit                   */
+    100663045,  /* Large positive number (endian-agnostic) */
+    2147483647, /* Overflow signed 32-bit when incremented */
+    2147483648, /* Overflow signed 32-bit when decremented */
+    4194304250, /* Large negative number (endian-agnostic) */
+    4294934527, /* Overflow signed 16-bit                  */
+    4294967295, /* u32::MAX                                */
+];
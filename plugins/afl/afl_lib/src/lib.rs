@@ -1,5 +1,7 @@
 mod defines;
 pub use defines::*;
+mod cmplog;
+pub use cmplog::*;
 
 /// (*mut AflState) Contains the state that most afl plugins need to function
 pub const STORE_AFL_GLOBALS: &str = "afl_globals";
@@ -7,14 +9,19 @@ pub const STORE_AFL_GLOBALS: &str = "afl_globals";
 pub const STORE_AFL_QUEUE: &str = "afl_queue";
 /// (*mut [u8; MAP_SIZE]) Holds the AFL coverage trace
 pub const STORE_AFL_TRACE_BITS: &str = "afl_trace_bits";
+/// (*mut CmpMap) Comparisons logged by the last run of the cmplog target
+pub const STORE_CMPLOG_MAP: &str = "cmplog_map";
+/// (*mut bool) Whether the cmplog target should run before the next execution
+pub const STORE_CMPLOG_RUN: &str = "cmplog_run";
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 #[repr(C)]
 pub struct AflQueueEntry {
     pub cal_left: u8,
     time_done: bool,
     was_fuzzed: bool,
     pub passed_det: bool,
+    pub passed_cmplog: bool,
     pub has_new_cov: bool,
     pub var_behavior: bool,
     favored: bool,
@@ -25,6 +32,8 @@ pub struct AflQueueEntry {
     pub exec_us: u64,
     pub handicap: u64,
     pub depth: u64,
+    /// Index of every edge hit when this entry was calibrated
+    pub edges: Vec<u32>,
 }
 impl Default for AflQueueEntry {
     fn default() -> Self {
@@ -33,6 +42,7 @@ impl Default for AflQueueEntry {
             time_done: false,
             was_fuzzed: false,
             passed_det: false,
+            passed_cmplog: false,
             has_new_cov: false,
             var_behavior: false,
             favored: false,
@@ -41,6 +51,7 @@ impl Default for AflQueueEntry {
             exec_us: 0,
             handicap: 0,
             depth: 0,
+            edges: Vec::new(),
         }
     }
 }
@@ -61,6 +72,12 @@ pub struct AflGlobals {
     pub total_cal_cycles: u64,
     pub total_bitmap_size: u64,
     pub total_bitmap_entries: u64,
+    /// Focus on inputs that hit rarely exercised edges (FairFuzz)
+    pub rare_edges: bool,
+    /// Number of executions that hit each edge (only kept in rare_edges mode)
+    pub edge_hits: Vec<u64>,
+    /// Edges hit less than this many times are considered rare
+    pub rare_cutoff: u64,
 }
 impl Default for AflGlobals {
     fn default() -> Self {
@@ -78,6 +95,9 @@ impl Default for AflGlobals {
             total_cal_cycles: 0,
             total_bitmap_size: 0,
             total_bitmap_entries: 0,
+            rare_edges: false,
+            edge_hits: Vec::new(),
+            rare_cutoff: 0,
         }
     }
 }
@@ -207,3 +227,53 @@ pub fn has_new_bits(virgin_map: &mut [u8], trace_bits: &[u8]) -> u8 {
 
     ret
 }
+
+/* Simplify trace into dst by eliminating hit count information
+and replacing it with 0x80 or 0x01 depending on whether the tuple
+is hit or not. Called on every new crash or timeout, should be
+reasonably fast. */
+pub fn simplify_trace(trace_bits: &[u8], dst: &mut Vec<u8>) {
+    dst.clear();
+    dst.extend(trace_bits.iter().map(|b| if *b == 0 { 0x01 } else { 0x80 }));
+}
+
+/* Add the edges hit in trace_bits to the total hit counts. Zero words
+are skipped as most of the map is usually empty. */
+pub fn increment_hits(edge_hits: &mut [u64], trace_bits: &[u8]) {
+    for (word_idx, word) in trace_bits.chunks_exact(8).enumerate() {
+        if word.iter().all(|b| *b == 0) {
+            continue;
+        }
+        for (i, b) in word.iter().enumerate() {
+            if *b != 0 {
+                unsafe {
+                    *edge_hits.get_unchecked_mut((word_idx << 3) + i) += 1;
+                }
+            }
+        }
+    }
+}
+
+/* Compute the hit count under which an edge is considered rare. This is
+the smallest power of two strictly above the lowest hit count. */
+pub fn rare_cutoff(edge_hits: &[u64]) -> u64 {
+    let min_hits = edge_hits.iter().filter(|h| **h > 0).min().copied();
+    match min_hits {
+        Some(v) => (v + 1).next_power_of_two(),
+        None => 0,
+    }
+}
+
+/// Returns the rarest edge in edges if it hits less than cutoff times
+pub fn rarest_edge(edges: &[u32], edge_hits: &[u64], cutoff: u64) -> Option<u32> {
+    let mut res = None;
+    let mut lowest = cutoff;
+    for edge in edges {
+        let hits = unsafe { *edge_hits.get_unchecked(*edge as usize) };
+        if hits < lowest {
+            lowest = hits;
+            res = Some(*edge);
+        }
+    }
+    res
+}
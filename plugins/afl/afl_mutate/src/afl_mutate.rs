@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
 
 pub use ::afl_lib::*;
 pub use ::cflib::*;
@@ -13,6 +15,22 @@ mod interesting;
 pub use interesting::*;
 mod havoc;
 pub use havoc::*;
+mod mask;
+pub use mask::*;
+mod rare;
+pub use rare::*;
+mod extras;
+pub use extras::*;
+mod auto_extras;
+pub use auto_extras::*;
+mod effector;
+pub use effector::*;
+mod splice;
+pub use splice::*;
+mod mopt;
+pub use mopt::*;
+mod input_to_state;
+pub use input_to_state::*;
 
 cflib::register!(name, env!("CARGO_PKG_NAME"));
 cflib::register!(load, init);
@@ -28,6 +46,28 @@ struct State {
     stage_name: String,
     /// State info for the current mutator stage
     cur_stage: MutatorStage,
+    /// Restrictions on which bytes can be mutated
+    mask: ByteMask,
+    /// Byte ranges the user allows us to mutate
+    regions: InputRegions,
+    /// Bytes worth mutating for the current input
+    eff_map: EffectorMap,
+    /// Tokens loaded from the user's dictionaries
+    user_extras: Vec<Vec<u8>>,
+    /// Tokens found while flipping bits
+    auto_extras: AutoExtras,
+    /// Where the auto extras are persisted
+    auto_extras_dir: PathBuf,
+    /// Operators applied by the last havoc iteration
+    havoc_ops: Vec<u8>,
+    /// Havoc operator scheduler
+    mopt: Option<MOpt>,
+    /// Current MOpt phase and operator distribution
+    stat_mopt: Option<StatStr>,
+    /// Reward count of the input we mutated last
+    last_reward: u64,
+    /// Number of finds each havoc operator took part in
+    stat_havoc_finds: Vec<(&'static str, StatNum)>,
     /// Stage name that lives in the fuzzer stats memory
     stat_cur_stage: StatStr,
     /// Stage total terations that lives in the fuzzer stats memory
@@ -44,17 +84,32 @@ struct State {
     cur_input: &'static mut CfInput,
     afl: &'static mut AflGlobals,
     afl_queue: &'static mut AflQueue,
+    trace_bits: Option<&'static Vec<u8>>,
+    input_rewards: Option<&'static Vec<u64>>,
+    cmplog_map: Option<&'static CmpMap>,
+    cmplog_run: Option<&'static mut bool>,
 }
 
 // Initialize our plugin
 fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
     #[allow(invalid_value)]
-    let state = Box::new(unsafe {
+    let mut state = Box::new(unsafe {
         State {
             force_update: true,
             prev_input_idx: 0,
             stage_name: String::new(),
             cur_stage: MutatorStage::default(),
+            mask: ByteMask::default(),
+            regions: InputRegions::default(),
+            eff_map: EffectorMap::default(),
+            user_extras: Vec::new(),
+            auto_extras: AutoExtras::default(),
+            auto_extras_dir: PathBuf::new(),
+            havoc_ops: Vec::with_capacity(1 << HAVOC_STACK_POW2),
+            mopt: None,
+            stat_mopt: None,
+            last_reward: 0,
+            stat_havoc_finds: Vec::new(),
             // Stats
             stat_cur_stage: core.new_stat_str("stage", 128, "[init]")?,
             stat_stage_progress: core.new_stat_num("progress", 0)?,
@@ -70,9 +125,42 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
             cur_input: MaybeUninit::zeroed().assume_init(),
             afl: MaybeUninit::zeroed().assume_init(),
             afl_queue: MaybeUninit::zeroed().assume_init(),
+            trace_bits: None,
+            input_rewards: None,
+            cmplog_map: None,
+            cmplog_run: None,
         }
     });
 
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+    state.load_conf(core, plugin_conf)?;
+
+    for name in HAVOC_OP_NAMES.iter() {
+        if state.stat_havoc_finds.iter().any(|(n, _)| n == name) {
+            continue;
+        }
+        let stat = core.new_stat_num(&format!("{}havoc_{}", TAG_PREFIX_TOTAL, name), 0)?;
+        state.stat_havoc_finds.push((name, stat));
+    }
+
+    // Reload the auto extras from a previous run
+    let state_dir: &String = unsafe { store.as_ref(STORE_STATE_DIR, Some(core))? };
+    state.auto_extras_dir.push(state_dir);
+    state.auto_extras_dir.push("auto_extras");
+    match state
+        .auto_extras
+        .load(&state.auto_extras_dir, &state.user_extras)
+    {
+        Ok(0) => {}
+        Ok(num) => core.info(&format!("Loaded {} auto extras", num)),
+        Err(e) => core.warn(&format!(
+            "Failed to load auto extras from '{}' : {}",
+            state.auto_extras_dir.to_string_lossy(),
+            e
+        )),
+    };
+
     Ok(Box::into_raw(state) as _)
 }
 
@@ -98,6 +186,17 @@ fn validate(
             }
         };
         state.afl_queue = store.as_mutref(STORE_AFL_QUEUE, Some(core))?;
+        state.trace_bits = store.as_ref(STORE_AFL_TRACE_BITS, None).ok();
+        state.input_rewards = store.as_ref(STORE_INPUT_REWARDS, None).ok();
+        state.cmplog_map = store.as_ref(STORE_CMPLOG_MAP, None).ok();
+        state.cmplog_run = store.as_mutref(STORE_CMPLOG_RUN, None).ok();
+    }
+
+    if state.input_rewards.is_none() {
+        if state.mopt.is_some() {
+            core.warn("MOpt cant learn without input rewards ! Is the `select_input` plugin running ?");
+        }
+        core.warn("Havoc finds wont be counted without input rewards ! Is the `select_input` plugin running ?");
     }
 
     Ok(())
@@ -105,7 +204,7 @@ fn validate(
 
 // Perform our task in the fuzzing loop
 fn mutate_input(
-    _core: &mut dyn PluginInterface,
+    core: &mut dyn PluginInterface,
     _store: &mut CfStore,
     plugin_ctx: *mut u8,
 ) -> Result<()> {
@@ -121,19 +220,64 @@ fn mutate_input(
         return Ok(());
     }
 
+    // Credit the operators of the last havoc iteration if it found something
+    let found = s.check_new_finds();
+    if !s.havoc_ops.is_empty() {
+        if found {
+            for (name, stat) in s.stat_havoc_finds.iter_mut() {
+                if s.havoc_ops.iter().any(|op| HAVOC_OP_NAMES[*op as usize] == *name) {
+                    *stat.val += 1;
+                }
+            }
+        }
+        if let Some(ref mut mopt) = s.mopt {
+            if mopt.report(&s.havoc_ops, found) {
+                if let Some(ref mut stat) = s.stat_mopt {
+                    s.stage_name.clear();
+                    mopt.desc(&mut s.stage_name);
+                    stat.set(&s.stage_name);
+                }
+            }
+        }
+        s.havoc_ops.clear();
+    }
+
     let stage = &mut s.cur_stage;
     let input = &mut s.cur_input;
     let afl = &mut s.afl;
     let q = unsafe { s.afl_queue.get_unchecked_mut(*s.cur_input_idx) };
+    let mut ctx = StageCtx {
+        mask: &mut s.mask,
+        eff_map: &mut s.eff_map,
+        trace_bits: s.trace_bits.map(|t| t.as_slice()),
+        user_extras: &s.user_extras,
+        auto_extras: &mut s.auto_extras,
+        exec_cksum: q.exec_cksum,
+        inputs: s.inputs,
+        cur_input_idx: *s.cur_input_idx,
+        havoc_ops: &mut s.havoc_ops,
+        mopt: s.mopt.as_mut(),
+        cmplog: s.cmplog_map,
+        cmplog_run: s.cmplog_run.as_deref_mut(),
+    };
 
     // Update stage name if we switched input
     if s.force_update || s.prev_input_idx != *s.cur_input_idx {
+        let idx = *s.cur_input_idx;
+        let regions = match s.regions.get(idx, unsafe { s.inputs.get_unchecked(idx) }) {
+            Ok(r) => r,
+            Err(e) => {
+                core.warn(&format!("Failed to load mutable regions of input {} : {}", idx, e));
+                None
+            }
+        };
+
         // Reset stage
-        stage.sync_to_input(q, afl, input);
+        stage.sync_to_input(q, afl, input, &mut ctx, regions);
         
         // Update stage name
         s.stage_name.clear();
-        stage.update_state(input, Some(&mut s.stage_name), Some(s.stat_total_iterations.val));
+        stage.update_state(input, &ctx, Some(&mut s.stage_name), Some(s.stat_total_iterations.val));
         s.stat_cur_stage.set(&s.stage_name);
         *s.stat_stage_progress.val = 0;
 
@@ -143,7 +287,7 @@ fn mutate_input(
 
     // Mutate the input
     loop {
-        match stage.mutate(input) {
+        match stage.mutate(input, &mut ctx) {
             StageResult::WillRestoreInput => {
                 // The mutator will restore the testcase
                 *s.no_select = true;
@@ -157,7 +301,7 @@ fn mutate_input(
             StageResult::Update => {
                 // Update cur_stage stat
                 s.stage_name.clear();
-                stage.update_state(input, Some(&mut s.stage_name), None);
+                stage.update_state(input, &ctx, Some(&mut s.stage_name), None);
                 s.stat_cur_stage.set(&s.stage_name);
 
                 // Loop again to mutate at least once
@@ -165,10 +309,10 @@ fn mutate_input(
             }
             StageResult::Done => {
                 // Can we progress to the next stage ?
-                if stage.next(q, afl, input) {
+                if stage.next(q, afl, input, &ctx) {
                     // Update cur_stage stat
                     s.stage_name.clear();
-                    stage.update_state(input, Some(&mut s.stage_name), Some(s.stat_total_iterations.val));
+                    stage.update_state(input, &ctx, Some(&mut s.stage_name), Some(s.stat_total_iterations.val));
                     s.stat_cur_stage.set(&s.stage_name);
                     *s.stat_stage_progress.val = 0;
                     
@@ -187,15 +331,123 @@ fn mutate_input(
     }
     *s.stat_stage_progress.val += 1;
 
+    // Persist new auto extras once the bitflip stage is over
+    if s.auto_extras.dirty && !matches!(s.cur_stage, MutatorStage::BitFlip(_)) {
+        if let Err(e) = s.auto_extras.save(&s.auto_extras_dir) {
+            core.warn(&format!("Failed to save auto extras : {}", e));
+        }
+    }
+
     Ok(())
 }
 
 // Unload and free our resources
 fn destroy(
-    _core: &mut dyn PluginInterface,
+    core: &mut dyn PluginInterface,
     _store: &mut CfStore,
     plugin_ctx: *mut u8,
 ) -> Result<()> {
-    let _state = box_take!(plugin_ctx, State);
+    let mut state = box_take!(plugin_ctx, State);
+
+    if state.auto_extras.dirty {
+        if let Err(e) = state.auto_extras.save(&state.auto_extras_dir) {
+            core.warn(&format!("Failed to save auto extras : {}", e));
+        }
+    }
     Ok(())
 }
+
+impl State {
+    /// Whether the last execution of the input we mutated found something
+    /// (new input or crash) according to the input rewards.
+    fn check_new_finds(&mut self) -> bool {
+        let rewards = match self.input_rewards {
+            Some(r) => r,
+            None => return false,
+        };
+        let found = match rewards.get(self.prev_input_idx) {
+            Some(r) => *r > self.last_reward,
+            None => false,
+        };
+        self.last_reward = rewards.get(*self.cur_input_idx).copied().unwrap_or(0);
+        found
+    }
+
+    /// Parse the plugin_conf for our values
+    pub fn load_conf(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        plugin_conf: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.regions = match InputRegions::from_conf(plugin_conf) {
+            Ok(r) => r,
+            Err(e) => {
+                core.error(&format!("Invalid mutate_regions : {}", e));
+                return Err(From::from("Invalid config".to_string()));
+            }
+        };
+
+        let mut dict_level = 0;
+        if let Some(v) = plugin_conf.get("afl_dict_level") {
+            match v.parse::<u32>() {
+                Ok(l) => dict_level = l,
+                Err(_) => {
+                    core.error(&format!("afl_dict_level must be a number : '{}'", v));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        // Dictionaries are separated like the PATH env variable
+        if let Some(v) = plugin_conf.get("afl_dict") {
+            for path in std::env::split_paths(v) {
+                if let Err(e) = load_extras(Path::new(&path), dict_level, &mut self.user_extras)
+                {
+                    core.error(&format!(
+                        "Failed to load dictionary '{}' : {}",
+                        path.to_string_lossy(),
+                        e
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+            core.info(&format!("Loaded {} user extras", self.user_extras.len()));
+        }
+
+        if plugin_conf.get("afl_mopt").is_some() {
+            let mut num_swarms = 5;
+            let mut pilot_period = 50000;
+            let mut core_period = 500000;
+            for (key, dst) in [
+                ("afl_mopt_swarms", &mut num_swarms),
+                ("afl_mopt_pilot_period", &mut pilot_period),
+                ("afl_mopt_core_period", &mut core_period),
+            ] {
+                if let Some(v) = plugin_conf.get(key) {
+                    match v.parse::<u64>() {
+                        Ok(num) if num > 0 => *dst = num,
+                        _ => {
+                            core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                            return Err(From::from("Invalid config".to_string()));
+                        }
+                    }
+                }
+            }
+
+            let mut mopt = MOpt::new(num_swarms as usize);
+            mopt.pilot_period = pilot_period;
+            mopt.core_period = core_period;
+
+            let mut desc = String::new();
+            mopt.desc(&mut desc);
+            self.stat_mopt = Some(core.new_stat_str("mopt_ops", 256, &desc)?);
+            core.info(&format!(
+                "Using MOpt havoc scheduling ({} swarms, pilot {}, core {})",
+                num_swarms, pilot_period, core_period
+            ));
+            self.mopt = Some(mopt);
+        }
+
+        Ok(())
+    }
+}
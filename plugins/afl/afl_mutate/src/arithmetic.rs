@@ -44,7 +44,7 @@ impl ArithState {
     }
 
     /// Increment/decrement values
-    pub fn mutate(&mut self, mut input: &mut [u8]) -> StageResult {
+    pub fn mutate(&mut self, mut input: &mut [u8], ctx: &mut StageCtx) -> StageResult {
         // Restore the orig input
         if let Some((idx, orig_val)) = self.prev_val.take() {
             unsafe {
@@ -90,6 +90,12 @@ impl ArithState {
 
             self.idx -= 1;
 
+            // Skip bytes we are not allowed to touch or that have no effect
+            let width = self.width.unsigned_abs() as usize;
+            if !ctx.mask.can_overwrite(self.idx, width) || !ctx.eff_map.has_effect(self.idx, width) {
+                continue;
+            }
+
             unsafe {
                 match self.width {
                     1 => {
@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::Path;
+
+use ::rand::Rng;
+
+use crate::*;
+
+/// Tokens found automatically while flipping bits
+#[derive(Debug, Default)]
+pub struct AutoExtras {
+    /// Every token found so far along with its hit count
+    found: Vec<(Vec<u8>, u32)>,
+    /// The most used tokens, sorted by size, that the mutators pick from
+    pub active: Vec<Vec<u8>>,
+    /// Whether new tokens were found since the last save
+    pub dirty: bool,
+}
+
+impl AutoExtras {
+    /// Adds a candidate token unless it is redundant
+    pub fn maybe_add(&mut self, token: &[u8], user_extras: &[Vec<u8>]) {
+        // Skip runs of identical bytes
+        if token.iter().all(|b| *b == token[0]) {
+            return;
+        }
+
+        // Skip values that the interesting stage already covers
+        if token.len() == 2 {
+            let val = u16::from_ne_bytes([token[0], token[1]]);
+            if INTERESTING_16
+                .iter()
+                .any(|i| *i == val || swap_16(*i) == val)
+            {
+                return;
+            }
+        } else if token.len() == 4 {
+            let val = u32::from_ne_bytes([token[0], token[1], token[2], token[3]]);
+            if INTERESTING_32
+                .iter()
+                .any(|i| *i == val || swap_32(*i) == val)
+            {
+                return;
+            }
+        }
+
+        // Skip tokens the user already gave us
+        if user_extras
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(token))
+        {
+            return;
+        }
+
+        match self
+            .found
+            .iter_mut()
+            .find(|(e, _)| e.eq_ignore_ascii_case(token))
+        {
+            Some((_, hit_cnt)) => *hit_cnt += 1,
+            None => {
+                if self.found.len() < MAX_AUTO_EXTRAS {
+                    self.found.push((token.to_vec(), 0));
+                } else {
+                    // Replace a random token from the least used half
+                    let idx = MAX_AUTO_EXTRAS / 2
+                        + ::rand::thread_rng().gen_range(0, MAX_AUTO_EXTRAS / 2);
+                    self.found[idx] = (token.to_vec(), 0);
+                }
+                self.dirty = true;
+            }
+        }
+
+        // Keep the most used tokens active
+        self.found.sort_by_key(|e| std::cmp::Reverse(e.1));
+        self.active.clear();
+        self.active.extend(
+            self.found
+                .iter()
+                .take(USE_AUTO_EXTRAS)
+                .map(|(e, _)| e.clone()),
+        );
+        self.active.sort_by_key(|e| e.len());
+    }
+
+    /// Loads tokens that were saved by a previous run
+    pub fn load(&mut self, dir: &Path, user_extras: &[Vec<u8>]) -> Result<usize> {
+        if !dir.is_dir() {
+            return Ok(0);
+        }
+
+        let mut tokens = Vec::new();
+        load_extras(dir, 0, &mut tokens)?;
+        for token in tokens.iter() {
+            if token.len() >= MIN_AUTO_EXTRA && token.len() <= MAX_AUTO_EXTRA {
+                self.maybe_add(token, user_extras);
+            }
+        }
+        self.dirty = false;
+
+        Ok(self.active.len())
+    }
+
+    /// Saves the active tokens to dir
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        if !dir.is_dir() {
+            fs::create_dir_all(dir)?;
+        }
+        for (i, token) in self.active.iter().enumerate() {
+            fs::write(dir.join(format!("auto_{:06}", i)), token)?;
+        }
+
+        // Tokens saved by a previous run would be loaded back on restart
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let idx = match name.to_str().and_then(|n| n.strip_prefix("auto_")) {
+                Some(num) => num.parse::<usize>().ok(),
+                None => continue,
+            };
+            if !matches!(idx, Some(i) if i < self.active.len()) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        self.dirty = false;
+
+        Ok(())
+    }
+}
@@ -17,11 +17,17 @@ fn max_idx(width: u8, input_len: usize) -> usize {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct BitFlipState {
     idx: usize,
     restore_val: bool,
     width: u8,
+    /// Bytes of the auto extra being collected (in reverse order)
+    collect: Vec<u8>,
+    /// Path checksum of the last byte observed
+    prev_cksum: u32,
+    /// Index of the last byte observed
+    last_byte: usize,
 }
 impl BitFlipState {
     pub fn new(input: &[u8]) -> Self {
@@ -29,6 +35,9 @@ impl BitFlipState {
             idx: max_idx(1, input.len()),
             restore_val: false,
             width: 1,
+            collect: Vec::with_capacity(MAX_AUTO_EXTRA + 1),
+            prev_cksum: 0,
+            last_byte: input.len(),
         }
     }
     pub fn desc(&self, dst: &mut String) {
@@ -56,7 +65,7 @@ impl BitFlipState {
     }
 
     /// Flips bits in the input starting from the end
-    pub fn mutate(&mut self, mut input: &mut [u8]) -> StageResult {
+    pub fn mutate(&mut self, mut input: &mut [u8], ctx: &mut StageCtx) -> StageResult {
         // Restore the orig input
         if self.restore_val {
             self.restore_val = false;
@@ -64,6 +73,9 @@ impl BitFlipState {
                 match self.width {
                     1 => {
                         input.flip_bit(self.idx);
+                        if self.idx & 7 == 7 {
+                            self.collect_auto(input, ctx);
+                        }
                     }
                     2 => {
                         input.flip_bit(self.idx);
@@ -77,6 +89,7 @@ impl BitFlipState {
                     }
                     8 => {
                         input.flip_byte(self.idx);
+                        self.update_eff_map(input, ctx);
                     }
                     16 => {
                         input.flip_word(self.idx);
@@ -88,17 +101,40 @@ impl BitFlipState {
             }
         }
 
-        if self.idx == 0 {
-            if self.width >= 32 {
-                return StageResult::Done;
-            }
-            
-            self.width *= 2;
-            self.idx = max_idx(self.width, input.len());
-            return StageResult::Update;
-        };
+        loop {
+            if self.idx == 0 {
+                if self.width == 1 {
+                    self.flush_auto(ctx);
+                } else if self.width == 8 {
+                    ctx.eff_map.finish();
+                }
+                if self.width >= 32 {
+                    return StageResult::Done;
+                }
+
+                self.width *= 2;
+                self.idx = max_idx(self.width, input.len());
+                if self.width == 8 {
+                    ctx.eff_map.reset(input.len());
+                }
+                return StageResult::Update;
+            };
+
+            self.idx -= 1;
 
-        self.idx -= 1;
+            // Skip bytes we are not allowed to touch or that have no effect
+            let (byte_idx, byte_len) = if self.width < 8 {
+                let first = self.idx >> 3;
+                (first, ((self.idx + self.width as usize - 1) >> 3) - first + 1)
+            } else {
+                (self.idx, (self.width / 8) as usize)
+            };
+            if ctx.mask.can_overwrite(byte_idx, byte_len)
+                && ctx.eff_map.has_effect(byte_idx, byte_len)
+            {
+                break;
+            }
+        }
         let num_bits = self.width as u8;
         self.restore_val = true;
         unsafe {
@@ -124,6 +160,61 @@ impl BitFlipState {
 
         StageResult::WillRestoreInput
     }
+
+    /// Marks the byte we just flipped if it changed the path
+    fn update_eff_map(&mut self, input: &[u8], ctx: &mut StageCtx) {
+        if ctx.eff_map.is_set(self.idx) {
+            return;
+        }
+
+        // Small inputs are not worth the hashing, mark everything
+        let cksum = match ctx.trace_bits {
+            Some(t) if ctx.exec_cksum != 0 && input.len() >= EFF_MIN_LEN => {
+                hash32(t, HASH_CONST)
+            }
+            _ => !ctx.exec_cksum,
+        };
+        if cksum != ctx.exec_cksum {
+            ctx.eff_map.set(self.idx);
+        }
+    }
+
+    /// Collects runs of bytes that change the path the same way when
+    /// flipped. These are likely to be tokens the target checks for.
+    fn collect_auto(&mut self, input: &[u8], ctx: &mut StageCtx) {
+        let trace_bits = match ctx.trace_bits {
+            Some(t) if ctx.exec_cksum != 0 => t,
+            _ => return,
+        };
+        let byte_idx = self.idx >> 3;
+        let cksum = hash32(trace_bits, HASH_CONST);
+
+        // Bytes skipped because of the mask end the current token
+        if byte_idx + 1 != self.last_byte {
+            self.flush_auto(ctx);
+        }
+        self.last_byte = byte_idx;
+
+        if cksum != self.prev_cksum {
+            self.flush_auto(ctx);
+            self.prev_cksum = cksum;
+        }
+
+        if cksum != ctx.exec_cksum && self.collect.len() <= MAX_AUTO_EXTRA {
+            self.collect.push(input[byte_idx]);
+        }
+    }
+
+    /// Adds the collected bytes as an auto extra if they look like a token
+    fn flush_auto(&mut self, ctx: &mut StageCtx) {
+        if self.collect.len() >= MIN_AUTO_EXTRA && self.collect.len() <= MAX_AUTO_EXTRA {
+            // Bytes were collected from the end of the input
+            self.collect.reverse();
+            ctx.auto_extras.maybe_add(&self.collect, ctx.user_extras);
+        }
+        self.collect.clear();
+        self.prev_cksum = ctx.exec_cksum;
+    }
 }
 
 /** Helper function to see if a particular change (xor_val = old ^ new) could
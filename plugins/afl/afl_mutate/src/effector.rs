@@ -0,0 +1,75 @@
+use crate::*;
+
+#[inline]
+fn eff_apos(idx: usize) -> usize {
+    idx >> EFF_MAP_SCALE2
+}
+
+/// Tracks which blocks of the input change the path when flipped so the
+/// deterministic stages can skip the others (AFL's effector map)
+#[derive(Debug, Default)]
+pub struct EffectorMap {
+    /// One entry per block of 1 << EFF_MAP_SCALE2 bytes
+    map: Vec<bool>,
+    /// Whether the 8/8 bitflip stage completed the map
+    built: bool,
+}
+
+impl EffectorMap {
+    /// Forgets the map, every byte has an effect
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.built = false;
+    }
+
+    /// Starts a new map for an input of len bytes
+    pub fn reset(&mut self, len: usize) {
+        self.map.clear();
+        self.map.resize(eff_apos(len.saturating_sub(1)) + 1, false);
+        self.built = false;
+
+        // The first and last blocks are always worth fuzzing
+        self.map[0] = true;
+        *self.map.last_mut().unwrap() = true;
+    }
+
+    /// Whether the block holding idx is already marked
+    pub fn is_set(&self, idx: usize) -> bool {
+        match self.map.get(eff_apos(idx)) {
+            Some(v) => *v,
+            None => true,
+        }
+    }
+
+    /// Marks the block holding idx as having an effect
+    pub fn set(&mut self, idx: usize) {
+        if let Some(v) = self.map.get_mut(eff_apos(idx)) {
+            *v = true;
+        }
+    }
+
+    /// Completes the map once every byte was flipped
+    pub fn finish(&mut self) {
+        if self.map.is_empty() {
+            return;
+        }
+
+        // Not worth skipping anything if most blocks have an effect
+        let eff_cnt = self.map.iter().filter(|v| **v).count();
+        if eff_cnt != self.map.len() && eff_cnt * 100 / self.map.len() > EFF_MAX_PERC {
+            self.map.iter_mut().for_each(|v| *v = true);
+        }
+        self.built = true;
+    }
+
+    /// Whether any of the bytes [idx, idx + len[ are worth mutating
+    pub fn has_effect(&self, idx: usize, len: usize) -> bool {
+        if !self.built || len == 0 {
+            return true;
+        }
+        (eff_apos(idx)..=eff_apos(idx + len - 1)).any(|i| match self.map.get(i) {
+            Some(v) => *v,
+            None => true,
+        })
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::Path;
+
+use ::rand::rngs::SmallRng;
+use ::rand::{Rng, SeedableRng};
+
+use crate::*;
+
+/// Loads AFL/libFuzzer style dictionaries into dst. The path can either be
+/// a dictionary file or a directory where every file is a single token.
+/// Tokens with a level above max_level are skipped.
+pub fn load_extras(path: &Path, max_level: u32, dst: &mut Vec<Vec<u8>>) -> Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let fpath = entry?.path();
+            if fpath.is_dir() {
+                continue;
+            }
+            let token = fs::read(&fpath)?;
+            if token.is_empty() {
+                continue;
+            }
+            if token.len() > MAX_DICT_FILE {
+                return Err(From::from(format!(
+                    "Extra '{}' is too big ({} > {})",
+                    fpath.to_string_lossy(),
+                    token.len(),
+                    MAX_DICT_FILE
+                )));
+            }
+            dst.push(token);
+        }
+    } else {
+        for token in load_dict(path, max_level)? {
+            if token.len() > MAX_DICT_FILE {
+                return Err(From::from(format!(
+                    "Keyword in '{}' is too big ({} > {})",
+                    path.to_string_lossy(),
+                    token.len(),
+                    MAX_DICT_FILE
+                )));
+            }
+            dst.push(token);
+        }
+    }
+
+    // Keep them sorted by size like AFL
+    dst.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+    dst.dedup();
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct ExtrasState {
+    /// Use the auto extras instead of the user's
+    auto: bool,
+    /// Insert the extras instead of overwriting
+    insert: bool,
+    idx: usize,
+    extra_idx: usize,
+    /// Position and length of the last change to revert
+    prev: Option<(usize, usize)>,
+    /// Bytes that were overwritten by the last change
+    orig_bytes: Vec<u8>,
+    /// fast/non-crypto grade random
+    rng: SmallRng,
+}
+
+impl ExtrasState {
+    pub fn new(auto: bool) -> Self {
+        Self {
+            auto,
+            insert: false,
+            idx: 0,
+            extra_idx: 0,
+            prev: None,
+            orig_bytes: Vec::with_capacity(MAX_DICT_FILE),
+            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+        }
+    }
+
+    pub fn desc(&self, dst: &mut String) {
+        dst.push_str(if self.auto {
+            "auto extras (over)"
+        } else if self.insert {
+            "user extras (insert)"
+        } else {
+            "user extras (over)"
+        });
+    }
+
+    /// Extras used by this stage
+    fn extras<'a>(&self, ctx: &'a StageCtx) -> &'a [Vec<u8>] {
+        if self.auto {
+            &ctx.auto_extras.active
+        } else {
+            ctx.user_extras
+        }
+    }
+
+    pub fn total_cycles(&self, input: &[u8], ctx: &StageCtx) -> usize {
+        let extras = self.extras(ctx);
+        let num_extras = std::cmp::min(extras.len(), MAX_DET_EXTRAS);
+        if self.insert {
+            (input.len() + 1) * extras.len()
+        } else {
+            input.len() * num_extras
+        }
+    }
+
+    pub fn mutate(&mut self, input: &mut CfInput, ctx: &mut StageCtx) -> StageResult {
+        let extras = self.extras(ctx);
+
+        // Restore the orig input
+        if let Some((idx, len)) = self.prev.take() {
+            if self.insert {
+                input.drain(idx..idx + len);
+            } else {
+                input[idx..idx + len].copy_from_slice(&self.orig_bytes);
+            }
+        }
+
+        loop {
+            if self.extra_idx >= extras.len() {
+                self.extra_idx = 0;
+                self.idx += 1;
+            }
+
+            if extras.is_empty()
+                || (!self.insert && self.idx >= input.len())
+                || self.idx > input.len()
+            {
+                // Auto extras are only used to overwrite
+                if self.insert || self.auto || extras.is_empty() {
+                    return StageResult::Done;
+                }
+                // Move on to inserting the extras
+                self.insert = true;
+                self.idx = 0;
+                self.extra_idx = 0;
+                return StageResult::Update;
+            }
+
+            let extra = unsafe { extras.get_unchecked(self.extra_idx) };
+            self.extra_idx += 1;
+
+            if self.insert {
+                if input.len() + extra.len() > MAX_FILE as usize
+                    || !ctx.mask.can_insert(self.idx)
+                {
+                    continue;
+                }
+                input.splice(self.idx..self.idx, extra.iter().copied());
+            } else {
+                /* Skip extras probabilistically if extras_cnt > MAX_DET_EXTRAS. Also
+                skip them if there's no room to insert the payload, if the token
+                is redundant, or if its entire span has no bytes set in the mask. */
+                if (extras.len() > MAX_DET_EXTRAS
+                    && self.rng.gen_range(0, extras.len()) >= MAX_DET_EXTRAS)
+                    || extra.len() > input.len() - self.idx
+                    || &input[self.idx..self.idx + extra.len()] == extra.as_slice()
+                    || !ctx.mask.can_overwrite(self.idx, extra.len())
+                    || !ctx.eff_map.has_effect(self.idx, extra.len())
+                {
+                    continue;
+                }
+                self.orig_bytes.clear();
+                self.orig_bytes
+                    .extend_from_slice(&input[self.idx..self.idx + extra.len()]);
+                input[self.idx..self.idx + extra.len()].copy_from_slice(extra);
+            }
+
+            self.prev = Some((self.idx, extra.len()));
+            return StageResult::WillRestoreInput;
+        }
+    }
+}
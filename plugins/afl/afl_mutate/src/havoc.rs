@@ -5,23 +5,57 @@ use ::rand::{Rng, SeedableRng};
 
 use crate::*;
 
+/// Number of random positions tried before giving up on a masked operation
+const MASK_RETRIES: usize = 8;
+
+/// Number of havoc operators (including the dictionary ones)
+pub const NUM_HAVOC_OPS: usize = 17;
+/// Name of every havoc operator as shown in the stats
+pub const HAVOC_OP_NAMES: [&str; NUM_HAVOC_OPS] = [
+    "flip_bit",
+    "interesting_8",
+    "interesting_16",
+    "interesting_32",
+    "sub_8",
+    "add_8",
+    "sub_16",
+    "add_16",
+    "sub_32",
+    "add_32",
+    "rand_byte",
+    "delete",
+    "delete",
+    "insert_chunk",
+    "overwrite_chunk",
+    "overwrite_extra",
+    "insert_extra",
+];
+
 #[derive(Debug)]
 pub struct HavocState {
     num_iterations: usize,
+    /// Score of the input the iterations were computed from
+    perf_score: u32,
     /// fast/non-crypto grade random
     rng: SmallRng,
 }
 impl HavocState {
     pub fn new(q: &mut AflQueueEntry, afl: &AflGlobals) -> Self {
         // Calculate perf_score from entry.exec_us and bitmap_size
-        let mut r = Self {
-            num_iterations: 0,
-            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
-        };
+        let mut r = Self::with_iterations(0);
         r.reset(q, afl);
         r
     }
 
+    /// Havoc that runs for a fixed number of iterations
+    pub fn with_iterations(num_iterations: usize) -> Self {
+        Self {
+            num_iterations,
+            perf_score: 0,
+            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+        }
+    }
+
     pub fn desc(&self, dst: &mut String) {
         dst.push_str("havoc");
     }
@@ -30,9 +64,18 @@ impl HavocState {
         self.num_iterations
     }
 
+    pub fn perf_score(&self) -> u32 {
+        self.perf_score
+    }
+
+    pub fn set_iterations(&mut self, num_iterations: usize) {
+        self.num_iterations = num_iterations;
+    }
+
     pub fn reset(&mut self, q: &mut AflQueueEntry, afl: &AflGlobals) {
         // Update weight for cur input
         let perf_score = calculate_score(q, afl);
+        self.perf_score = perf_score;
 
         // Recalculate number of iterations based on weight
         self.num_iterations = if afl.skip_deterministic {
@@ -49,113 +92,162 @@ impl HavocState {
         }
     }
 
-    pub fn mutate(&mut self, input: &mut CfInput) -> StageResult {
-        self.num_iterations -= 1;
-        if self.num_iterations == 0 {
+    /// Picks between the user and auto extras (at least one must not be empty)
+    fn pick_extras<'a>(&mut self, user: &'a [Vec<u8>], auto: &'a [Vec<u8>]) -> &'a [Vec<u8>] {
+        if auto.is_empty() || (!user.is_empty() && self.rng.gen()) {
+            user
+        } else {
+            auto
+        }
+    }
+
+    /// Picks a random index in [0, max[ that satisfies is_allowed
+    fn rand_idx<F: Fn(usize) -> bool>(&mut self, max: usize, is_allowed: F) -> Option<usize> {
+        for _ in 0..MASK_RETRIES {
+            let idx = self.rng.gen_range(0, max);
+            if is_allowed(idx) {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    pub fn mutate(&mut self, input: &mut CfInput, ctx: &mut StageCtx) -> StageResult {
+        if self.num_iterations <= 1 {
+            self.num_iterations = 0;
             return StageResult::Done;
         }
+        self.num_iterations -= 1;
+
+        // The input was restored to its original contents
+        ctx.mask.restore();
+        let user_extras = ctx.user_extras;
+        let auto_extras = ctx.auto_extras.active.as_slice();
+        let mask = &mut *ctx.mask;
+        let mut mopt = ctx.mopt.as_deref_mut();
+        let applied_ops = &mut *ctx.havoc_ops;
+        // Dictionary operations are only picked when we have extras
+        let num_ops = if user_extras.is_empty() && auto_extras.is_empty() { 15 } else { NUM_HAVOC_OPS };
 
         let mut num_stacks = 1 << self.rng.gen_range(1, HAVOC_STACK_POW2);
         loop {
             unsafe {
-                match self.rng.gen_range(0, 15) {
+                let op = match mopt {
+                    Some(ref mut m) => m.select_op(num_ops),
+                    None => self.rng.gen_range(0, num_ops),
+                };
+                match op {
                     // Flip a single bit somewhere
                     0 => {
-                        input.flip_bit(self.rng.gen_range(0, input.len() << 3));
+                        if let Some(idx) = self.rand_idx(input.len(), |i| mask.can_overwrite(i, 1)) {
+                            applied_ops.push(op as u8);
+                            input.flip_bit((idx << 3) + self.rng.gen_range(0, 8));
+                        }
                     }
                     // Set byte to interesting value
                     1 => {
-                        input.set_byte(
-                            self.rng.gen_range(0, input.len()),
-                            *INTERESTING_8
-                                .get_unchecked(self.rng.gen_range(0, INTERESTING_8.len())),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len(), |i| mask.can_overwrite(i, 1)) {
+                            applied_ops.push(op as u8);
+                            input.set_byte(
+                                idx,
+                                *INTERESTING_8
+                                    .get_unchecked(self.rng.gen_range(0, INTERESTING_8.len())),
+                            );
+                        }
                     }
                     // Set word to interesting value
                     2 => {
                         if input.len() < 2 {
                             continue;
                         }
-                        input.set_word(
-                            self.rng.gen_range(0, input.len() - 1),
-                            *INTERESTING_16
-                                .get_unchecked(self.rng.gen_range(0, INTERESTING_16.len())),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len() - 1, |i| mask.can_overwrite(i, 2)) {
+                            applied_ops.push(op as u8);
+                            input.set_word(
+                                idx,
+                                *INTERESTING_16
+                                    .get_unchecked(self.rng.gen_range(0, INTERESTING_16.len())),
+                            );
+                        }
                     }
                     // Set dword to intersting value
                     3 => {
                         if input.len() < 4 {
                             continue;
                         }
-                        input.set_dword(
-                            self.rng.gen_range(0, input.len() - 3),
-                            *INTERESTING_32
-                                .get_unchecked(self.rng.gen_range(0, INTERESTING_32.len())),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len() - 3, |i| mask.can_overwrite(i, 4)) {
+                            applied_ops.push(op as u8);
+                            input.set_dword(
+                                idx,
+                                *INTERESTING_32
+                                    .get_unchecked(self.rng.gen_range(0, INTERESTING_32.len())),
+                            );
+                        }
                     }
                     // Randomly subtract from byte.
                     4 => {
-                        input.sub_byte(
-                            self.rng.gen_range(0, input.len()),
-                            self.rng.gen_range(1, (ARITH_MAX + 1) as u8),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len(), |i| mask.can_overwrite(i, 1)) {
+                            applied_ops.push(op as u8);
+                            input.sub_byte(idx, self.rng.gen_range(1, (ARITH_MAX + 1) as u8));
+                        }
                     }
                     // Randomly add to byte
                     5 => {
-                        input.add_byte(
-                            self.rng.gen_range(0, input.len()),
-                            self.rng.gen_range(1, (ARITH_MAX + 1) as u8),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len(), |i| mask.can_overwrite(i, 1)) {
+                            applied_ops.push(op as u8);
+                            input.add_byte(idx, self.rng.gen_range(1, (ARITH_MAX + 1) as u8));
+                        }
                     }
                     // Randomly subtract from word
                     6 => {
                         if input.len() < 2 {
                             continue;
                         }
-                        input.sub_word(
-                            self.rng.gen_range(0, input.len() - 1),
-                            self.rng.gen_range(1, (ARITH_MAX + 1) as u16),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len() - 1, |i| mask.can_overwrite(i, 2)) {
+                            applied_ops.push(op as u8);
+                            input.sub_word(idx, self.rng.gen_range(1, (ARITH_MAX + 1) as u16));
+                        }
                     }
                     // Randomly add to word
                     7 => {
                         if input.len() < 2 {
                             continue;
                         }
-                        input.add_word(
-                            self.rng.gen_range(0, input.len() - 1),
-                            self.rng.gen_range(1, (ARITH_MAX + 1) as u16),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len() - 1, |i| mask.can_overwrite(i, 2)) {
+                            applied_ops.push(op as u8);
+                            input.add_word(idx, self.rng.gen_range(1, (ARITH_MAX + 1) as u16));
+                        }
                     }
                     // Randomly subtract from dword
                     8 => {
                         if input.len() < 4 {
                             continue;
                         }
-                        input.sub_dword(
-                            self.rng.gen_range(0, input.len() - 3),
-                            self.rng.gen_range(1, (ARITH_MAX + 1) as u32),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len() - 3, |i| mask.can_overwrite(i, 4)) {
+                            applied_ops.push(op as u8);
+                            input.sub_dword(idx, self.rng.gen_range(1, (ARITH_MAX + 1) as u32));
+                        }
                     }
                     // Randomly add to dword
                     9 => {
                         if input.len() < 4 {
                             continue;
                         }
-                        input.add_dword(
-                            self.rng.gen_range(0, input.len() - 3),
-                            self.rng.gen_range(1, (ARITH_MAX + 1) as u32),
-                        );
+                        if let Some(idx) = self.rand_idx(input.len() - 3, |i| mask.can_overwrite(i, 4)) {
+                            applied_ops.push(op as u8);
+                            input.add_dword(idx, self.rng.gen_range(1, (ARITH_MAX + 1) as u32));
+                        }
                     }
                     // Set a random byte to a random value
                     10 => {
-                        let rand_idx = self.rng.gen_range(0, input.len());
-                        let mut rand_val = self.rng.gen_range(0, 256) as u8;
-                        // Make sure its different
-                        while *input.get_unchecked(rand_idx) == rand_val {
-                            rand_val = self.rng.gen_range(0, 256) as u8;
+                        if let Some(rand_idx) = self.rand_idx(input.len(), |i| mask.can_overwrite(i, 1)) {
+                            applied_ops.push(op as u8);
+                            let mut rand_val = self.rng.gen_range(0, 256) as u8;
+                            // Make sure its different
+                            while *input.get_unchecked(rand_idx) == rand_val {
+                                rand_val = self.rng.gen_range(0, 256) as u8;
+                            }
+                            input.set_byte(rand_idx, rand_val);
                         }
-                        input.set_byte(rand_idx, rand_val);
                     }
                     // Delete bytes. We're making this a bit more likely than insertion (the next option) in hopes of keeping files reasonably small
                     11 | 12 => {
@@ -164,11 +256,13 @@ impl HavocState {
                         }
 
                         let del_len = choose_block_len(input.len() - 1, &mut self.rng);
-                        let del_from = self.rng.gen_range(0, input.len() - del_len + 1);
-
-                        let out_buf = input.as_mut_ptr();
-                        copy_nonoverlapping(out_buf.add(del_from + del_len), out_buf.add(del_from), input.len() - del_len - del_from);
-                        input.set_len(input.len() - del_len);
+                        if let Some(del_from) = self.rand_idx(input.len() - del_len + 1, |i| mask.can_delete(i, del_len)) {
+                            applied_ops.push(op as u8);
+                            let out_buf = input.as_mut_ptr();
+                            copy_nonoverlapping(out_buf.add(del_from + del_len), out_buf.add(del_from), input.len() - del_len - del_from);
+                            input.set_len(input.len() - del_len);
+                            mask.on_delete(del_from, del_len);
+                        }
                     }
                     // Insert a cloned chunk (75%) or a constant value (25%)
                     13 => {
@@ -177,35 +271,38 @@ impl HavocState {
                             continue;
                         }
                         
-                        let insert_idx = self.rng.gen_range(0, input.len());
-                        let clone_len;
-                        let mut clone_from = None;
+                        if let Some(insert_idx) = self.rand_idx(input.len(), |i| mask.can_insert(i)) {
+                            applied_ops.push(op as u8);
+                            let clone_len;
+                            let mut clone_from = None;
                         
-                        /* Clone bytes (75%) or insert a block of constant bytes (25%). */
-                        if self.rng.gen_range(0, 4) != 0 {
-                            clone_len = choose_block_len(input.len(), &mut self.rng);
-                            clone_from = Some(self.rng.gen_range(0, input.len() - clone_len + 1));
-                        } else {
-                            clone_len = choose_block_len(HAVOC_BLK_XL as usize, &mut self.rng);
-                        }
+                            /* Clone bytes (75%) or insert a block of constant bytes (25%). */
+                            if self.rng.gen_range(0, 4) != 0 {
+                                clone_len = choose_block_len(input.len(), &mut self.rng);
+                                clone_from = Some(self.rng.gen_range(0, input.len() - clone_len + 1));
+                            } else {
+                                clone_len = choose_block_len(HAVOC_BLK_XL as usize, &mut self.rng);
+                            }
                         
-                        input.reserve(clone_len);
-
-                        // Shift the tail before overwriting some bytes
-                        copy(input.as_ptr().add(insert_idx), input.as_mut_ptr().add(insert_idx + clone_len), input.len() - insert_idx);
-
-                        if let Some(clone_start) = clone_from {
-                            // Copy the cloned chunk
-                            copy(input.as_ptr().add(clone_start), input.as_mut_ptr().add(insert_idx), clone_len);
-                        } else {
-                            // Copy a random value
-                            let val = self.rng.gen_range(0, 256) as u8;
-                            for i in insert_idx..insert_idx+clone_len {
-                                *input.get_unchecked_mut(i) = val;
+                            input.reserve(clone_len);
+
+                            // Shift the tail before overwriting some bytes
+                            copy(input.as_ptr().add(insert_idx), input.as_mut_ptr().add(insert_idx + clone_len), input.len() - insert_idx);
+
+                            if let Some(clone_start) = clone_from {
+                                // Copy the cloned chunk
+                                copy(input.as_ptr().add(clone_start), input.as_mut_ptr().add(insert_idx), clone_len);
+                            } else {
+                                // Copy a random value
+                                let val = self.rng.gen_range(0, 256) as u8;
+                                for i in insert_idx..insert_idx+clone_len {
+                                    *input.get_unchecked_mut(i) = val;
+                                }
                             }
-                        }
 
-                        input.set_len(input.len() + clone_len);
+                            input.set_len(input.len() + clone_len);
+                            mask.on_insert(insert_idx, clone_len);
+                        }
                     }
                     // Overwrite bytes with a random chunk (75%) or a constant value (25%)
                     14 => {
@@ -214,18 +311,44 @@ impl HavocState {
                         }
 
                         let copy_len = choose_block_len(input.len() - 1, &mut self.rng);
-                        let copy_to = self.rng.gen_range(0, input.len() - copy_len + 1);
-
-                        if self.rng.gen_range(0, 4) != 0 {
-                            let copy_from = self.rng.gen_range(0, input.len() - copy_len + 1);
-                            copy(input.as_ptr().add(copy_from), input.as_mut_ptr().add(copy_to), copy_len);
-                        } else {
-                            let val = self.rng.gen_range(0, 256) as u8;
-                            for i in copy_to..copy_to+copy_len {
-                                *input.get_unchecked_mut(i) = val;
+                        if let Some(copy_to) = self.rand_idx(input.len() - copy_len + 1, |i| mask.can_overwrite(i, copy_len)) {
+                            applied_ops.push(op as u8);
+                            if self.rng.gen_range(0, 4) != 0 {
+                                let copy_from = self.rng.gen_range(0, input.len() - copy_len + 1);
+                                copy(input.as_ptr().add(copy_from), input.as_mut_ptr().add(copy_to), copy_len);
+                            } else {
+                                let val = self.rng.gen_range(0, 256) as u8;
+                                for i in copy_to..copy_to+copy_len {
+                                    *input.get_unchecked_mut(i) = val;
+                                }
                             }
                         }
                     }
+                    // Overwrite bytes with an extra
+                    15 => {
+                        let extras = self.pick_extras(user_extras, auto_extras);
+                        let extra = &extras[self.rng.gen_range(0, extras.len())];
+                        if extra.len() > input.len() {
+                            continue;
+                        }
+                        if let Some(insert_at) = self.rand_idx(input.len() - extra.len() + 1, |i| mask.can_overwrite(i, extra.len())) {
+                            applied_ops.push(op as u8);
+                            input[insert_at..insert_at + extra.len()].copy_from_slice(extra);
+                        }
+                    }
+                    // Insert an extra
+                    16 => {
+                        let extras = self.pick_extras(user_extras, auto_extras);
+                        let extra = &extras[self.rng.gen_range(0, extras.len())];
+                        if input.len() + extra.len() >= MAX_FILE as usize {
+                            continue;
+                        }
+                        if let Some(insert_at) = self.rand_idx(input.len() + 1, |i| mask.can_insert(i)) {
+                            applied_ops.push(op as u8);
+                            input.splice(insert_at..insert_at, extra.iter().copied());
+                            mask.on_insert(insert_at, extra.len());
+                        }
+                    }
                     _ => unreachable!(),
                 }
             }
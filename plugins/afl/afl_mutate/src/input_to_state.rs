@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use crate::*;
+
+/// Replaces values the target compares against with the other operand (RedQueen)
+#[derive(Debug, Default)]
+pub struct InputToStateState {
+    /// Whether the cmplog target has been run on the original input
+    captured: bool,
+    /// Whether the comparison operands have been parsed
+    collected: bool,
+    /// Bytes found in the input and what to replace them with
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    pair_idx: usize,
+    /// Offset to search the current pattern from
+    pos: usize,
+    /// Offset and original contents of the last substitution
+    prev_val: Option<(usize, Vec<u8>)>,
+}
+
+impl InputToStateState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn desc(&self, dst: &mut String) {
+        dst.push_str(if self.collected {
+            "input-to-state"
+        } else {
+            "cmplog capture"
+        });
+    }
+
+    pub fn total_cycles(&self) -> usize {
+        if self.collected {
+            self.pairs.len()
+        } else {
+            1
+        }
+    }
+
+    pub fn mutate(&mut self, input: &mut CfInput, ctx: &mut StageCtx) -> StageResult {
+        // Have the cmplog target log the comparisons of the original input
+        if !self.captured {
+            match ctx.cmplog_run.as_mut() {
+                Some(r) => **r = true,
+                None => return StageResult::Done,
+            };
+            self.captured = true;
+            return StageResult::WillRestoreInput;
+        }
+
+        if !self.collected {
+            if let Some(map) = ctx.cmplog {
+                self.collect(map, input);
+            }
+            self.collected = true;
+            return StageResult::Update;
+        }
+
+        // Restore the orig input
+        if let Some((idx, orig)) = self.prev_val.take() {
+            input[idx..idx + orig.len()].copy_from_slice(&orig);
+        }
+
+        while let Some((pattern, repl)) = self.pairs.get(self.pair_idx) {
+            let idx = match find(&input[self.pos..], pattern) {
+                Some(i) => self.pos + i,
+                None => {
+                    self.pair_idx += 1;
+                    self.pos = 0;
+                    continue;
+                }
+            };
+            self.pos = idx + 1;
+
+            let len = std::cmp::min(repl.len(), input.len() - idx);
+            if !ctx.mask.can_overwrite(idx, len) {
+                continue;
+            }
+            self.prev_val = Some((idx, input[idx..idx + len].to_vec()));
+            input[idx..idx + len].copy_from_slice(&repl[..len]);
+            return StageResult::WillRestoreInput;
+        }
+
+        StageResult::Done
+    }
+
+    /// Builds the list of substitutions from the operands logged by the target
+    fn collect(&mut self, map: &CmpMap, input: &[u8]) {
+        let mut seen = HashSet::new();
+        for (idx, header) in map.headers.iter().enumerate() {
+            let hits = header.hits();
+            if hits == 0 {
+                continue;
+            }
+
+            if header.kind() == CMP_TYPE_INS {
+                let size = header.size();
+                if size > 8 {
+                    continue;
+                }
+                for op in map.log[idx].iter().take(std::cmp::min(hits, CMP_MAP_H)) {
+                    for (pattern, repl) in [(op.v0, op.v1), (op.v1, op.v0)] {
+                        self.add_int(pattern, repl, size, input, &mut seen);
+                    }
+                }
+            } else {
+                for op in map.fn_log(idx).iter().take(std::cmp::min(hits, CMP_MAP_RTN_H)) {
+                    self.add(op.v0(), op.v1(), input, &mut seen);
+                    self.add(op.v1(), op.v0(), input, &mut seen);
+                }
+            }
+        }
+    }
+
+    /// Adds integer operands as little and big endian, including the
+    /// +/-1 variants for inequality comparisons
+    fn add_int(
+        &mut self,
+        pattern: u64,
+        repl: u64,
+        size: usize,
+        input: &[u8],
+        seen: &mut HashSet<(Vec<u8>, Vec<u8>)>,
+    ) {
+        let mask = if size == 8 {
+            u64::MAX
+        } else {
+            (1 << (size * 8)) - 1
+        };
+        let pattern = pattern & mask;
+        if pattern == repl & mask {
+            return;
+        }
+
+        let pattern_le = &pattern.to_le_bytes()[..size];
+        let pattern_be: Vec<u8> = pattern_le.iter().rev().copied().collect();
+        for repl in [repl, repl.wrapping_add(1), repl.wrapping_sub(1)] {
+            let repl_le = &(repl & mask).to_le_bytes()[..size];
+            self.add(pattern_le, repl_le, input, seen);
+            if size > 1 {
+                let repl_be: Vec<u8> = repl_le.iter().rev().copied().collect();
+                self.add(&pattern_be, &repl_be, input, seen);
+            }
+        }
+    }
+
+    fn add(
+        &mut self,
+        pattern: &[u8],
+        repl: &[u8],
+        input: &[u8],
+        seen: &mut HashSet<(Vec<u8>, Vec<u8>)>,
+    ) {
+        if pattern.is_empty()
+            || repl.is_empty()
+            || pattern == repl
+            || self.pairs.len() >= MAX_CMPLOG_PAIRS
+            || find(input, pattern).is_none()
+        {
+            return;
+        }
+        let pair = (pattern.to_vec(), repl.to_vec());
+        if seen.insert(pair.clone()) {
+            self.pairs.push(pair);
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
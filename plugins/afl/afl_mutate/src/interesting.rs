@@ -48,7 +48,7 @@ impl InterestState {
         total
     }
 
-    pub fn mutate(&mut self, mut input: &mut [u8]) -> StageResult {
+    pub fn mutate(&mut self, mut input: &mut [u8], ctx: &mut StageCtx) -> StageResult {
         // Restore the orig input
         if let Some((idx, orig_val)) = self.prev_val.take() {
             unsafe {
@@ -95,6 +95,13 @@ impl InterestState {
 
             self.idx -= 1;
 
+            // Skip bytes we are not allowed to touch or that have no effect
+            if !ctx.mask.can_overwrite(self.idx, self.width as usize)
+                || !ctx.eff_map.has_effect(self.idx, self.width as usize)
+            {
+                continue;
+            }
+
             let orig = unsafe {
                 match self.width {
                     1 => input.set_byte(self.idx, *INTERESTING_8.get_unchecked(self.val_idx)) as _,
@@ -179,37 +186,3 @@ pub fn could_be_interest(old_val: u32, new_val: u32, blen: u8, check_le: bool) -
     }
     false
 }
-
-pub const INTERESTING_8: &[u8] = &[
-    0,   /*                                         */
-    1,   /*                                         */
-    16,  /* One-off with common buffer size         */
-    32,  /* One-off with common buffer size         */
-    64,  /* One-off with common buffer size         */
-    100, /* One-off with common buffer size         */
-    127, /*                                         */
-    128, /* Overflow signed 8-bit when decremented  */
-    255, /* u8::MAX                                 */
-];
-pub const INTERESTING_16: &[u16] = &[
-    0, 1, 16, 32, 64, 100, 127, 128, 128, 255,   /* Overflow signed 8-bit                   */
-    256,   /* Overflow unsig 8-bit                    */
-    512,   /* One-off with common buffer size         */
-    1000,  /* One-off with common buffer size         */
-    1024,  /* One-off with common buffer size         */
-    4096,  /* One-off with common buffer size         */
-    32767, /* Overflow signed 16-bit when incremented */
-    32768, /* Overflow signed 16-bit when decremented */
-    65407, /* Overflow signed 8-bit                   */
-    65535, /* u16::MAX                                */
-];
-pub const INTERESTING_32: &[u32] = &[
-    0, 1, 16, 32, 64, 100, 127, 128, 128, 255, 256, 512, 1000, 1024, 4096, 32767, 32768, 65407,
-    65535, 65536,      /* Overflow unsig 16 bit                   */
-    100663045,  /* Large positive number (endian-agnostic) */
-    2147483647, /* Overflow signed 32-bit when incremented */
-    2147483648, /* Overflow signed 32-bit when decremented */
-    4194304250, /* Large negative number (endian-agnostic) */
-    4294934527, /* Overflow signed 16-bit                  */
-    4294967295, /* u32::MAX                                */
-];
@@ -0,0 +1,140 @@
+/// Byte can be overwritten
+pub const MASK_OVERWRITE: u8 = 1;
+/// Bytes can be inserted before this byte
+pub const MASK_INSERT: u8 = 2;
+/// Byte can be deleted
+pub const MASK_DELETE: u8 = 4;
+pub const MASK_ALL: u8 = MASK_OVERWRITE | MASK_INSERT | MASK_DELETE;
+
+/// Restricts which operations the mutators can do on every byte of the input
+#[derive(Debug, Default)]
+pub struct ByteMask {
+    /// Flags for the original input. Empty when there are no restrictions
+    orig: Vec<u8>,
+    /// Flags restore() goes back to, the original ones unless the input was spliced
+    base: Vec<u8>,
+    /// Flags for the input currently being mutated (can grow/shrink in havoc)
+    cur: Vec<u8>,
+}
+
+impl ByteMask {
+    /// Removes all restrictions
+    pub fn clear(&mut self) {
+        self.orig.clear();
+        self.base.clear();
+        self.cur.clear();
+    }
+
+    /// Whether there is no restrictions
+    pub fn is_empty(&self) -> bool {
+        self.orig.is_empty()
+    }
+
+    /// Sets the flags for every byte of the original input
+    pub fn set(&mut self, flags: &[u8]) {
+        self.orig.clear();
+        self.orig.extend_from_slice(flags);
+        self.reset_base();
+    }
+
+    /// Only allows mutations inside the ranges. Bytes can still be
+    /// inserted right after a range.
+    pub fn set_regions(&mut self, ranges: &[std::ops::Range<usize>], len: usize) {
+        let mut flags = vec![0; len];
+        for r in ranges {
+            for f in flags[r.clone()].iter_mut() {
+                *f = MASK_ALL;
+            }
+            if let Some(f) = flags.get_mut(r.end) {
+                *f |= MASK_INSERT;
+            }
+        }
+        self.set(&flags);
+    }
+
+    /// Restricts the original flags further
+    pub fn intersect(&mut self, flags: &[u8]) {
+        if self.is_empty() {
+            self.set(flags);
+            return;
+        }
+        for (f, new) in self.orig.iter_mut().zip(flags.iter()) {
+            *f &= new;
+        }
+        self.reset_base();
+    }
+
+    /// Resets the current flags to match the input restore() goes back to
+    pub fn restore(&mut self) {
+        self.cur.clear();
+        self.cur.extend_from_slice(&self.base);
+    }
+
+    /// Makes restore() go back to the original input
+    pub fn reset_base(&mut self) {
+        self.base.clear();
+        self.base.extend_from_slice(&self.orig);
+        self.restore();
+    }
+
+    /// Makes restore() go back to the original input spliced at split_at with
+    /// another one, len bytes in total. Bytes from the other input can be
+    /// freely mutated.
+    pub fn splice_base(&mut self, split_at: usize, len: usize) {
+        if self.is_empty() {
+            return;
+        }
+        let split_at = std::cmp::min(split_at, self.orig.len());
+        self.base.clear();
+        self.base.extend_from_slice(&self.orig[..split_at]);
+        self.base.resize(std::cmp::max(len, split_at), MASK_ALL);
+        self.restore();
+    }
+
+    #[inline]
+    fn has(&self, idx: usize, flag: u8) -> bool {
+        match self.cur.get(idx) {
+            Some(f) => f & flag != 0,
+            // Bytes past the mask are always allowed
+            None => true,
+        }
+    }
+
+    /// Whether the bytes [idx, idx + len[ can be overwritten
+    pub fn can_overwrite(&self, idx: usize, len: usize) -> bool {
+        self.is_empty() || (idx..idx + len).all(|i| self.has(i, MASK_OVERWRITE))
+    }
+
+    /// Whether bytes can be inserted at idx
+    pub fn can_insert(&self, idx: usize) -> bool {
+        self.is_empty() || self.has(idx, MASK_INSERT)
+    }
+
+    /// Whether op (MASK_*) can be applied to idx
+    pub fn allows(&self, idx: usize, op: u8) -> bool {
+        self.is_empty() || self.has(idx, op)
+    }
+
+    /// Whether the bytes [idx, idx + len[ can be deleted
+    pub fn can_delete(&self, idx: usize, len: usize) -> bool {
+        self.is_empty() || (idx..idx + len).all(|i| self.has(i, MASK_DELETE))
+    }
+
+    /// Keeps the current flags in sync after inserting len bytes at idx.
+    /// Inserted bytes can be freely mutated.
+    pub fn on_insert(&mut self, idx: usize, len: usize) {
+        if self.is_empty() || idx > self.cur.len() {
+            return;
+        }
+        self.cur.splice(idx..idx, vec![MASK_ALL; len]);
+    }
+
+    /// Keeps the current flags in sync after deleting len bytes at idx
+    pub fn on_delete(&mut self, idx: usize, len: usize) {
+        if self.is_empty() || idx >= self.cur.len() {
+            return;
+        }
+        let end = std::cmp::min(idx + len, self.cur.len());
+        self.cur.drain(idx..end);
+    }
+}
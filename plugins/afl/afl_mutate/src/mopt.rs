@@ -0,0 +1,242 @@
+use ::rand::rngs::SmallRng;
+use ::rand::{Rng, SeedableRng};
+
+use crate::*;
+
+const W_INIT: f64 = 0.9;
+const W_END: f64 = 0.3;
+const G_MAX: u64 = 5000;
+const PROB_MIN: f64 = 0.05;
+const PROB_MAX: f64 = 1.0;
+
+/// A set of operator selection probabilities
+#[derive(Debug)]
+struct Swarm {
+    /// Current selection weight of each operator
+    x: Vec<f64>,
+    /// Velocity of each weight
+    v: Vec<f64>,
+    /// Weights that gave the best efficiency for each operator
+    l_best: Vec<f64>,
+    l_best_eff: Vec<f64>,
+    /// Finds/uses of each operator during the current pilot phase
+    finds: Vec<u64>,
+    cycles: Vec<u64>,
+    /// Havoc iterations and finds during the current pilot phase
+    total_finds: u64,
+    total_cycles: u64,
+    /// finds/iterations of the last pilot phase
+    fitness: f64,
+}
+
+impl Swarm {
+    fn new(rng: &mut SmallRng) -> Self {
+        let mut x: Vec<f64> = (0..NUM_HAVOC_OPS)
+            .map(|_| rng.gen_range(PROB_MIN, PROB_MAX))
+            .collect();
+        normalize(&mut x);
+        Self {
+            l_best: x.clone(),
+            x,
+            v: vec![0.1; NUM_HAVOC_OPS],
+            l_best_eff: vec![0.0; NUM_HAVOC_OPS],
+            finds: vec![0; NUM_HAVOC_OPS],
+            cycles: vec![0; NUM_HAVOC_OPS],
+            total_finds: 0,
+            total_cycles: 0,
+            fitness: 0.0,
+        }
+    }
+}
+
+fn normalize(x: &mut [f64]) {
+    let sum: f64 = x.iter().sum();
+    if sum > 0.0 {
+        x.iter_mut().for_each(|v| *v /= sum);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Evaluating the swarm at this index
+    Pilot(usize),
+    /// Fuzzing with the best swarm
+    Core(usize),
+}
+
+/// MOpt havoc operator scheduler. Particle swarms learn which
+/// operators find new inputs and havoc picks operators accordingly.
+#[derive(Debug)]
+pub struct MOpt {
+    swarms: Vec<Swarm>,
+    /// Best known weight of each operator across swarms
+    g_best: Vec<f64>,
+    /// Finds/uses of each operator during the core phase
+    core_finds: Vec<u64>,
+    core_cycles: Vec<u64>,
+    phase: Phase,
+    /// Havoc iterations since the start of the phase
+    phase_cycles: u64,
+    /// Number of PSO updates done
+    g_now: u64,
+    /// Length of the phases in havoc iterations
+    pub pilot_period: u64,
+    pub core_period: u64,
+    /// fast/non-crypto grade random
+    rng: SmallRng,
+}
+
+impl MOpt {
+    pub fn new(num_swarms: usize) -> Self {
+        let mut rng = SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap();
+        let swarms = (0..num_swarms).map(|_| Swarm::new(&mut rng)).collect();
+        Self {
+            swarms,
+            g_best: vec![1.0 / NUM_HAVOC_OPS as f64; NUM_HAVOC_OPS],
+            core_finds: vec![0; NUM_HAVOC_OPS],
+            core_cycles: vec![0; NUM_HAVOC_OPS],
+            phase: Phase::Pilot(0),
+            phase_cycles: 0,
+            g_now: 0,
+            pilot_period: 50000,
+            core_period: 500000,
+            rng,
+        }
+    }
+
+    fn cur_swarm(&self) -> &Swarm {
+        match self.phase {
+            Phase::Pilot(i) | Phase::Core(i) => &self.swarms[i],
+        }
+    }
+
+    /// Picks an operator in [0, num_ops[ using the current swarm's weights
+    pub fn select_op(&mut self, num_ops: usize) -> usize {
+        let x = match self.phase {
+            Phase::Pilot(i) | Phase::Core(i) => &self.swarms[i].x[..num_ops],
+        };
+        let sum: f64 = x.iter().sum();
+        let mut target = self.rng.gen_range(0.0, sum);
+        for (op, p) in x.iter().enumerate() {
+            if target < *p {
+                return op;
+            }
+            target -= p;
+        }
+        num_ops - 1
+    }
+
+    /// Records the operators used by a havoc iteration and whether it found
+    /// something. Returns true when the phase changed.
+    pub fn report(&mut self, ops: &[u8], found: bool) -> bool {
+        let mut used = [false; NUM_HAVOC_OPS];
+        for op in ops {
+            used[*op as usize] = true;
+        }
+
+        let (finds, cycles) = match self.phase {
+            Phase::Pilot(i) => {
+                let s = &mut self.swarms[i];
+                s.total_cycles += 1;
+                if found {
+                    s.total_finds += 1;
+                }
+                (&mut s.finds, &mut s.cycles)
+            }
+            Phase::Core(_) => (&mut self.core_finds, &mut self.core_cycles),
+        };
+        for op in (0..NUM_HAVOC_OPS).filter(|op| used[*op]) {
+            cycles[op] += 1;
+            if found {
+                finds[op] += 1;
+            }
+        }
+
+        self.phase_cycles += 1;
+        match self.phase {
+            Phase::Pilot(i) if self.phase_cycles >= self.pilot_period => {
+                self.end_pilot(i);
+                true
+            }
+            Phase::Core(_) if self.phase_cycles >= self.core_period => {
+                self.end_core();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Updates the swarm's local bests and moves on to the next swarm
+    fn end_pilot(&mut self, idx: usize) {
+        let s = &mut self.swarms[idx];
+        for op in 0..NUM_HAVOC_OPS {
+            if s.cycles[op] == 0 {
+                continue;
+            }
+            let eff = s.finds[op] as f64 / s.cycles[op] as f64;
+            if eff > s.l_best_eff[op] {
+                s.l_best_eff[op] = eff;
+                s.l_best[op] = s.x[op];
+            }
+        }
+        s.fitness = s.total_finds as f64 / std::cmp::max(s.total_cycles, 1) as f64;
+        s.finds.iter_mut().for_each(|v| *v = 0);
+        s.cycles.iter_mut().for_each(|v| *v = 0);
+        s.total_finds = 0;
+        s.total_cycles = 0;
+
+        self.phase_cycles = 0;
+        self.phase = if idx + 1 < self.swarms.len() {
+            Phase::Pilot(idx + 1)
+        } else {
+            // Every swarm was evaluated, fuzz with the best one
+            let mut best = 0;
+            for (i, s) in self.swarms.iter().enumerate() {
+                if s.fitness > self.swarms[best].fitness {
+                    best = i;
+                }
+            }
+            Phase::Core(best)
+        };
+    }
+
+    /// Updates the global bests and moves every swarm (PSO step)
+    fn end_core(&mut self) {
+        let total_finds: u64 = self.core_finds.iter().sum();
+        if total_finds > 0 {
+            for op in 0..NUM_HAVOC_OPS {
+                self.g_best[op] = self.core_finds[op] as f64 / total_finds as f64;
+            }
+        }
+        self.core_finds.iter_mut().for_each(|v| *v = 0);
+        self.core_cycles.iter_mut().for_each(|v| *v = 0);
+
+        self.g_now = std::cmp::min(self.g_now + 1, G_MAX);
+        let w_now = (W_INIT - W_END) * (G_MAX - self.g_now) as f64 / G_MAX as f64 + W_END;
+
+        for s in self.swarms.iter_mut() {
+            for op in 0..NUM_HAVOC_OPS {
+                s.v[op] = w_now * s.v[op]
+                    + self.rng.gen_range(0.0, 1.0) * (s.l_best[op] - s.x[op])
+                    + self.rng.gen_range(0.0, 1.0) * (self.g_best[op] - s.x[op]);
+                s.x[op] = (s.x[op] + s.v[op]).clamp(PROB_MIN, PROB_MAX);
+            }
+            normalize(&mut s.x);
+        }
+
+        self.phase_cycles = 0;
+        self.phase = Phase::Pilot(0);
+    }
+
+    /// Writes the phase and the operator distribution (in %)
+    pub fn desc(&self, dst: &mut String) {
+        use std::fmt::Write;
+        let _ = match self.phase {
+            Phase::Pilot(i) => write!(dst, "pilot {} :", i),
+            Phase::Core(i) => write!(dst, "core {} :", i),
+        };
+        for p in self.cur_swarm().x.iter() {
+            let _ = write!(dst, " {}", (p * 100.0).round() as u32);
+        }
+    }
+}
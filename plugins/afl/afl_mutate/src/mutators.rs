@@ -17,22 +17,54 @@ pub enum StageResult {
     Done,
 }
 
+/// Values shared with the stages while they mutate
+pub struct StageCtx<'a> {
+    /// Restrictions on what can be done to the input
+    pub mask: &'a mut ByteMask,
+    /// Bytes that are worth mutating in the deterministic stages
+    pub eff_map: &'a mut EffectorMap,
+    /// Coverage of the previous execution if available
+    pub trace_bits: Option<&'a [u8]>,
+    /// Tokens from the user provided dictionaries
+    pub user_extras: &'a [Vec<u8>],
+    /// Tokens found while flipping bits
+    pub auto_extras: &'a mut AutoExtras,
+    /// Path checksum of the original input (0 when unknown)
+    pub exec_cksum: u32,
+    /// Every input of the fuzzer
+    pub inputs: &'a [CfInputInfo],
+    /// Index of the input being mutated
+    pub cur_input_idx: usize,
+    /// Operators applied by the last havoc iteration
+    pub havoc_ops: &'a mut Vec<u8>,
+    /// Picks the havoc operators when enabled
+    pub mopt: Option<&'a mut MOpt>,
+    /// Comparisons logged by the cmplog target if available
+    pub cmplog: Option<&'a CmpMap>,
+    /// Set to run the cmplog target on the next execution
+    pub cmplog_run: Option<&'a mut bool>,
+}
+
 #[derive(Debug)]
 pub enum MutatorStage {
+    /// Builds a mask of the bytes that keep hitting a rare edge
+    RareMask(RareMaskState),
+    /// Substitutes the operands of comparisons (RedQueen)
+    InputToState(InputToStateState),
     /// Flips groups of bits [1,2,4,8,16,32]
     BitFlip(BitFlipState),
     /// Perform arithmetic operations [8,16,32]
     Arithmetic(ArithState),
     /// Insert interesting values [8,16,32]
     Interesting(InterestState),
-    /*
     /// Use user provided values
-    UserExtra,
+    UserExtra(ExtrasState),
     /// Automaticaly found values
-    AutoExtra,
-    */
+    AutoExtra(ExtrasState),
     /// Do anything
     Havoc(HavocState),
+    /// Havoc on the input spliced with another one
+    Splice(SpliceState),
 }
 impl Default for MutatorStage {
     fn default() -> Self {
@@ -46,8 +78,28 @@ impl MutatorStage {
         q: &mut AflQueueEntry,
         afl: &AflGlobals,
         input: &mut CfInput,
+        ctx: &mut StageCtx,
+        regions: Option<&MutableRegions>,
     ) -> &mut Self {
-        if afl.skip_deterministic || q.passed_det {
+        ctx.mask.clear();
+        ctx.eff_map.clear();
+
+        // Only touch the regions the user allows
+        if let Some(r) = regions {
+            ctx.mask.set_regions(&r.resolve(input.len()), input.len());
+        }
+
+        // Start by finding which bytes we can touch if the input hits a rare edge
+        if afl.rare_edges {
+            if let Some(edge) = rarest_edge(&q.edges, &afl.edge_hits, afl.rare_cutoff) {
+                *self = Self::RareMask(RareMaskState::new(input, edge));
+                return self;
+            }
+        }
+
+        if ctx.cmplog.is_some() && !q.passed_cmplog {
+            *self = Self::InputToState(InputToStateState::new());
+        } else if afl.skip_deterministic || q.passed_det {
             if let Self::Havoc(ref mut s) = self {
                 s.reset(q, afl);
             } else {
@@ -61,9 +113,32 @@ impl MutatorStage {
     }
     
     /// Progress to the next mutator
-    pub fn next(&mut self, q: &mut AflQueueEntry, afl: &AflGlobals, input: &[u8]) -> bool {
+    pub fn next(
+        &mut self,
+        q: &mut AflQueueEntry,
+        afl: &AflGlobals,
+        input: &[u8],
+        ctx: &StageCtx,
+    ) -> bool {
         match self {
-            Self::Havoc(_) => false,
+            Self::Havoc(s) => {
+                *self = Self::Splice(SpliceState::new(s.perf_score(), afl));
+                true
+            }
+            Self::Splice(_) => false,
+            Self::RareMask(_) if ctx.cmplog.is_some() && !q.passed_cmplog => {
+                *self = Self::InputToState(InputToStateState::new());
+                true
+            }
+            Self::RareMask(_) | Self::InputToState(_) => {
+                q.passed_cmplog = true;
+                *self = if afl.skip_deterministic || q.passed_det {
+                    Self::Havoc(HavocState::new(q, afl))
+                } else {
+                    Self::BitFlip(BitFlipState::new(input))
+                };
+                true
+            }
             Self::BitFlip(_) => {
                 *self = Self::Arithmetic(ArithState::new(input));
                 true
@@ -73,22 +148,34 @@ impl MutatorStage {
                 true
             }
             Self::Interesting(_) => {
+                *self = Self::UserExtra(ExtrasState::new(false));
+                true
+            }
+            Self::UserExtra(_) => {
+                *self = Self::AutoExtra(ExtrasState::new(true));
+                true
+            }
+            Self::AutoExtra(_) => {
                 *self = Self::Havoc(HavocState::new(q, afl));
                 true
             }
         }
     }
 
-    pub fn mutate(&mut self, input: &mut CfInput) -> StageResult {
+    pub fn mutate(&mut self, input: &mut CfInput, ctx: &mut StageCtx) -> StageResult {
         match self {
-            Self::Havoc(s) => s.mutate(input),
-            Self::BitFlip(s) => s.mutate(input),
-            Self::Arithmetic(s) => s.mutate(input),
-            Self::Interesting(s) => s.mutate(input),
+            Self::Havoc(s) => s.mutate(input, ctx),
+            Self::Splice(s) => s.mutate(input, ctx),
+            Self::RareMask(s) => s.mutate(input, ctx),
+            Self::InputToState(s) => s.mutate(input, ctx),
+            Self::BitFlip(s) => s.mutate(input, ctx),
+            Self::Arithmetic(s) => s.mutate(input, ctx),
+            Self::Interesting(s) => s.mutate(input, ctx),
+            Self::UserExtra(s) | Self::AutoExtra(s) => s.mutate(input, ctx),
         }
     }
 
-    pub fn update_state(&self, input: &[u8], name: Option<&mut String>, total_cycles: Option<&mut u64>) {
+    pub fn update_state(&self, input: &[u8], ctx: &StageCtx, name: Option<&mut String>, total_cycles: Option<&mut u64>) {
         match self {
             Self::Havoc(s) => {
                 if let Some(n) = name {
@@ -98,6 +185,30 @@ impl MutatorStage {
                     *c = s.total_cycles() as _;
                 }
             },
+            Self::Splice(s) => {
+                if let Some(n) = name {
+                    s.desc(n);
+                }
+                if let Some(c) = total_cycles {
+                    *c = s.total_cycles() as _;
+                }
+            },
+            Self::RareMask(s) => {
+                if let Some(n) = name {
+                    s.desc(n);
+                }
+                if let Some(c) = total_cycles {
+                    *c = s.total_cycles(input) as _;
+                }
+            },
+            Self::InputToState(s) => {
+                if let Some(n) = name {
+                    s.desc(n);
+                }
+                if let Some(c) = total_cycles {
+                    *c = s.total_cycles() as _;
+                }
+            },
             Self::BitFlip(s) => {
                 if let Some(n) = name {
                     s.desc(n);
@@ -122,6 +233,14 @@ impl MutatorStage {
                     *c = s.total_cycles(input) as _;
                 }
             },
+            Self::UserExtra(s) | Self::AutoExtra(s) => {
+                if let Some(n) = name {
+                    s.desc(n);
+                }
+                if let Some(c) = total_cycles {
+                    *c = s.total_cycles(input, ctx) as _;
+                }
+            },
         }
     }
 }
@@ -0,0 +1,94 @@
+use crate::*;
+
+/// Operations tested on every byte
+const MASK_OPS: [u8; 3] = [MASK_OVERWRITE, MASK_DELETE, MASK_INSERT];
+
+/// Finds out which bytes can be mutated while still hitting a rare edge (FairFuzz)
+#[derive(Debug)]
+pub struct RareMaskState {
+    /// The rare edge we want to keep hitting
+    edge: u32,
+    idx: usize,
+    op_idx: usize,
+    /// Original value of the last byte we touched
+    prev_val: Option<u8>,
+    flags: Vec<u8>,
+}
+
+impl RareMaskState {
+    pub fn new(input: &[u8], edge: u32) -> Self {
+        Self {
+            edge,
+            idx: 0,
+            op_idx: 0,
+            prev_val: None,
+            flags: vec![0; input.len()],
+        }
+    }
+
+    pub fn desc(&self, dst: &mut String) {
+        use std::fmt::Write;
+        let _ = write!(dst, "rare mask {:04X}", self.edge);
+    }
+
+    pub fn total_cycles(&self, input: &[u8]) -> usize {
+        input.len() * MASK_OPS.len()
+    }
+
+    fn advance(&mut self) {
+        self.op_idx += 1;
+        if self.op_idx == MASK_OPS.len() {
+            self.op_idx = 0;
+            self.idx += 1;
+        }
+    }
+
+    pub fn mutate(&mut self, input: &mut CfInput, ctx: &mut StageCtx) -> StageResult {
+        let trace_bits = match ctx.trace_bits {
+            Some(t) => t,
+            // We cant tell which bytes are safe without coverage
+            None => return StageResult::Done,
+        };
+
+        // See if our last change still hit the edge
+        if let Some(orig) = self.prev_val.take() {
+            let op = MASK_OPS[self.op_idx];
+            if unsafe { *trace_bits.get_unchecked(self.edge as usize) } != 0 {
+                self.flags[self.idx] |= op;
+            }
+
+            // Restore the orig input
+            match op {
+                MASK_OVERWRITE => input[self.idx] = orig,
+                MASK_DELETE => input.insert(self.idx, orig),
+                _ => {
+                    input.remove(self.idx);
+                }
+            };
+
+            self.advance();
+        }
+
+        // Skip operations the current mask already forbids
+        while self.idx < input.len() && !ctx.mask.allows(self.idx, MASK_OPS[self.op_idx]) {
+            self.advance();
+        }
+
+        if self.idx >= input.len() {
+            ctx.mask.intersect(&self.flags);
+            return StageResult::Done;
+        }
+
+        let orig = input[self.idx];
+        match MASK_OPS[self.op_idx] {
+            MASK_OVERWRITE => input[self.idx] ^= 0xFF,
+            MASK_DELETE => {
+                input.remove(self.idx);
+            }
+            _ => input.insert(self.idx, orig ^ 0xFF),
+        };
+        self.prev_val = Some(orig);
+
+        StageResult::WillRestoreInput
+    }
+}
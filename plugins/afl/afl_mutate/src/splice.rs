@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fs;
+
+use ::rand::rngs::SmallRng;
+use ::rand::{Rng, SeedableRng};
+
+use crate::*;
+
+/// Splices the input with another one and runs havoc on the result
+#[derive(Debug)]
+pub struct SpliceState {
+    cycle: u32,
+    /// Havoc iterations for every spliced input
+    num_iterations: usize,
+    /// Contents of the input before splicing
+    orig: Vec<u8>,
+    /// Current spliced input, empty when a new one is needed
+    buf: Vec<u8>,
+    /// Contents of the inputs read from disk, None if they could not be read
+    partners: HashMap<usize, Option<Vec<u8>>>,
+    havoc: HavocState,
+    /// fast/non-crypto grade random
+    rng: SmallRng,
+}
+
+impl SpliceState {
+    pub fn new(perf_score: u32, afl: &AflGlobals) -> Self {
+        let mut num_iterations =
+            SPLICE_HAVOC as usize * perf_score as usize / afl.havoc_div as usize / 100;
+        if num_iterations < HAVOC_MIN {
+            num_iterations = HAVOC_MIN;
+        }
+
+        Self {
+            cycle: 0,
+            num_iterations,
+            orig: Vec::new(),
+            buf: Vec::new(),
+            partners: HashMap::new(),
+            havoc: HavocState::with_iterations(0),
+            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+        }
+    }
+
+    pub fn desc(&self, dst: &mut String) {
+        use std::fmt::Write;
+        let _ = write!(dst, "splice {}", self.cycle);
+    }
+
+    pub fn total_cycles(&self) -> usize {
+        self.num_iterations
+    }
+
+    /// Builds a new spliced input from orig and a random other input
+    fn splice(&mut self, ctx: &mut StageCtx) -> bool {
+        if ctx.inputs.len() < 2 {
+            return false;
+        }
+
+        // Pick an input that isnt the current one
+        let mut idx = self.rng.gen_range(0, ctx.inputs.len() - 1);
+        if idx >= ctx.cur_input_idx {
+            idx += 1;
+        }
+        let info = unsafe { ctx.inputs.get_unchecked(idx) };
+        if info.len < 2 {
+            return false;
+        }
+        let other = match (&info.contents, &info.path) {
+            (Some(c), _) => c.as_slice(),
+            (None, Some(p)) => match self.partners.entry(idx).or_insert_with(|| fs::read(p).ok()) {
+                Some(c) => c.as_slice(),
+                None => return false,
+            },
+            _ => return false,
+        };
+
+        // Find a suitable splicing location between the first and last differing byte
+        let mut f_diff = None;
+        let mut l_diff = 0;
+        for (i, (a, b)) in self.orig.iter().zip(other.iter()).enumerate() {
+            if a != b {
+                if f_diff.is_none() {
+                    f_diff = Some(i);
+                }
+                l_diff = i;
+            }
+        }
+        let f_diff = match f_diff {
+            Some(f) if l_diff >= 2 && f != l_diff => f,
+            _ => return false,
+        };
+        let split_at = f_diff + self.rng.gen_range(0, l_diff - f_diff);
+        // Everything after the split gets replaced
+        if !ctx.mask.can_delete(split_at, self.orig.len() - split_at) {
+            return false;
+        }
+
+        self.buf.clear();
+        self.buf.extend_from_slice(&self.orig[..split_at]);
+        self.buf.extend_from_slice(&other[split_at..]);
+        // Havoc restores the mask of the spliced input on every iteration
+        ctx.mask.splice_base(split_at, self.buf.len());
+        true
+    }
+
+    pub fn mutate(&mut self, input: &mut CfInput, ctx: &mut StageCtx) -> StageResult {
+        // The first call always gets the original input
+        if self.cycle == 0 && self.orig.is_empty() {
+            self.orig.extend_from_slice(input);
+        }
+
+        if self.buf.is_empty() {
+            loop {
+                if self.cycle == SPLICE_CYCLES {
+                    input.clear();
+                    input.extend_from_slice(&self.orig);
+                    ctx.mask.reset_base();
+                    return StageResult::Done;
+                }
+                self.cycle += 1;
+                if self.splice(ctx) {
+                    break;
+                }
+            }
+            self.havoc.set_iterations(self.num_iterations);
+            return StageResult::Update;
+        }
+
+        // Havoc always starts from the spliced input
+        input.clear();
+        input.extend_from_slice(&self.buf);
+        match self.havoc.mutate(input, ctx) {
+            StageResult::Done => {
+                self.buf.clear();
+                self.mutate(input, ctx)
+            }
+            r => r,
+        }
+    }
+}
@@ -19,6 +19,7 @@ struct State {
     first_trace: Vec<u8>,
     tmp: String,
     init_testcase_num: usize,
+    prev_selected_idx: usize,
 
     queued_with_cov: StatNum,
     queued_variable: StatNum,
@@ -47,6 +48,7 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
             first_trace: Vec::with_capacity(MAP_SIZE),
             tmp: String::new(),
             init_testcase_num: 0,
+            prev_selected_idx: 0,
 
             // Stats
             queued_with_cov: core
@@ -120,6 +122,21 @@ fn update_state(
         s.queue.resize(s.inputs.len(), val);
     }
 
+    if s.afl.rare_edges {
+        // Keep track of how many times every edge got hit
+        if let Some(trace_bits) = s.trace_bits {
+            increment_hits(&mut s.afl.edge_hits, trace_bits);
+        }
+
+        // Queue up inputs hitting rare edges once in a while
+        if s.prev_selected_idx != *s.input_idx {
+            s.prev_selected_idx = *s.input_idx;
+            if s.init_testcase_num == 0 && s.input_priority.is_empty() {
+                s.prioritize_rare_inputs();
+            }
+        }
+    }
+
     // Process calibration info from last run
     if s.is_calibrating {
         let mut first_cal = false;
@@ -157,6 +174,14 @@ fn update_state(
                 s.first_trace.extend_from_slice(&trace_bits);
                 q.exec_cksum = cksum;
                 q.bitmap_size = count_bytes(trace_bits);
+                if s.afl.rare_edges {
+                    q.edges.clear();
+                    for (i, b) in trace_bits.iter().enumerate() {
+                        if *b != 0 {
+                            q.edges.push(i as u32);
+                        }
+                    }
+                }
                 s.afl.total_bitmap_size += q.bitmap_size as u64;
                 s.afl.total_bitmap_entries += 1;
                 *s.queued_with_cov.val += 1;
@@ -258,10 +283,35 @@ impl State {
         if plugin_conf.get("afl_skip_deterministic").is_some() {
             self.afl.skip_deterministic = true;
         }
-        
+
+        if plugin_conf.get("afl_rare_edges").is_some() {
+            self.afl.rare_edges = true;
+            self.afl.edge_hits = vec![0; MAP_SIZE];
+        }
+
         Ok(())
     }
 
+    /// Pushes the inputs that hit rare edges in the priority queue, the rarest first.
+    /// Inputs that still need calibration are also pushed so they dont get starved.
+    pub fn prioritize_rare_inputs(&mut self) {
+        self.afl.rare_cutoff = rare_cutoff(&self.afl.edge_hits);
+
+        for (idx, q) in self.queue.iter().enumerate() {
+            if q.cal_left != 0 {
+                self.input_priority
+                    .push(InputPriority::from(usize::MAX, idx));
+                continue;
+            }
+
+            if let Some(edge) = rarest_edge(&q.edges, &self.afl.edge_hits, self.afl.rare_cutoff) {
+                let hits = unsafe { *self.afl.edge_hits.get_unchecked(edge as usize) };
+                self.input_priority
+                    .push(InputPriority::from(usize::MAX - 1 - hits as usize, idx));
+            }
+        }
+    }
+
     pub fn show_init_stats(&mut self, core: &mut dyn PluginInterface, store: &CfStore) {
         use std::fmt::Write;
         let mut max_len: usize = 0;
@@ -1,8 +1,12 @@
+use std::collections::HashMap;
 use std::mem::MaybeUninit;
 
 use ::cflib::*;
 use ::rand::rngs::SmallRng;
-use ::rand::{Rng, SeedableRng};
+use ::rand::SeedableRng;
+
+mod strategies;
+pub use strategies::*;
 
 cflib::register!(name, env!("CARGO_PKG_NAME"));
 cflib::register!(load, init);
@@ -10,27 +14,61 @@ cflib::register!(pre_fuzz, validate);
 cflib::register!(fuzz, mutate_input);
 cflib::register!(unload, destroy);
 
+const DEFAULT_MAX_MUTATIONS: usize = 16;
+const DEFAULT_MUTATION_RATE: f64 = 10.0;
+
 struct State {
-    /// fast/non-crypto grade random
-    rng: SmallRng,
+    mutator: Mutator,
+    /// How the number of mutations is picked
+    distribution: Distribution,
+    /// Maximum number of mutations per iteration
+    max_mutations: usize,
+    /// Maximum number of mutations in percent of the input length
+    mutation_rate: f64,
+    /// Byte ranges the user allows us to mutate
+    regions: InputRegions,
     no_mutate: &'static bool,
+    inputs: &'static Vec<CfInputInfo>,
+    cur_input_idx: &'static usize,
     /// Reference to the currently selected input
     cur_input: &'static mut CfInput,
-    
 }
 
 // Initialize our plugin
 fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+    let regions = match InputRegions::from_conf(plugin_conf) {
+        Ok(r) => r,
+        Err(e) => {
+            core.error(&format!("Invalid mutate_regions : {}", e));
+            return Err(From::from("Invalid config".to_string()));
+        }
+    };
+
     #[allow(invalid_value)]
-    let s = Box::new(unsafe {
+    let mut s = Box::new(unsafe {
         State {
-            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+            mutator: Mutator {
+                rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+                strategies: ALL_STRATEGIES.to_vec(),
+                min_len: 1,
+                // Inputs can grow without limit by default
+                max_len: usize::MAX,
+            },
+            distribution: Distribution::Exponential,
+            max_mutations: DEFAULT_MAX_MUTATIONS,
+            mutation_rate: DEFAULT_MUTATION_RATE,
+            regions,
             // core store values
             no_mutate: store.as_mutref(STORE_NO_MUTATE, Some(core))?,
             // Plugin store values
+            inputs: MaybeUninit::zeroed().assume_init(),
+            cur_input_idx: MaybeUninit::zeroed().assume_init(),
             cur_input: MaybeUninit::zeroed().assume_init(),
         }
     });
+    s.load_conf(core, plugin_conf)?;
 
     Ok(Box::into_raw(s) as _)
 }
@@ -45,6 +83,8 @@ fn validate(
 
     // We need a plugin that creates in input_bytes
     unsafe {
+        s.inputs = store.as_ref(STORE_INPUT_LIST, Some(core))?;
+        s.cur_input_idx = store.as_ref(STORE_INPUT_IDX, Some(core))?;
         s.cur_input = store.as_mutref(STORE_INPUT_BYTES, Some(core))?;
     }
 
@@ -53,22 +93,38 @@ fn validate(
 
 // Perform our task in the fuzzing loop
 fn mutate_input(
-    _core: &mut dyn PluginInterface,
+    core: &mut dyn PluginInterface,
     _store: &mut CfStore,
     plugin_ctx: *mut u8,
 ) -> Result<()> {
     let s = box_ref!(plugin_ctx, State);
 
-    if *s.no_mutate || s.cur_input.is_empty() {
-        // Input is empty ??
+    if *s.no_mutate {
         return Ok(());
     }
 
     let input_len = s.cur_input.len();
-    // Randomly mutate some bytes in the first chunk
-    let num_of_bytes_mutated = s.rng.gen_range(0, input_len);
-    for _ in 0..num_of_bytes_mutated {
-        s.cur_input[s.rng.gen_range(0, input_len)] = s.rng.gen::<u8>();
+    let idx = *s.cur_input_idx;
+    let regions = match s.regions.get(idx, unsafe { s.inputs.get_unchecked(idx) }) {
+        Ok(r) => r,
+        Err(e) => {
+            core.warn(&format!("Failed to load mutable regions of input {} : {}", idx, e));
+            None
+        }
+    };
+    let mut ranges = match regions {
+        Some(r) => r.resolve(input_len),
+        None => std::iter::once(0..input_len).collect(),
+    };
+
+    // Scale the number of mutations with the input size
+    let max_mutations = (input_len as f64 * s.mutation_rate / 100.0) as usize;
+    let max_mutations = std::cmp::max(1, std::cmp::min(max_mutations, s.max_mutations));
+    let num_mutations = s.distribution.pick(&mut s.mutator.rng, max_mutations);
+    for _ in 0..num_mutations {
+        if !s.mutator.mutate(s.cur_input, &mut ranges) {
+            break;
+        }
     }
 
     Ok(())
@@ -83,3 +139,98 @@ fn destroy(
     let _state = box_take!(plugin_ctx, State);
     Ok(())
 }
+
+impl State {
+    /// Parse the plugin_conf for our values
+    pub fn load_conf(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        plugin_conf: &HashMap<String, String>,
+    ) -> Result<()> {
+        if let Some(v) = plugin_conf.get("basic_seed") {
+            match v.parse::<u64>() {
+                Ok(seed) => self.mutator.rng = SmallRng::seed_from_u64(seed),
+                Err(_) => {
+                    core.error(&format!("basic_seed must be a number : '{}'", v));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        // Strategies are separated by ','
+        if let Some(v) = plugin_conf.get("basic_strategies") {
+            self.mutator.strategies.clear();
+            for name in v.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                match Strategy::parse(name) {
+                    Ok(st) if !self.mutator.strategies.contains(&st) => {
+                        self.mutator.strategies.push(st)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        core.error(&format!("Invalid basic_strategies : {}", e));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+            if self.mutator.strategies.is_empty() {
+                core.error("basic_strategies must contain at least one strategy");
+                return Err(From::from("Invalid config".to_string()));
+            }
+        }
+
+        if let Some(v) = plugin_conf.get("basic_distribution") {
+            match Distribution::parse(v) {
+                Ok(d) => self.distribution = d,
+                Err(e) => {
+                    core.error(&format!("Invalid basic_distribution : {}", e));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        if let Some(v) = plugin_conf.get("basic_mutation_rate") {
+            match v.parse::<f64>() {
+                Ok(rate) if rate > 0.0 && rate <= 100.0 => self.mutation_rate = rate,
+                _ => {
+                    core.error(&format!(
+                        "basic_mutation_rate must be a percentage above 0 : '{}'",
+                        v
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        if let Some(v) = plugin_conf.get("basic_min_len") {
+            match v.parse::<usize>() {
+                Ok(num) => self.mutator.min_len = num,
+                Err(_) => {
+                    core.error(&format!("basic_min_len must be a number : '{}'", v));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        // Inputs over basic_max_len are not truncated, they just stop growing
+        for (key, dst) in [
+            ("basic_max_mutations", &mut self.max_mutations),
+            ("basic_max_len", &mut self.mutator.max_len),
+        ] {
+            if let Some(v) = plugin_conf.get(key) {
+                match v.parse::<usize>() {
+                    Ok(num) if num > 0 => *dst = num,
+                    _ => {
+                        core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+        }
+        if self.mutator.min_len > self.mutator.max_len {
+            core.error("basic_min_len cant be bigger than basic_max_len");
+            return Err(From::from("Invalid config".to_string()));
+        }
+
+        Ok(())
+    }
+}
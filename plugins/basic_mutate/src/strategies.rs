@@ -0,0 +1,249 @@
+use std::ops::Range;
+
+use ::afl_lib::{INTERESTING_16, INTERESTING_32, INTERESTING_8};
+use ::rand::rngs::SmallRng;
+use ::rand::Rng;
+
+/// Biggest block inserted, deleted or duplicated at once
+const MAX_BLOCK_LEN: usize = 64;
+/// Number of times a mutation is retried when it cant be applied
+const MAX_RETRIES: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Strategy {
+    /// Flips a single bit
+    BitFlip,
+    /// Overwrites a byte with a random value
+    RandomByte,
+    /// Overwrites 1, 2 or 4 bytes with an interesting value
+    Interesting,
+    /// Inserts random or repeated bytes
+    Insert,
+    /// Deletes a block of bytes
+    Delete,
+    /// Inserts a copy of a block right after it
+    Duplicate,
+}
+pub const ALL_STRATEGIES: &[Strategy] = &[
+    Strategy::BitFlip,
+    Strategy::RandomByte,
+    Strategy::Interesting,
+    Strategy::Insert,
+    Strategy::Delete,
+    Strategy::Duplicate,
+];
+
+impl Strategy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "bitflip" => Self::BitFlip,
+            "byte" => Self::RandomByte,
+            "interesting" => Self::Interesting,
+            "insert" => Self::Insert,
+            "delete" => Self::Delete,
+            "duplicate" => Self::Duplicate,
+            _ => return Err(format!("Unknown strategy '{}'", name)),
+        })
+    }
+}
+
+/// How the number of mutations per iteration is picked
+#[derive(Debug, Copy, Clone)]
+pub enum Distribution {
+    /// Any count between 1 and the max
+    Uniform,
+    /// Powers of two up to the max, favoring small counts
+    Exponential,
+}
+
+impl Distribution {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "uniform" => Self::Uniform,
+            "exponential" => Self::Exponential,
+            _ => return Err(format!("Unknown distribution '{}'", name)),
+        })
+    }
+
+    /// Picks a count in [1, max]
+    pub fn pick(self, rng: &mut SmallRng, max: usize) -> usize {
+        match self {
+            Self::Uniform => rng.gen_range(1, max + 1),
+            Self::Exponential => {
+                let num_bits = (usize::BITS - max.leading_zeros()) as usize;
+                1 << rng.gen_range(0, num_bits)
+            }
+        }
+    }
+}
+
+pub struct Mutator {
+    /// fast/non-crypto grade random
+    pub rng: SmallRng,
+    pub strategies: Vec<Strategy>,
+    pub min_len: usize,
+    pub max_len: usize,
+}
+
+impl Mutator {
+    /// Applies a random strategy to the bytes in ranges. The ranges are
+    /// kept in sync when the input grows or shrinks.
+    pub fn mutate(&mut self, input: &mut Vec<u8>, ranges: &mut [Range<usize>]) -> bool {
+        for _ in 0..MAX_RETRIES {
+            let strategy = self.strategies[self.rng.gen_range(0, self.strategies.len())];
+            if self.apply(strategy, input, ranges) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn apply(
+        &mut self,
+        strategy: Strategy,
+        input: &mut Vec<u8>,
+        ranges: &mut [Range<usize>],
+    ) -> bool {
+        match strategy {
+            Strategy::BitFlip => {
+                let idx = match self.pick_block(ranges, 1) {
+                    Some(i) => i,
+                    None => return false,
+                };
+                input[idx] ^= 1 << self.rng.gen_range(0, 8);
+            }
+            Strategy::RandomByte => {
+                let idx = match self.pick_block(ranges, 1) {
+                    Some(i) => i,
+                    None => return false,
+                };
+                // Make sure the value changes
+                input[idx] ^= self.rng.gen_range(1, 256) as u8;
+            }
+            Strategy::Interesting => {
+                let width = 1 << self.rng.gen_range(0, 3);
+                let idx = match self.pick_block(ranges, width) {
+                    Some(i) => i,
+                    None => return false,
+                };
+                let val: u32 = match width {
+                    1 => INTERESTING_8[self.rng.gen_range(0, INTERESTING_8.len())] as u32,
+                    2 => INTERESTING_16[self.rng.gen_range(0, INTERESTING_16.len())] as u32,
+                    _ => INTERESTING_32[self.rng.gen_range(0, INTERESTING_32.len())],
+                };
+                let dst = &mut input[idx..idx + width];
+                dst.copy_from_slice(&val.to_le_bytes()[..width]);
+                // Big endian half of the time
+                if self.rng.gen() {
+                    dst.reverse();
+                }
+            }
+            Strategy::Insert => {
+                if input.len() >= self.max_len {
+                    return false;
+                }
+                let idx = match self.pick_insert(ranges) {
+                    Some(i) => i,
+                    None => return false,
+                };
+                let len = self
+                    .rng
+                    .gen_range(1, std::cmp::min(MAX_BLOCK_LEN, self.max_len - input.len()) + 1);
+                let block: Vec<u8> = if self.rng.gen() {
+                    vec![self.rng.gen(); len]
+                } else {
+                    (0..len).map(|_| self.rng.gen()).collect()
+                };
+                input.splice(idx..idx, block);
+                on_insert(ranges, idx, len);
+            }
+            Strategy::Delete => {
+                if input.len() <= self.min_len {
+                    return false;
+                }
+                let max_len = std::cmp::min(MAX_BLOCK_LEN, input.len() - self.min_len);
+                let len = self.rng.gen_range(1, max_len + 1);
+                let idx = match self.pick_block(ranges, len) {
+                    Some(i) => i,
+                    None => return false,
+                };
+                input.drain(idx..idx + len);
+                on_delete(ranges, idx, len);
+            }
+            Strategy::Duplicate => {
+                if input.is_empty() || input.len() >= self.max_len {
+                    return false;
+                }
+                let max_len = std::cmp::min(
+                    std::cmp::min(MAX_BLOCK_LEN, input.len()),
+                    self.max_len - input.len(),
+                );
+                let len = self.rng.gen_range(1, max_len + 1);
+                let idx = match self.pick_block(ranges, len) {
+                    Some(i) => i,
+                    None => return false,
+                };
+                let block = input[idx..idx + len].to_vec();
+                input.splice(idx + len..idx + len, block);
+                on_insert(ranges, idx + len, len);
+            }
+        }
+        true
+    }
+
+    /// Picks the start of a block of len bytes that fits in a range
+    fn pick_block(&mut self, ranges: &[Range<usize>], len: usize) -> Option<usize> {
+        let num_starts = |r: &Range<usize>| (r.end - r.start + 1).saturating_sub(len);
+        let total: usize = ranges.iter().map(num_starts).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut offset = self.rng.gen_range(0, total);
+        for r in ranges {
+            let n = num_starts(r);
+            if offset < n {
+                return Some(r.start + offset);
+            }
+            offset -= n;
+        }
+        None
+    }
+
+    /// Picks where bytes can be inserted (anywhere in a range or right after it)
+    fn pick_insert(&mut self, ranges: &[Range<usize>]) -> Option<usize> {
+        let total: usize = ranges.iter().map(|r| r.len() + 1).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut offset = self.rng.gen_range(0, total);
+        for r in ranges {
+            if offset <= r.len() {
+                return Some(r.start + offset);
+            }
+            offset -= r.len() + 1;
+        }
+        None
+    }
+}
+
+fn on_insert(ranges: &mut [Range<usize>], idx: usize, len: usize) {
+    for r in ranges.iter_mut() {
+        if r.start > idx {
+            r.start += len;
+            r.end += len;
+        } else if r.end >= idx {
+            r.end += len;
+        }
+    }
+}
+
+fn on_delete(ranges: &mut [Range<usize>], idx: usize, len: usize) {
+    for r in ranges.iter_mut() {
+        if r.start > idx {
+            r.start -= len;
+            r.end -= len;
+        } else if r.end > idx {
+            r.end -= len;
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+
+use ::cflib::*;
+
+mod field;
+pub use field::*;
+
+cflib::register!(name, env!("CARGO_PKG_NAME"));
+cflib::register!(load, init);
+cflib::register!(pre_fuzz, validate);
+cflib::register!(fuzz, fixup_input);
+cflib::register!(unload, destroy);
+
+struct State {
+    /// Fields recomputed in the order they were declared
+    fields: Vec<ChecksumField>,
+    /// Number of inputs that had at least one field fixed
+    num_fixups: StatNum,
+
+    /// Fixed up copy of the input given to the target
+    exec_input: &'static mut CfInput,
+    owned_exec_input: CfInput,
+    is_exec_input_owner: bool,
+
+    no_mutate: &'static bool,
+    /// Reference to the currently selected input
+    cur_input: &'static CfInput,
+}
+
+// Initialize our plugin
+fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+
+    // Fields are separated by ';'
+    let mut fields = Vec::new();
+    if let Some(v) = plugin_conf.get("checksum_fields") {
+        for decl in v.split(';').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            match ChecksumField::parse(decl) {
+                Ok(f) => fields.push(f),
+                Err(e) => {
+                    core.error(&format!("Invalid checksum field '{}' : {}", decl, e));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+    }
+    if fields.is_empty() {
+        core.error("No fields declared in 'checksum_fields' plugin_conf");
+        return Err(From::from("Invalid config".to_string()));
+    }
+    core.info(&format!("Fixing up {} checksum fields", fields.len()));
+
+    #[allow(invalid_value)]
+    let mut s = Box::new(unsafe {
+        State {
+            fields,
+            num_fixups: core.new_stat_num(&format!("{}fixups", TAG_PREFIX_TOTAL), 0)?,
+            exec_input: MaybeUninit::zeroed().assume_init(),
+            owned_exec_input: CfInput::new(),
+            is_exec_input_owner: false,
+            // core store values
+            no_mutate: store.as_mutref(STORE_NO_MUTATE, Some(core))?,
+            // Plugin store values
+            cur_input: MaybeUninit::zeroed().assume_init(),
+        }
+    });
+
+    // The mutators must keep their own copy of the input intact
+    unsafe {
+        let (val, is_owned) =
+            store.as_mutref_or_insert(STORE_EXEC_BYTES, &mut s.owned_exec_input, Some(core))?;
+        s.exec_input = val;
+        s.is_exec_input_owner = is_owned;
+    }
+
+    Ok(Box::into_raw(s) as _)
+}
+
+// Make sure we have everything to fuzz properly
+fn validate(
+    core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    // We need a plugin that creates in input_bytes
+    unsafe {
+        s.cur_input = store.as_ref(STORE_INPUT_BYTES, Some(core))?;
+    }
+
+    Ok(())
+}
+
+// Perform our task in the fuzzing loop
+fn fixup_input(
+    _core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    // The first plugin transforming the input starts from the mutated bytes
+    if s.is_exec_input_owner {
+        s.exec_input.clear();
+        s.exec_input.extend_from_slice(s.cur_input);
+    }
+
+    // Leave inputs that were not mutated as is
+    if *s.no_mutate {
+        return Ok(());
+    }
+
+    let mut changed = false;
+    for field in s.fields.iter() {
+        changed |= field.apply(s.exec_input);
+    }
+    if changed {
+        *s.num_fixups.val += 1;
+    }
+
+    Ok(())
+}
+
+// Unload and free our resources
+fn destroy(
+    _core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_take!(plugin_ctx, State);
+
+    // If we created the exec_bytes
+    if s.is_exec_input_owner {
+        let _ = store.remove(STORE_EXEC_BYTES);
+    }
+
+    Ok(())
+}
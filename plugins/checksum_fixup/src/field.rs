@@ -0,0 +1,376 @@
+use std::convert::TryFrom;
+
+/// Algorithms used to compute a checksum field
+#[derive(Debug, Copy, Clone)]
+pub enum Algorithm {
+    Crc32,
+    Crc32c,
+    Crc16,
+    Adler32,
+    /// Sum of every byte
+    Sum,
+    /// Xor of every byte
+    Xor,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "crc32" => Self::Crc32,
+            "crc32c" => Self::Crc32c,
+            "crc16" => Self::Crc16,
+            "adler32" => Self::Adler32,
+            "sum" => Self::Sum,
+            "xor" => Self::Xor,
+            _ => return Err(format!("Unknown algorithm '{}'", name)),
+        })
+    }
+
+    /// Field width used when none is provided
+    pub fn default_width(self) -> usize {
+        match self {
+            Self::Crc32 | Self::Crc32c | Self::Adler32 => 4,
+            Self::Crc16 => 2,
+            Self::Sum | Self::Xor => 1,
+        }
+    }
+
+    pub fn compute(self, data: &[u8]) -> u64 {
+        match self {
+            Self::Crc32 => crc::crc32::checksum_ieee(data) as u64,
+            Self::Crc32c => crc::crc32::checksum_castagnoli(data) as u64,
+            Self::Crc16 => crc::crc16::checksum_x25(data) as u64,
+            Self::Adler32 => {
+                let (mut a, mut b) = (1u32, 0u32);
+                for chunk in data.chunks(5552) {
+                    for v in chunk {
+                        a += *v as u32;
+                        b += a;
+                    }
+                    a %= 65521;
+                    b %= 65521;
+                }
+                ((b << 16) | a) as u64
+            }
+            Self::Sum => data.iter().fold(0u64, |s, v| s.wrapping_add(*v as u64)),
+            Self::Xor => data.iter().fold(0u64, |s, v| s ^ *v as u64),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Num(usize),
+    /// Length of the input
+    End,
+    /// Integer read from the input (offset, width, big endian)
+    Int(usize, usize, bool),
+}
+
+/// Position in the input made of terms added or subtracted together :
+///   - Numbers (decimal or 0x hex)
+///   - `end` : the length of the input
+///   - `u8@OFF`, `u16le@OFF`, `u32be@OFF`... : an integer read from the input
+///
+/// Positions starting with '-' are relative to the end of the input.
+#[derive(Debug, Clone)]
+pub struct Position {
+    terms: Vec<(bool, Term)>,
+}
+
+impl Position {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let mut terms = Vec::new();
+        if expr.starts_with('-') {
+            terms.push((false, Term::End));
+        }
+
+        let mut negative = false;
+        let mut first = true;
+        let mut cur = String::new();
+        for c in expr.chars().chain(std::iter::once('+')) {
+            if c != '+' && c != '-' {
+                cur.push(c);
+                continue;
+            }
+            let term = cur.trim();
+            if !term.is_empty() {
+                terms.push((negative, parse_term(term)?));
+            } else if !first {
+                return Err(format!("Missing term in '{}'", expr));
+            }
+            negative = c == '-';
+            first = false;
+            cur.clear();
+        }
+
+        if terms.is_empty() {
+            return Err("Empty position".to_string());
+        }
+        Ok(Self { terms })
+    }
+
+    /// Computes the position for this input. None when it falls out of bounds.
+    pub fn resolve(&self, input: &[u8]) -> Option<usize> {
+        let mut pos: i64 = 0;
+        for (negative, term) in self.terms.iter() {
+            let val = match term {
+                Term::Num(n) => *n as u64,
+                Term::End => input.len() as u64,
+                Term::Int(offset, width, big_endian) => {
+                    read_int(input, *offset, *width, *big_endian)?
+                }
+            };
+            let val = i64::try_from(val).ok()?;
+            pos = if *negative {
+                pos.checked_sub(val)?
+            } else {
+                pos.checked_add(val)?
+            };
+        }
+        if pos < 0 || pos as usize > input.len() {
+            return None;
+        }
+        Some(pos as usize)
+    }
+}
+
+fn parse_num(s: &str) -> Result<usize, String> {
+    let res = if let Some(hex) = s.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else {
+        s.parse::<usize>()
+    };
+    res.map_err(|e| format!("Invalid number '{}' : {}", s, e))
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    if term == "end" {
+        return Ok(Term::End);
+    }
+    let (kind, offset) = match term.split_once('@') {
+        Some(v) => v,
+        None => return Ok(Term::Num(parse_num(term)?)),
+    };
+    let (width, big_endian) = match kind {
+        "u8" => (1, false),
+        "u16le" => (2, false),
+        "u16be" => (2, true),
+        "u32le" => (4, false),
+        "u32be" => (4, true),
+        "u64le" => (8, false),
+        "u64be" => (8, true),
+        _ => return Err(format!("Unknown integer type '{}'", kind)),
+    };
+    Ok(Term::Int(parse_num(offset)?, width, big_endian))
+}
+
+fn read_int(input: &[u8], offset: usize, width: usize, big_endian: bool) -> Option<u64> {
+    let bytes = input.get(offset..offset.checked_add(width)?)?;
+    let mut val = 0u64;
+    for i in 0..width {
+        let b = if big_endian { bytes[i] } else { bytes[width - 1 - i] };
+        val = (val << 8) | b as u64;
+    }
+    Some(val)
+}
+
+fn write_int(dst: &mut [u8], mut val: u64, big_endian: bool) {
+    let width = dst.len();
+    for i in 0..width {
+        let idx = if big_endian { width - 1 - i } else { i };
+        dst[idx] = val as u8;
+        val >>= 8;
+    }
+}
+
+/// A checksum stored in the input
+#[derive(Debug, Clone)]
+pub struct ChecksumField {
+    pub algo: Algorithm,
+    /// Where the checksum is stored
+    pub offset: Position,
+    pub width: usize,
+    pub big_endian: bool,
+    /// Bytes covered by the checksum [start, end[
+    pub start: Position,
+    pub end: Position,
+}
+
+impl ChecksumField {
+    /// Parses a field declared as comma separated key=value pairs :
+    ///   algo=crc32,offset=-4,width=4,endian=be,start=0,end=-4
+    pub fn parse(decl: &str) -> Result<Self, String> {
+        let mut algo = None;
+        let mut offset = None;
+        let mut width = None;
+        let mut big_endian = false;
+        let mut start = Position::parse("0")?;
+        let mut end = Position::parse("end")?;
+
+        for pair in decl.split(',') {
+            let (key, val) = match pair.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => return Err(format!("Expected key=value but got '{}'", pair.trim())),
+            };
+            match key {
+                "algo" => algo = Some(Algorithm::parse(val)?),
+                "offset" => offset = Some(Position::parse(val)?),
+                "width" => match parse_num(val)? {
+                    w @ 1..=8 => width = Some(w),
+                    w => return Err(format!("Width must be between 1 and 8 : {}", w)),
+                },
+                "endian" => {
+                    big_endian = match val {
+                        "le" => false,
+                        "be" => true,
+                        _ => return Err(format!("Endian must be 'le' or 'be' : '{}'", val)),
+                    }
+                }
+                "start" => start = Position::parse(val)?,
+                "end" => end = Position::parse(val)?,
+                _ => return Err(format!("Unknown key '{}'", key)),
+            };
+        }
+
+        let algo = algo.ok_or_else(|| "Missing 'algo'".to_string())?;
+        Ok(Self {
+            algo,
+            offset: offset.ok_or_else(|| "Missing 'offset'".to_string())?,
+            width: width.unwrap_or_else(|| algo.default_width()),
+            big_endian,
+            start,
+            end,
+        })
+    }
+
+    /// Recomputes the checksum. Returns whether the input was changed.
+    pub fn apply(&self, input: &mut [u8]) -> bool {
+        let (offset, start, end) = match (
+            self.offset.resolve(input),
+            self.start.resolve(input),
+            self.end.resolve(input),
+        ) {
+            (Some(o), Some(s), Some(e)) if s <= e && o + self.width <= input.len() => (o, s, e),
+            // Input does not contain the field
+            _ => return false,
+        };
+
+        let mut val = self.algo.compute(&input[start..end]);
+        if self.width < 8 {
+            val &= (1 << (self.width * 8)) - 1;
+        }
+        if read_int(input, offset, self.width, self.big_endian) == Some(val) {
+            return false;
+        }
+        write_int(&mut input[offset..offset + self.width], val, self.big_endian);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(expr: &str, input: &[u8]) -> Option<usize> {
+        Position::parse(expr).unwrap().resolve(input)
+    }
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(Algorithm::Crc32.compute(b"123456789"), 0xCBF4_3926);
+        assert_eq!(Algorithm::Crc32c.compute(b"123456789"), 0xE306_9283);
+        assert_eq!(Algorithm::Crc16.compute(b"123456789"), 0x906E);
+        assert_eq!(Algorithm::Adler32.compute(b"Wikipedia"), 0x11E6_0398);
+        // Long enough to need more than one modulo pass
+        assert_eq!(Algorithm::Adler32.compute(&[0xFF; 6000]), 0xA497_59EA);
+        assert_eq!(Algorithm::Sum.compute(&[0xFF, 0x02]), 0x101);
+        assert_eq!(Algorithm::Xor.compute(&[0xF0, 0x0F, 0x01]), 0xFE);
+    }
+
+    #[test]
+    fn end_relative_positions() {
+        let input = [0u8; 10];
+        assert_eq!(pos("-4", &input), Some(6));
+        assert_eq!(pos("end - 4", &input), Some(6));
+        assert_eq!(pos("-0", &input), Some(10));
+        assert_eq!(pos("-11", &input), None);
+        assert_eq!(pos("11", &input), None);
+    }
+
+    #[test]
+    fn hex_numbers() {
+        let input = [0u8; 0x20];
+        assert_eq!(pos("0x10", &input), Some(16));
+        assert_eq!(pos("0x10 + 0x2", &input), Some(18));
+        assert_eq!(pos("-0x8", &input), Some(24));
+        assert!(Position::parse("0xZZ").is_err());
+    }
+
+    #[test]
+    fn integer_terms() {
+        // 16 bit length at offset 1 followed by a header of 3 bytes
+        let input = [0xAA, 0x00, 0x04, 1, 2, 3, 4, 0, 0];
+        assert_eq!(pos("u16be@1 + 3", &input), Some(7));
+        assert_eq!(pos("u8@0x2", &input), Some(4));
+        assert_eq!(pos("u16le@1", &input), None);
+        // Reading past the end of the input
+        assert_eq!(pos("u32le@8", &input), None);
+        assert!(Position::parse("u24le@0").is_err());
+        assert!(Position::parse("1 + + 2").is_err());
+        assert!(Position::parse("").is_err());
+    }
+
+    #[test]
+    fn length_field_ranges() {
+        // u32le length, payload, u32le crc32 of the payload
+        let field =
+            ChecksumField::parse("algo=crc32,offset=u32le@0 + 4,start=4,end=u32le@0 + 4").unwrap();
+        let mut input = vec![9, 0, 0, 0];
+        input.extend_from_slice(b"123456789");
+        input.extend_from_slice(&[0; 4]);
+        input.extend_from_slice(b"trailing");
+        assert!(field.apply(&mut input));
+        assert_eq!(&input[13..17], &0xCBF4_3926u32.to_le_bytes());
+        // Already fixed
+        assert!(!field.apply(&mut input));
+    }
+
+    #[test]
+    fn big_endian_fields() {
+        let field = ChecksumField::parse("algo=adler32,offset=-4,endian=be,end=-4").unwrap();
+        let mut input = b"Wikipedia".to_vec();
+        input.extend_from_slice(&[0; 4]);
+        assert!(field.apply(&mut input));
+        assert_eq!(&input[9..], &[0x11, 0xE6, 0x03, 0x98]);
+    }
+
+    #[test]
+    fn skips_out_of_bounds_fields() {
+        let field = ChecksumField::parse("algo=crc32,offset=8,start=0,end=4").unwrap();
+        let mut input = vec![0; 10];
+        assert!(!field.apply(&mut input));
+        assert_eq!(input, vec![0; 10]);
+
+        // Length field pointing past the end of the input
+        let field = ChecksumField::parse("algo=sum,offset=0,start=1,end=u8@0").unwrap();
+        let mut input = vec![0xFF, 1, 2];
+        assert!(!field.apply(&mut input));
+        assert_eq!(input, vec![0xFF, 1, 2]);
+
+        // Start after end
+        let field = ChecksumField::parse("algo=xor,offset=0,start=3,end=2").unwrap();
+        let mut input = vec![0; 4];
+        assert!(!field.apply(&mut input));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(ChecksumField::parse("offset=0").is_err());
+        assert!(ChecksumField::parse("algo=crc32").is_err());
+        assert!(ChecksumField::parse("algo=md5,offset=0").is_err());
+        assert!(ChecksumField::parse("algo=sum,offset=0,width=9").is_err());
+        assert!(ChecksumField::parse("algo=sum,offset=0,endian=mixed").is_err());
+    }
+}
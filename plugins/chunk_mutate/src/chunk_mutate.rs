@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fs;
+use std::mem::MaybeUninit;
+
+use ::cflib::*;
+use ::rand::rngs::SmallRng;
+use ::rand::{Rng, SeedableRng};
+
+mod layout;
+pub use layout::*;
+mod mutate;
+pub use mutate::*;
+
+cflib::register!(name, env!("CARGO_PKG_NAME"));
+cflib::register!(load, init);
+cflib::register!(pre_fuzz, validate);
+cflib::register!(fuzz, mutate_input);
+cflib::register!(unload, destroy);
+
+const DEFAULT_MAX_MUTATIONS: usize = 4;
+const DEFAULT_MAX_CHUNK_LEN: usize = 64 * 1024;
+const DEFAULT_MAX_INPUT_LEN: usize = 1024 * 1024;
+/// Parsed donors kept around before the cache is emptied
+const MAX_CACHED_DONORS: usize = 256;
+
+struct State {
+    layout: ChunkLayout,
+    mutator: ChunkMutator,
+    /// Maximum number of mutations per iteration
+    max_mutations: usize,
+    /// Inputs are not grown past this length
+    max_input_len: usize,
+    /// Parsed inputs used for splicing, by index in INPUT_LIST
+    donors: HashMap<usize, Option<ChunkedInput>>,
+    /// Rebuilt input
+    tmp: Vec<u8>,
+    /// Number of inputs that could not be parsed
+    num_unparsed: StatNum,
+
+    no_mutate: &'static bool,
+    inputs: &'static Vec<CfInputInfo>,
+    cur_input_idx: &'static usize,
+    /// Reference to the currently selected input
+    cur_input: &'static mut CfInput,
+}
+
+// Initialize our plugin
+fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+
+    let layout = match plugin_conf.get("chunk_format").map(|v| v.as_str()) {
+        Some("png") => ChunkLayout::png(),
+        Some("riff") => ChunkLayout::riff(),
+        Some("tlv") => {
+            let decl = plugin_conf
+                .get("chunk_tlv_layout")
+                .map(|v| v.as_str())
+                .unwrap_or("");
+            match ChunkLayout::tlv(decl) {
+                Ok(l) => l,
+                Err(e) => {
+                    core.error(&format!("Invalid chunk_tlv_layout : {}", e));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+        Some(v) => {
+            core.error(&format!(
+                "chunk_format must be 'png', 'riff' or 'tlv' : '{}'",
+                v
+            ));
+            return Err(From::from("Invalid config".to_string()));
+        }
+        None => {
+            core.error("No format declared in 'chunk_format' plugin_conf");
+            return Err(From::from("Invalid config".to_string()));
+        }
+    };
+    core.info(&format!("Chunk layout : {:?}", layout));
+
+    #[allow(invalid_value)]
+    let mut s = Box::new(unsafe {
+        State {
+            layout,
+            mutator: ChunkMutator {
+                rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+                max_chunk_len: DEFAULT_MAX_CHUNK_LEN,
+            },
+            max_mutations: DEFAULT_MAX_MUTATIONS,
+            max_input_len: DEFAULT_MAX_INPUT_LEN,
+            donors: HashMap::new(),
+            tmp: Vec::new(),
+            num_unparsed: core.new_stat_num(&format!("{}unparsed_inputs", TAG_PREFIX_TOTAL), 0)?,
+            // core store values
+            no_mutate: store.as_mutref(STORE_NO_MUTATE, Some(core))?,
+            // Plugin store values
+            inputs: MaybeUninit::zeroed().assume_init(),
+            cur_input_idx: MaybeUninit::zeroed().assume_init(),
+            cur_input: MaybeUninit::zeroed().assume_init(),
+        }
+    });
+    s.load_conf(core, plugin_conf)?;
+
+    Ok(Box::into_raw(s) as _)
+}
+
+// Make sure we have everything to fuzz properly
+fn validate(
+    core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    // We need a plugin that creates in input_bytes
+    unsafe {
+        s.inputs = store.as_ref(STORE_INPUT_LIST, Some(core))?;
+        s.cur_input_idx = store.as_ref(STORE_INPUT_IDX, Some(core))?;
+        s.cur_input = store.as_mutref(STORE_INPUT_BYTES, Some(core))?;
+    }
+
+    Ok(())
+}
+
+// Perform our task in the fuzzing loop
+fn mutate_input(
+    _core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    if *s.no_mutate {
+        return Ok(());
+    }
+
+    let num_mutations = s.mutator.rng.gen_range(1, s.max_mutations + 1);
+    let mut parsed = match s.layout.parse(s.cur_input) {
+        Some(p) => p,
+        None => {
+            // Fallback to byte level mutations
+            *s.num_unparsed.val += 1;
+            for _ in 0..num_mutations {
+                s.mutator.mutate_bytes(s.cur_input, s.max_input_len);
+            }
+            return Ok(());
+        }
+    };
+
+    let mut donor_idx: Option<Option<usize>> = None;
+    for _ in 0..num_mutations {
+        let op = s.mutator.pick_op();
+        if op != ChunkOp::Splice {
+            s.mutator.apply(op, &mut parsed);
+            continue;
+        }
+        // Only pick another input once per iteration
+        if donor_idx.is_none() {
+            donor_idx = Some(s.pick_donor());
+        }
+        let donor = match donor_idx {
+            Some(Some(idx)) => s.donors.get(&idx),
+            _ => None,
+        };
+        if let Some(Some(d)) = donor {
+            s.mutator.splice(&mut parsed, d);
+        }
+    }
+
+    // Rebuild the input with valid lengths and CRCs
+    s.layout.serialize(&parsed, &mut s.tmp);
+    // Keep the original when it grew too big
+    if s.tmp.len() > s.max_input_len && s.tmp.len() > s.cur_input.len() {
+        return Ok(());
+    }
+    std::mem::swap(s.cur_input, &mut s.tmp);
+
+    Ok(())
+}
+
+// Unload and free our resources
+fn destroy(
+    _core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let _state = box_take!(plugin_ctx, State);
+    Ok(())
+}
+
+impl State {
+    /// Parse the plugin_conf for our values
+    pub fn load_conf(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        plugin_conf: &HashMap<String, String>,
+    ) -> Result<()> {
+        if let Some(v) = plugin_conf.get("chunk_seed") {
+            match v.parse::<u64>() {
+                Ok(seed) => self.mutator.rng = SmallRng::seed_from_u64(seed),
+                Err(_) => {
+                    core.error(&format!("chunk_seed must be a number : '{}'", v));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        for (key, dst) in [
+            ("chunk_max_mutations", &mut self.max_mutations),
+            ("chunk_max_len", &mut self.mutator.max_chunk_len),
+            ("chunk_max_input_len", &mut self.max_input_len),
+        ] {
+            if let Some(v) = plugin_conf.get(key) {
+                match v.parse::<usize>() {
+                    Ok(num) if num > 0 => *dst = num,
+                    _ => {
+                        core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+        }
+
+        // Chunks cant be bigger than what their length field can hold
+        if self.layout.len_width < 8 {
+            let max_len = (1u64 << (self.layout.len_width * 8)) - 1;
+            if (self.mutator.max_chunk_len as u64) > max_len {
+                self.mutator.max_chunk_len = max_len as usize;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Picks another random input of the corpus and makes sure it is parsed
+    fn pick_donor(&mut self) -> Option<usize> {
+        if self.inputs.len() < 2 {
+            return None;
+        }
+        // Skip over the current input
+        let mut idx = self.mutator.rng.gen_range(0, self.inputs.len() - 1);
+        if idx >= *self.cur_input_idx {
+            idx += 1;
+        }
+
+        if !self.donors.contains_key(&idx) {
+            if self.donors.len() >= MAX_CACHED_DONORS {
+                self.donors.clear();
+            }
+            let info = unsafe { self.inputs.get_unchecked(idx) };
+            let donor = match (&info.contents, &info.path) {
+                (Some(c), _) => self.layout.parse(c),
+                (None, Some(p)) => match fs::read(p) {
+                    Ok(c) => self.layout.parse(&c),
+                    Err(_) => None,
+                },
+                _ => None,
+            };
+            self.donors.insert(idx, donor);
+        }
+        Some(idx)
+    }
+}
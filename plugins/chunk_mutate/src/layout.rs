@@ -0,0 +1,358 @@
+/// Length field that covers the whole file (e.g. the RIFF size)
+#[derive(Debug, Clone)]
+pub struct FileLen {
+    pub offset: usize,
+    pub width: usize,
+    pub big_endian: bool,
+    /// Bytes of the file that are not counted
+    pub excluded: usize,
+}
+
+/// Describes how chunks are laid out in a format
+#[derive(Debug, Clone)]
+pub struct ChunkLayout {
+    /// Bytes before the first chunk (signature, file header...)
+    pub header_len: usize,
+    /// Width of the chunk type
+    pub type_len: usize,
+    /// Width and endianness of the chunk length
+    pub len_width: usize,
+    pub len_big_endian: bool,
+    /// Whether the length comes before the type
+    pub len_first: bool,
+    /// CRC32 after every chunk (true when big endian). It covers everything
+    /// after the length when it comes first, the whole chunk otherwise.
+    pub crc: Option<bool>,
+    /// Chunks are padded to a multiple of this
+    pub align: usize,
+    pub file_len: Option<FileLen>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub kind: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// An input split into chunks
+#[derive(Debug, Default)]
+pub struct ChunkedInput {
+    pub header: Vec<u8>,
+    pub chunks: Vec<Chunk>,
+    /// Bytes after the last valid chunk
+    pub trailer: Vec<u8>,
+}
+
+fn read_int(bytes: &[u8], big_endian: bool) -> u64 {
+    let mut val = 0u64;
+    for i in 0..bytes.len() {
+        let b = if big_endian {
+            bytes[i]
+        } else {
+            bytes[bytes.len() - 1 - i]
+        };
+        val = (val << 8) | b as u64;
+    }
+    val
+}
+
+fn write_int(dst: &mut Vec<u8>, mut val: u64, width: usize, big_endian: bool) {
+    let start = dst.len();
+    for _ in 0..width {
+        dst.push(val as u8);
+        val >>= 8;
+    }
+    if big_endian {
+        dst[start..].reverse();
+    }
+}
+
+impl ChunkLayout {
+    pub fn png() -> Self {
+        Self {
+            header_len: 8,
+            type_len: 4,
+            len_width: 4,
+            len_big_endian: true,
+            len_first: true,
+            crc: Some(true),
+            align: 1,
+            file_len: None,
+        }
+    }
+
+    pub fn riff() -> Self {
+        Self {
+            header_len: 12,
+            type_len: 4,
+            len_width: 4,
+            len_big_endian: false,
+            len_first: false,
+            crc: None,
+            align: 2,
+            file_len: Some(FileLen {
+                offset: 4,
+                width: 4,
+                big_endian: false,
+                excluded: 8,
+            }),
+        }
+    }
+
+    /// Parses a generic TLV layout declared as comma separated key=value pairs :
+    ///   header=0,type=1,len=2be,order=tl,crc=none,align=1
+    pub fn tlv(decl: &str) -> Result<Self, String> {
+        let mut layout = Self {
+            header_len: 0,
+            type_len: 1,
+            len_width: 2,
+            len_big_endian: true,
+            len_first: false,
+            crc: None,
+            align: 1,
+            file_len: None,
+        };
+        let parse_num = |v: &str| {
+            v.parse::<usize>()
+                .map_err(|e| format!("Invalid number '{}' : {}", v, e))
+        };
+
+        for pair in decl.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            let (key, val) = match pair.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => return Err(format!("Expected key=value but got '{}'", pair)),
+            };
+            match key {
+                "header" => layout.header_len = parse_num(val)?,
+                "type" => layout.type_len = parse_num(val)?,
+                "len" => {
+                    let (width, big_endian) = if let Some(w) = val.strip_suffix("be") {
+                        (w, true)
+                    } else if let Some(w) = val.strip_suffix("le") {
+                        (w, false)
+                    } else {
+                        (val, true)
+                    };
+                    layout.len_width = parse_num(width)?;
+                    layout.len_big_endian = big_endian;
+                }
+                "order" => {
+                    layout.len_first = match val {
+                        "tl" => false,
+                        "lt" => true,
+                        _ => return Err(format!("Order must be 'tl' or 'lt' : '{}'", val)),
+                    }
+                }
+                "crc" => {
+                    layout.crc = match val {
+                        "none" => None,
+                        "crc32be" => Some(true),
+                        "crc32le" => Some(false),
+                        _ => {
+                            return Err(format!(
+                                "Crc must be 'none', 'crc32be' or 'crc32le' : '{}'",
+                                val
+                            ))
+                        }
+                    }
+                }
+                "align" => layout.align = parse_num(val)?,
+                _ => return Err(format!("Unknown key '{}'", key)),
+            }
+        }
+
+        if layout.len_width == 0 || layout.len_width > 8 {
+            return Err("Length width must be between 1 and 8".to_string());
+        }
+        if layout.align == 0 {
+            return Err("Alignment must be above 0".to_string());
+        }
+        Ok(layout)
+    }
+
+    fn padding(&self, len: usize) -> usize {
+        (self.align - len % self.align) % self.align
+    }
+
+    /// Splits the input into chunks. Returns None when no chunk could be parsed.
+    pub fn parse(&self, input: &[u8]) -> Option<ChunkedInput> {
+        if input.len() < self.header_len {
+            return None;
+        }
+        let mut res = ChunkedInput {
+            header: input[..self.header_len].to_vec(),
+            ..ChunkedInput::default()
+        };
+
+        let crc_len = if self.crc.is_some() { 4 } else { 0 };
+        let mut pos = self.header_len;
+        loop {
+            let rest = &input[pos..];
+            let hdr_len = self.type_len + self.len_width;
+            if rest.len() < hdr_len {
+                break;
+            }
+            let (kind, len) = if self.len_first {
+                (
+                    &rest[self.len_width..hdr_len],
+                    &rest[..self.len_width],
+                )
+            } else {
+                (&rest[..self.type_len], &rest[self.type_len..hdr_len])
+            };
+            let data_len = read_int(len, self.len_big_endian) as usize;
+            let chunk_len = match hdr_len
+                .checked_add(data_len)
+                .and_then(|l| l.checked_add(crc_len))
+            {
+                Some(l) if l <= rest.len() => l,
+                _ => break,
+            };
+
+            res.chunks.push(Chunk {
+                kind: kind.to_vec(),
+                data: rest[hdr_len..hdr_len + data_len].to_vec(),
+            });
+            // The last chunk might not be padded
+            pos += std::cmp::min(chunk_len + self.padding(data_len), rest.len());
+        }
+        res.trailer.extend_from_slice(&input[pos..]);
+
+        if res.chunks.is_empty() {
+            return None;
+        }
+        Some(res)
+    }
+
+    /// Rebuilds the input, fixing up lengths and CRCs
+    pub fn serialize(&self, input: &ChunkedInput, dst: &mut Vec<u8>) {
+        dst.clear();
+        dst.extend_from_slice(&input.header);
+        for chunk in input.chunks.iter() {
+            let start = dst.len();
+            if self.len_first {
+                write_int(dst, chunk.data.len() as u64, self.len_width, self.len_big_endian);
+            }
+            let type_start = dst.len();
+            dst.extend_from_slice(&chunk.kind);
+            if !self.len_first {
+                write_int(dst, chunk.data.len() as u64, self.len_width, self.len_big_endian);
+            }
+            dst.extend_from_slice(&chunk.data);
+            if let Some(big_endian) = self.crc {
+                // PNG style CRC covers the type and data
+                let crc = if self.len_first {
+                    crc::crc32::checksum_ieee(&dst[type_start..])
+                } else {
+                    crc::crc32::checksum_ieee(&dst[start..])
+                };
+                write_int(dst, crc as u64, 4, big_endian);
+            }
+            let padding = self.padding(chunk.data.len());
+            dst.resize(dst.len() + padding, 0);
+        }
+        dst.extend_from_slice(&input.trailer);
+
+        if let Some(ref f) = self.file_len {
+            if dst.len() >= f.offset + f.width {
+                let mut len = Vec::with_capacity(f.width);
+                write_int(
+                    &mut len,
+                    dst.len().saturating_sub(f.excluded) as u64,
+                    f.width,
+                    f.big_endian,
+                );
+                dst[f.offset..f.offset + f.width].copy_from_slice(&len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut c = (data.len() as u32).to_be_bytes().to_vec();
+        c.extend_from_slice(kind);
+        c.extend_from_slice(data);
+        let crc = crc::crc32::checksum_ieee(&c[4..]);
+        c.extend_from_slice(&crc.to_be_bytes());
+        c
+    }
+
+    #[test]
+    fn png_roundtrip() {
+        let layout = ChunkLayout::png();
+        let mut input = b"\x89PNG\r\n\x1a\n".to_vec();
+        input.extend(png_chunk(b"IHDR", &[1, 2, 3]));
+        input.extend(png_chunk(b"IEND", &[]));
+        input.extend_from_slice(b"junk");
+
+        let parsed = layout.parse(&input).unwrap();
+        assert_eq!(parsed.chunks.len(), 2);
+        assert_eq!(parsed.chunks[0].kind, b"IHDR");
+        assert_eq!(parsed.chunks[0].data, [1, 2, 3]);
+        assert_eq!(parsed.trailer, b"junk");
+
+        let mut out = Vec::new();
+        layout.serialize(&parsed, &mut out);
+        assert_eq!(out, input);
+    }
+
+    #[test]
+    fn riff_fixes_lengths_and_padding() {
+        let layout = ChunkLayout::riff();
+        let mut input = b"RIFF\0\0\0\0WAVE".to_vec();
+        input.extend_from_slice(b"fmt \x03\0\0\0abc\0");
+        input.extend_from_slice(b"data\x02\0\0\0xy");
+
+        let mut parsed = layout.parse(&input).unwrap();
+        assert_eq!(parsed.chunks.len(), 2);
+        assert_eq!(parsed.chunks[0].data, b"abc");
+        assert_eq!(parsed.chunks[1].data, b"xy");
+
+        parsed.chunks[1].data.push(b'z');
+        let mut out = Vec::new();
+        layout.serialize(&parsed, &mut out);
+        assert_eq!(&out[4..8], &((out.len() - 8) as u32).to_le_bytes());
+        assert_eq!(&out[24..33], b"data\x03\0\0\0x");
+        assert_eq!(out.len(), 12 + 12 + 12);
+    }
+
+    #[test]
+    fn truncated_chunks_go_to_the_trailer() {
+        let layout = ChunkLayout::tlv("").unwrap();
+        let input = b"\x01\x00\x02ab\x02\x00\x09cd";
+        let parsed = layout.parse(input).unwrap();
+        assert_eq!(parsed.chunks.len(), 1);
+        assert_eq!(parsed.trailer, b"\x02\x00\x09cd");
+        assert!(layout.parse(b"\x01\x00\x09ab").is_none());
+    }
+
+    #[test]
+    fn parses_tlv_decl() {
+        let l = ChunkLayout::tlv("header=4, type=2,len=4le,order=lt,crc=crc32le,align=4").unwrap();
+        assert_eq!(l.header_len, 4);
+        assert_eq!(l.type_len, 2);
+        assert_eq!(l.len_width, 4);
+        assert!(!l.len_big_endian);
+        assert!(l.len_first);
+        assert_eq!(l.crc, Some(false));
+        assert_eq!(l.align, 4);
+
+        let l = ChunkLayout::tlv("len=1").unwrap();
+        assert_eq!(l.len_width, 1);
+        assert!(l.len_big_endian);
+        assert_eq!(l.crc, None);
+    }
+
+    #[test]
+    fn rejects_invalid_tlv_decl() {
+        for decl in [
+            "len=0", "len=9be", "align=0", "order=x", "crc=md5", "type", "foo=1", "type=-1",
+        ] {
+            assert!(ChunkLayout::tlv(decl).is_err(), "{}", decl);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use ::rand::rngs::SmallRng;
+use ::rand::Rng;
+
+use crate::layout::*;
+
+/// Biggest block inserted or deleted inside a chunk
+const MAX_BLOCK_LEN: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChunkOp {
+    /// Removes a chunk
+    Drop,
+    /// Inserts a copy of a chunk right after it
+    Duplicate,
+    /// Swaps two chunks
+    Reorder,
+    /// Takes a chunk from another input of the corpus
+    Splice,
+    /// Mutates the bytes inside a chunk
+    MutateData,
+}
+pub const ALL_OPS: &[ChunkOp] = &[
+    ChunkOp::Drop,
+    ChunkOp::Duplicate,
+    ChunkOp::Reorder,
+    ChunkOp::Splice,
+    ChunkOp::MutateData,
+];
+
+pub struct ChunkMutator {
+    /// fast/non-crypto grade random
+    pub rng: SmallRng,
+    /// Maximum size of the data in a chunk
+    pub max_chunk_len: usize,
+}
+
+impl ChunkMutator {
+    pub fn pick_op(&mut self) -> ChunkOp {
+        ALL_OPS[self.rng.gen_range(0, ALL_OPS.len())]
+    }
+
+    /// Applies a chunk level mutation. Returns false if it could not be applied.
+    pub fn apply(&mut self, op: ChunkOp, input: &mut ChunkedInput) -> bool {
+        let chunks = &mut input.chunks;
+        match op {
+            ChunkOp::Drop => {
+                // Always keep at least one chunk
+                if chunks.len() < 2 {
+                    return false;
+                }
+                let idx = self.rng.gen_range(0, chunks.len());
+                chunks.remove(idx);
+            }
+            ChunkOp::Duplicate => {
+                let idx = self.rng.gen_range(0, chunks.len());
+                let chunk = chunks[idx].clone();
+                chunks.insert(idx + 1, chunk);
+            }
+            ChunkOp::Reorder => {
+                if chunks.len() < 2 {
+                    return false;
+                }
+                let a = self.rng.gen_range(0, chunks.len());
+                let mut b = self.rng.gen_range(0, chunks.len() - 1);
+                if b >= a {
+                    b += 1;
+                }
+                chunks.swap(a, b);
+            }
+            ChunkOp::MutateData => {
+                let idx = self.rng.gen_range(0, chunks.len());
+                let max_len = self.max_chunk_len;
+                self.mutate_bytes(&mut chunks[idx].data, max_len);
+            }
+            // Needs another input
+            ChunkOp::Splice => return false,
+        }
+        true
+    }
+
+    /// Replaces a chunk of the same type with one from the donor, or inserts it
+    pub fn splice(&mut self, input: &mut ChunkedInput, donor: &ChunkedInput) -> bool {
+        if donor.chunks.is_empty() {
+            return false;
+        }
+        let chunk = &donor.chunks[self.rng.gen_range(0, donor.chunks.len())];
+        let same_kind: Vec<usize> = input
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.kind == chunk.kind)
+            .map(|(i, _)| i)
+            .collect();
+
+        if !same_kind.is_empty() && self.rng.gen() {
+            let idx = same_kind[self.rng.gen_range(0, same_kind.len())];
+            input.chunks[idx] = chunk.clone();
+        } else {
+            let idx = self.rng.gen_range(0, input.chunks.len() + 1);
+            input.chunks.insert(idx, chunk.clone());
+        }
+        true
+    }
+
+    /// Byte level mutation, used inside chunks and for inputs that dont parse
+    pub fn mutate_bytes(&mut self, data: &mut Vec<u8>, max_len: usize) {
+        let choice = if data.is_empty() {
+            2
+        } else {
+            self.rng.gen_range(0, 4)
+        };
+        match choice {
+            // Bit flip
+            0 => {
+                let idx = self.rng.gen_range(0, data.len());
+                data[idx] ^= 1 << self.rng.gen_range(0, 8);
+            }
+            // Random byte
+            1 => {
+                let idx = self.rng.gen_range(0, data.len());
+                data[idx] ^= self.rng.gen_range(1, 256) as u8;
+            }
+            // Insert random bytes
+            2 => {
+                if data.len() >= max_len {
+                    return;
+                }
+                let len = self
+                    .rng
+                    .gen_range(1, std::cmp::min(MAX_BLOCK_LEN, max_len - data.len()) + 1);
+                let idx = self.rng.gen_range(0, data.len() + 1);
+                let block: Vec<u8> = (0..len).map(|_| self.rng.gen()).collect();
+                data.splice(idx..idx, block);
+            }
+            // Delete a block
+            _ => {
+                let len = self
+                    .rng
+                    .gen_range(1, std::cmp::min(MAX_BLOCK_LEN, data.len()) + 1);
+                let idx = self.rng.gen_range(0, data.len() - len + 1);
+                data.drain(idx..idx + len);
+            }
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::mem::MaybeUninit;
+use std::path::Path;
+
+use ::cflib::*;
+use ::rand::rngs::SmallRng;
+use ::rand::{Rng, SeedableRng};
+
+mod ffi;
+pub use ffi::*;
+
+cflib::register!(name, env!("CARGO_PKG_NAME"));
+cflib::register!(load, init);
+cflib::register!(pre_fuzz, validate);
+cflib::register!(fuzz, mutate_input);
+cflib::register!(unload, destroy);
+
+/// Iterations per input when the library has no afl_custom_fuzz_count
+const DEFAULT_FUZZ_COUNT: u32 = 256;
+/// AFL++'s default for afl_custom_havoc_mutation_probability
+const DEFAULT_HAVOC_PROBABILITY: u8 = 6;
+const DEFAULT_MAX_SIZE: usize = 1024 * 1024;
+
+struct State {
+    mutator: CustomMutator,
+    /// fast/non-crypto grade random
+    rng: SmallRng,
+    max_size: usize,
+    /// Chance (in %) to use afl_custom_havoc_mutation over afl_custom_fuzz
+    havoc_probability: u8,
+    /// Mutations left for the current input
+    iterations_left: u32,
+    prev_input_idx: usize,
+    /// Another input given to afl_custom_fuzz for splicing
+    add_buf: Vec<u8>,
+    /// Number of inputs we already told the library about
+    num_known_inputs: usize,
+    /// Number of times the library produced an input
+    num_mutations: StatNum,
+    /// Post processed input given to the target
+    exec_input: &'static mut CfInput,
+    owned_exec_input: CfInput,
+    is_exec_input_owner: bool,
+    /// Set when post_process rejects the input
+    skip_exec: &'static mut bool,
+    owned_skip_exec: bool,
+    is_skip_exec_owner: bool,
+
+    restore_input: &'static mut bool,
+    no_select: &'static mut bool,
+    no_mutate: &'static bool,
+    inputs: &'static Vec<CfInputInfo>,
+    cur_input_idx: &'static usize,
+    cur_input: &'static mut CfInput,
+}
+
+// Initialize our plugin
+fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+
+    let lib_path = match plugin_conf.get("custom_mutator_library") {
+        Some(v) => v,
+        None => {
+            core.error("Missing 'custom_mutator_library' in plugin_conf");
+            return Err(From::from("Invalid config".to_string()));
+        }
+    };
+
+    let mut rng = SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap();
+    let seed = match plugin_conf.get("custom_mutator_seed") {
+        Some(v) => match v.parse::<u32>() {
+            Ok(s) => s,
+            Err(_) => {
+                core.error(&format!("custom_mutator_seed must be a number : '{}'", v));
+                return Err(From::from("Invalid config".to_string()));
+            }
+        },
+        None => rng.gen(),
+    };
+
+    let max_size = match plugin_conf.get("custom_mutator_max_size") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(s) if s > 0 => s,
+            _ => {
+                core.error(&format!(
+                    "custom_mutator_max_size must be a number above 0 : '{}'",
+                    v
+                ));
+                return Err(From::from("Invalid config".to_string()));
+            }
+        },
+        None => DEFAULT_MAX_SIZE,
+    };
+
+    let mutator = match CustomMutator::load(Path::new(lib_path), seed) {
+        Ok(m) => m,
+        Err(e) => {
+            core.error(&format!(
+                "Failed to load custom mutator '{}' : {}",
+                lib_path, e
+            ));
+            return Err(From::from("Invalid config".to_string()));
+        }
+    };
+    let havoc_probability = match mutator.havoc_probability {
+        Some(f) => std::cmp::min(unsafe { f(mutator.data) }, 100),
+        None => DEFAULT_HAVOC_PROBABILITY,
+    };
+
+    #[allow(invalid_value)]
+    let mut s = Box::new(unsafe {
+        State {
+            mutator,
+            rng,
+            max_size,
+            havoc_probability,
+            iterations_left: 0,
+            prev_input_idx: usize::MAX,
+            add_buf: Vec::new(),
+            num_known_inputs: 0,
+            num_mutations: core.new_stat_num(&format!("{}custom_mutations", TAG_PREFIX_TOTAL), 0)?,
+            exec_input: MaybeUninit::zeroed().assume_init(),
+            owned_exec_input: CfInput::new(),
+            is_exec_input_owner: false,
+            skip_exec: MaybeUninit::zeroed().assume_init(),
+            owned_skip_exec: false,
+            is_skip_exec_owner: false,
+            // Core store values
+            restore_input: store.as_mutref(STORE_RESTORE_INPUT, Some(core))?,
+            no_select: store.as_mutref(STORE_NO_SELECT, Some(core))?,
+            no_mutate: store.as_mutref(STORE_NO_MUTATE, Some(core))?,
+            // Plugin store values
+            inputs: MaybeUninit::zeroed().assume_init(),
+            cur_input_idx: MaybeUninit::zeroed().assume_init(),
+            cur_input: MaybeUninit::zeroed().assume_init(),
+        }
+    });
+
+    // The post processed input is only used to run the target
+    if s.mutator.post_process.is_some() {
+        unsafe {
+            let (val, is_owned) =
+                store.as_mutref_or_insert(STORE_EXEC_BYTES, &mut s.owned_exec_input, Some(core))?;
+            s.exec_input = val;
+            s.is_exec_input_owner = is_owned;
+
+            let (val, is_owned) =
+                store.as_mutref_or_insert(STORE_SKIP_EXEC, &mut s.owned_skip_exec, Some(core))?;
+            s.skip_exec = val;
+            s.is_skip_exec_owner = is_owned;
+        }
+    }
+
+    Ok(Box::into_raw(s) as _)
+}
+
+// Make sure we have everything to fuzz properly
+fn validate(
+    core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    unsafe {
+        s.inputs = store.as_ref(STORE_INPUT_LIST, Some(core))?;
+        s.cur_input_idx = store.as_ref(STORE_INPUT_IDX, Some(core))?;
+        s.cur_input = store.as_mutref(STORE_INPUT_BYTES, Some(core))?;
+    }
+    // Inputs that exist before fuzzing are not new queue entries
+    s.num_known_inputs = s.inputs.len();
+
+    Ok(())
+}
+
+// Perform our task in the fuzzing loop
+fn mutate_input(
+    _core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    s.notify_new_inputs();
+
+    if !*s.no_mutate && s.mutator.can_mutate() {
+        s.mutate();
+    }
+
+    // Fix up a copy of the input right before it is executed
+    if let Some(post_process) = s.mutator.post_process {
+        if s.is_skip_exec_owner {
+            *s.skip_exec = false;
+        }
+        // The first plugin transforming the input starts from the mutated bytes
+        if s.is_exec_input_owner {
+            s.exec_input.clear();
+            s.exec_input.extend_from_slice(s.cur_input);
+        }
+        let mut out_buf: *mut u8 = std::ptr::null_mut();
+        let out_len = unsafe {
+            post_process(
+                s.mutator.data,
+                s.exec_input.as_mut_ptr(),
+                s.exec_input.len(),
+                &mut out_buf,
+            )
+        };
+        s.set_exec_input(out_buf, out_len);
+    }
+
+    Ok(())
+}
+
+// Unload and free our resources
+fn destroy(
+    _core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    // Dropping the mutator calls afl_custom_deinit
+    let state = box_take!(plugin_ctx, State);
+    // If we created the exec_bytes
+    if state.is_exec_input_owner {
+        let _ = store.remove(STORE_EXEC_BYTES);
+    }
+    if state.is_skip_exec_owner {
+        let _ = store.remove(STORE_SKIP_EXEC);
+    }
+    Ok(())
+}
+
+impl State {
+    /// Replaces the input with a buffer returned by the library
+    fn set_input(&mut self, out_buf: *mut u8, out_len: usize) {
+        if out_buf.is_null() || out_len == 0 {
+            return;
+        }
+        let out = unsafe { std::slice::from_raw_parts(out_buf, out_len) };
+        // The library might have mutated in place
+        if out.as_ptr() != self.cur_input.as_ptr() {
+            self.cur_input.clear();
+            self.cur_input.extend_from_slice(out);
+        } else {
+            self.cur_input.truncate(out_len);
+        }
+    }
+
+    /// Replaces the executed input with a buffer returned by post_process
+    fn set_exec_input(&mut self, out_buf: *mut u8, out_len: usize) {
+        // The library does not want this input to run
+        if out_len == 0 {
+            *self.skip_exec = true;
+            return;
+        }
+        if out_buf.is_null() {
+            return;
+        }
+        let out = unsafe { std::slice::from_raw_parts(out_buf, out_len) };
+        // The library might have processed in place
+        if out.as_ptr() != self.exec_input.as_ptr() {
+            self.exec_input.clear();
+            self.exec_input.extend_from_slice(out);
+        } else {
+            self.exec_input.truncate(out_len);
+        }
+    }
+
+    /// Tells the library about inputs that were added to the input list
+    fn notify_new_inputs(&mut self) {
+        let queue_new_entry = match self.mutator.queue_new_entry {
+            Some(f) => f,
+            None => {
+                self.num_known_inputs = self.inputs.len();
+                return;
+            }
+        };
+
+        let orig = self
+            .inputs
+            .get(self.prev_input_idx)
+            .and_then(|i| i.path.as_ref())
+            .and_then(|p| CString::new(p.to_string_lossy().as_bytes()).ok());
+        while self.num_known_inputs < self.inputs.len() {
+            let info = unsafe { self.inputs.get_unchecked(self.num_known_inputs) };
+            self.num_known_inputs += 1;
+
+            let path = match info.path.as_ref() {
+                Some(p) => CString::new(p.to_string_lossy().as_bytes()).unwrap_or_default(),
+                None => continue,
+            };
+            unsafe {
+                queue_new_entry(
+                    self.mutator.data,
+                    path.as_ptr() as _,
+                    orig.as_ref().map_or(std::ptr::null(), |o| o.as_ptr() as _),
+                )
+            };
+        }
+    }
+
+    /// Called when we start fuzzing a new input
+    fn start_input(&mut self) {
+        self.iterations_left = 0;
+        let info = unsafe { self.inputs.get_unchecked(*self.cur_input_idx) };
+
+        // The library can ask to skip inputs
+        if let (Some(queue_get), Some(p)) = (self.mutator.queue_get, &info.path) {
+            let path = CString::new(p.to_string_lossy().as_bytes()).unwrap_or_default();
+            if unsafe { queue_get(self.mutator.data, path.as_ptr() as _) } == 0 {
+                return;
+            }
+        }
+
+        self.iterations_left = match self.mutator.fuzz_count {
+            Some(f) => unsafe { f(self.mutator.data, self.cur_input.as_ptr(), self.cur_input.len()) },
+            None => DEFAULT_FUZZ_COUNT,
+        };
+
+        // Pick another input to splice with
+        self.add_buf.clear();
+        if !self.mutator.splice_optout && self.inputs.len() > 1 {
+            let other = &self.inputs[self.rng.gen_range(0, self.inputs.len())];
+            match (&other.contents, &other.path) {
+                (Some(c), _) => self.add_buf.extend_from_slice(c),
+                (None, Some(p)) => {
+                    if let Ok(c) = fs::read(p) {
+                        self.add_buf = c;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Mutates the current input with the library
+    fn mutate(&mut self) {
+        if self.prev_input_idx != *self.cur_input_idx || self.iterations_left == 0 {
+            self.prev_input_idx = *self.cur_input_idx;
+            self.start_input();
+        }
+
+        if self.iterations_left == 0 {
+            // Move on to the next input
+            *self.restore_input = false;
+            *self.no_select = false;
+            self.prev_input_idx = usize::MAX;
+            return;
+        }
+        self.iterations_left -= 1;
+
+        let use_havoc = match (self.mutator.fuzz, self.mutator.havoc_mutation) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(_), Some(_)) => self.rng.gen_range(0, 100) < self.havoc_probability,
+        };
+
+        let mut out_buf: *mut u8 = std::ptr::null_mut();
+        let out_len = unsafe {
+            if use_havoc {
+                self.mutator.havoc_mutation.unwrap()(
+                    self.mutator.data,
+                    self.cur_input.as_mut_ptr(),
+                    self.cur_input.len(),
+                    &mut out_buf,
+                    self.max_size,
+                )
+            } else {
+                let (add_buf, add_len) = if self.add_buf.is_empty() {
+                    (std::ptr::null_mut(), 0)
+                } else {
+                    (self.add_buf.as_mut_ptr(), self.add_buf.len())
+                };
+                self.mutator.fuzz.unwrap()(
+                    self.mutator.data,
+                    self.cur_input.as_mut_ptr(),
+                    self.cur_input.len(),
+                    &mut out_buf,
+                    add_buf,
+                    add_len,
+                    self.max_size,
+                )
+            }
+        };
+        if !out_buf.is_null() && out_len != 0 {
+            *self.num_mutations.val += 1;
+        }
+        self.set_input(out_buf, std::cmp::min(out_len, self.max_size));
+
+        // select_input gives us back the original contents for the next mutation
+        *self.restore_input = true;
+        *self.no_select = false;
+    }
+}
@@ -0,0 +1,140 @@
+use std::ffi::c_void;
+use std::path::Path;
+
+use ::cflib::*;
+
+/* Hooks of the AFL++ custom mutator API. The afl_state_t given to init is zeroed memory. */
+pub type InitFn = unsafe extern "C" fn(afl: *mut c_void, seed: u32) -> *mut c_void;
+pub type DeinitFn = unsafe extern "C" fn(data: *mut c_void);
+pub type FuzzCountFn = unsafe extern "C" fn(data: *mut c_void, buf: *const u8, buf_size: usize) -> u32;
+pub type FuzzFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+    add_buf: *mut u8,
+    add_buf_size: usize,
+    max_size: usize,
+) -> usize;
+pub type HavocMutationFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+    max_size: usize,
+) -> usize;
+pub type HavocProbabilityFn = unsafe extern "C" fn(data: *mut c_void) -> u8;
+pub type PostProcessFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+) -> usize;
+pub type QueueGetFn = unsafe extern "C" fn(data: *mut c_void, filename: *const u8) -> u8;
+pub type QueueNewEntryFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    filename_new_queue: *const u8,
+    filename_orig_queue: *const u8,
+) -> u8;
+pub type SpliceOptoutFn = unsafe extern "C" fn(data: *mut c_void);
+
+/// Bigger than AFL++'s afl_state_t, only its pointers live outside of it
+const AFL_STATE_SIZE: usize = 1024 * 1024;
+
+/// A loaded custom mutator library and its hooks
+pub struct CustomMutator {
+    /// Value returned by afl_custom_init
+    pub data: *mut c_void,
+    pub deinit: DeinitFn,
+    pub fuzz_count: Option<FuzzCountFn>,
+    pub fuzz: Option<FuzzFn>,
+    pub havoc_mutation: Option<HavocMutationFn>,
+    pub havoc_probability: Option<HavocProbabilityFn>,
+    pub post_process: Option<PostProcessFn>,
+    pub queue_get: Option<QueueGetFn>,
+    pub queue_new_entry: Option<QueueNewEntryFn>,
+    /// Whether the mutator asked for add_buf to never be provided
+    pub splice_optout: bool,
+    /// Stand-in for afl_state_t, mutators that follow its pointers are not supported
+    _afl_state: Vec<u64>,
+    // Keeps the hooks valid
+    _lib: libloading::Library,
+}
+
+/// Gets an optional hook from the library
+macro_rules! get_hook {
+    ($lib:ident, $name:expr, $typ:ty) => {
+        unsafe { $lib.get::<$typ>($name) }.ok().map(|s| *s)
+    };
+}
+
+impl CustomMutator {
+    pub fn load(path: &Path, seed: u32) -> Result<Self> {
+        let lib = match libloading::Library::new(path) {
+            Ok(l) => l,
+            Err(e) => return Err(From::from(format!("{}", e))),
+        };
+
+        let init = match get_hook!(lib, b"afl_custom_init\0", InitFn) {
+            Some(f) => f,
+            None => return Err(From::from("Missing afl_custom_init".to_string())),
+        };
+        let deinit = match get_hook!(lib, b"afl_custom_deinit\0", DeinitFn) {
+            Some(f) => f,
+            None => return Err(From::from("Missing afl_custom_deinit".to_string())),
+        };
+
+        let mut r = Self {
+            data: std::ptr::null_mut(),
+            deinit,
+            fuzz_count: get_hook!(lib, b"afl_custom_fuzz_count\0", FuzzCountFn),
+            fuzz: get_hook!(lib, b"afl_custom_fuzz\0", FuzzFn),
+            havoc_mutation: get_hook!(lib, b"afl_custom_havoc_mutation\0", HavocMutationFn),
+            havoc_probability: get_hook!(
+                lib,
+                b"afl_custom_havoc_mutation_probability\0",
+                HavocProbabilityFn
+            ),
+            post_process: get_hook!(lib, b"afl_custom_post_process\0", PostProcessFn),
+            queue_get: get_hook!(lib, b"afl_custom_queue_get\0", QueueGetFn),
+            queue_new_entry: get_hook!(lib, b"afl_custom_queue_new_entry\0", QueueNewEntryFn),
+            splice_optout: false,
+            _afl_state: vec![0; AFL_STATE_SIZE / std::mem::size_of::<u64>()],
+            _lib: lib,
+        };
+
+        if r.fuzz.is_none() && r.havoc_mutation.is_none() && r.post_process.is_none() {
+            return Err(From::from(
+                "Library has no fuzz, havoc_mutation or post_process hook".to_string(),
+            ));
+        }
+
+        r.data = unsafe { init(r._afl_state.as_mut_ptr() as _, seed) };
+        if r.data.is_null() {
+            return Err(From::from("afl_custom_init failed".to_string()));
+        }
+
+        // The opt-out has to be called after init
+        let lib = &r._lib;
+        if let Some(f) = get_hook!(lib, b"afl_custom_splice_optout\0", SpliceOptoutFn) {
+            unsafe { f(r.data) };
+            r.splice_optout = true;
+        }
+
+        Ok(r)
+    }
+
+    /// Whether the library can mutate inputs (and not just post-process them)
+    pub fn can_mutate(&self) -> bool {
+        self.fuzz.is_some() || self.havoc_mutation.is_some()
+    }
+}
+
+impl Drop for CustomMutator {
+    fn drop(&mut self) {
+        // Nothing to free when init was not called or failed
+        if !self.data.is_null() {
+            unsafe { (self.deinit)(self.data) };
+        }
+    }
+}
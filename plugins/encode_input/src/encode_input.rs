@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+
+use ::cflib::*;
+
+mod transform;
+pub use transform::*;
+
+cflib::register!(name, env!("CARGO_PKG_NAME"));
+cflib::register!(load, init);
+cflib::register!(pre_fuzz, validate);
+cflib::register!(fuzz, encode_input);
+cflib::register!(unload, destroy);
+
+struct State {
+    /// Encodings applied in order to the mutated input
+    transforms: Vec<Transform>,
+    /// Bytes given to the target
+    exec_input: &'static mut CfInput,
+    owned_exec_input: CfInput,
+    is_exec_input_owner: bool,
+    tmp: Vec<u8>,
+
+    /// Reference to the currently selected input
+    cur_input: &'static CfInput,
+}
+
+// Initialize our plugin
+fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+
+    // Transforms are separated by ','
+    let mut transforms = Vec::new();
+    if let Some(v) = plugin_conf.get("encode_transforms") {
+        for name in v.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            match Transform::parse(name) {
+                Ok(t) => transforms.push(t),
+                Err(e) => {
+                    core.error(&format!("Invalid encode_transforms : {}", e));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+    }
+    if transforms.is_empty() {
+        core.error("No transforms declared in 'encode_transforms' plugin_conf");
+        return Err(From::from("Invalid config".to_string()));
+    }
+    core.info(&format!("Encoding inputs with {:?}", transforms));
+
+    #[allow(invalid_value)]
+    let mut s = Box::new(unsafe {
+        State {
+            transforms,
+            exec_input: MaybeUninit::zeroed().assume_init(),
+            owned_exec_input: CfInput::new(),
+            is_exec_input_owner: false,
+            tmp: Vec::new(),
+            // Plugin store values
+            cur_input: MaybeUninit::zeroed().assume_init(),
+        }
+    });
+
+    // EXEC_BYTES, shared with the other plugins that transform the input
+    unsafe {
+        let (val, is_owned) =
+            store.as_mutref_or_insert(STORE_EXEC_BYTES, &mut s.owned_exec_input, Some(core))?;
+        s.exec_input = val;
+        s.is_exec_input_owner = is_owned;
+    }
+
+    Ok(Box::into_raw(s) as _)
+}
+
+// Make sure we have everything to fuzz properly
+fn validate(
+    core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    // We need a plugin that creates in input_bytes
+    unsafe {
+        s.cur_input = store.as_ref(STORE_INPUT_BYTES, Some(core))?;
+    }
+
+    Ok(())
+}
+
+// Perform our task in the fuzzing loop
+fn encode_input(
+    core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    // The input is encoded even when it was not mutated
+    if s.is_exec_input_owner {
+        s.exec_input.clear();
+        s.exec_input.extend_from_slice(s.cur_input);
+    }
+    for t in s.transforms.iter() {
+        s.tmp.clear();
+        if let Err(e) = t.encode(s.exec_input, &mut s.tmp) {
+            core.error(&format!("Failed to encode input with {:?} : {}", t, e));
+            return Err(From::from(e));
+        }
+        std::mem::swap(s.exec_input, &mut s.tmp);
+    }
+
+    Ok(())
+}
+
+// Unload and free our resources
+fn destroy(
+    _core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_take!(plugin_ctx, State);
+
+    // If we created the exec_bytes
+    if s.is_exec_input_owner {
+        let _ = store.remove(STORE_EXEC_BYTES);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::io::Write;
+
+use ::flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use ::flate2::Compression;
+
+/// Encodings that can be applied to the input before it is executed
+#[derive(Debug, Copy, Clone)]
+pub enum Transform {
+    Gzip,
+    Zlib,
+    Deflate,
+    Base64,
+    Hex,
+    /// Prefixes the data with its length
+    LenPrefix {
+        width: usize,
+        big_endian: bool,
+    },
+}
+
+impl Transform {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "gzip" => Self::Gzip,
+            "zlib" => Self::Zlib,
+            "deflate" => Self::Deflate,
+            "base64" => Self::Base64,
+            "hex" => Self::Hex,
+            "len8" => Self::LenPrefix {
+                width: 1,
+                big_endian: false,
+            },
+            "len16le" | "len16be" | "len32le" | "len32be" => Self::LenPrefix {
+                width: if name.starts_with("len16") { 2 } else { 4 },
+                big_endian: name.ends_with("be"),
+            },
+            _ => return Err(format!("Unknown transform '{}'", name)),
+        })
+    }
+
+    /// Appends the encoded src to dst
+    pub fn encode(&self, src: &[u8], dst: &mut Vec<u8>) -> std::io::Result<()> {
+        match self {
+            Self::Gzip => {
+                let mut e = GzEncoder::new(dst, Compression::default());
+                e.write_all(src)?;
+                e.finish()?;
+            }
+            Self::Zlib => {
+                let mut e = ZlibEncoder::new(dst, Compression::default());
+                e.write_all(src)?;
+                e.finish()?;
+            }
+            Self::Deflate => {
+                let mut e = DeflateEncoder::new(dst, Compression::default());
+                e.write_all(src)?;
+                e.finish()?;
+            }
+            Self::Base64 => dst.extend_from_slice(::base64::encode(src).as_bytes()),
+            Self::Hex => {
+                const DIGITS: &[u8; 16] = b"0123456789abcdef";
+                dst.reserve(src.len() * 2);
+                for b in src {
+                    dst.push(DIGITS[(b >> 4) as usize]);
+                    dst.push(DIGITS[(b & 0xF) as usize]);
+                }
+            }
+            Self::LenPrefix { width, big_endian } => {
+                // Data is cut to the largest length the prefix can hold
+                let max_len = u64::MAX >> (64 - *width * 8);
+                let src = &src[..std::cmp::min(src.len() as u64, max_len) as usize];
+                let len = (src.len() as u64).to_le_bytes();
+                let len = &len[..*width];
+                if *big_endian {
+                    dst.extend(len.iter().rev());
+                } else {
+                    dst.extend_from_slice(len);
+                }
+                dst.extend_from_slice(src);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use ::flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+
+    const DATA: &[u8] = b"\x00\x01\xFEsome data, some data, some data\xFF";
+
+    fn encode(name: &str, src: &[u8]) -> Vec<u8> {
+        let mut dst = Vec::new();
+        Transform::parse(name)
+            .unwrap()
+            .encode(src, &mut dst)
+            .unwrap();
+        dst
+    }
+
+    fn decompress<R: Read>(mut r: R) -> Vec<u8> {
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        assert_eq!(decompress(GzDecoder::new(&encode("gzip", DATA)[..])), DATA);
+        assert_eq!(
+            decompress(ZlibDecoder::new(&encode("zlib", DATA)[..])),
+            DATA
+        );
+        assert_eq!(
+            decompress(DeflateDecoder::new(&encode("deflate", DATA)[..])),
+            DATA
+        );
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        assert_eq!(encode("base64", b"abcd"), b"YWJjZA==");
+        assert_eq!(::base64::decode(encode("base64", DATA)).unwrap(), DATA);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(encode("hex", &[0x00, 0xAB, 0x1F]), b"00ab1f");
+        let hex = encode("hex", DATA);
+        let decoded: Vec<u8> = hex
+            .chunks(2)
+            .map(|c| u8::from_str_radix(std::str::from_utf8(c).unwrap(), 16).unwrap())
+            .collect();
+        assert_eq!(decoded, DATA);
+    }
+
+    #[test]
+    fn len_prefix_round_trips() {
+        for (name, prefix) in [
+            ("len8", &[3][..]),
+            ("len16le", &[3, 0][..]),
+            ("len16be", &[0, 3][..]),
+            ("len32le", &[3, 0, 0, 0][..]),
+            ("len32be", &[0, 0, 0, 3][..]),
+        ]
+        .iter()
+        {
+            let out = encode(name, b"abc");
+            assert_eq!(&out[..prefix.len()], *prefix);
+            assert_eq!(&out[prefix.len()..], b"abc");
+        }
+    }
+
+    #[test]
+    fn len_prefix_caps_long_data() {
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let out = encode("len8", &data);
+        assert_eq!(out[0], 255);
+        assert_eq!(&out[1..], &data[..255]);
+
+        let out = encode("len16be", &data);
+        assert_eq!(&out[..2], &[0x01, 0x2C]);
+        assert_eq!(&out[2..], &data[..]);
+    }
+}
@@ -29,6 +29,10 @@ pub struct State {
     new_inputs: &'static mut Vec<CfNewInput>,
     owned_new_inputs: Vec<CfNewInput>,
     stat_queue_dir: StatStr,
+
+    /// Index of the input that new inputs are derived from
+    input_idx: Option<&'static usize>,
+    input_rewards: Option<&'static mut Vec<u64>>,
 }
 
 // Initialize our plugin
@@ -50,6 +54,8 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
             input_list: MaybeUninit::zeroed().assume_init(),
             new_inputs: MaybeUninit::zeroed().assume_init(),
             stat_queue_dir: MaybeUninit::zeroed().assume_init(),
+            input_idx: None,
+            input_rewards: None,
         }
     });
 
@@ -117,10 +123,18 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
 // Make sure we have everything to fuzz properly
 fn validate(
     _core: &mut dyn PluginInterface,
-    _store: &mut CfStore,
-    _plugin_ctx: *mut u8,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
 ) -> Result<()> {
-    // We dont rely on any other plugin
+    let s = box_ref!(plugin_ctx, State);
+
+    // We dont rely on any other plugin but credit the selected input for
+    // new finds and pass its regions down if someone keeps track of it
+    unsafe {
+        s.input_idx = store.as_ref(STORE_INPUT_IDX, None).ok();
+        s.input_rewards = store.as_mutref(STORE_INPUT_REWARDS, None).ok();
+    }
+
     Ok(())
 }
 
@@ -1,3 +1,4 @@
+use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::prelude::*;
 use std::path::Path;
@@ -42,6 +43,17 @@ fn write_file(dst: &Path, buf: &[u8]) -> bool {
     true
 }
 
+/// Copies the regions sidecar of the parent input next to path
+fn copy_parent_regions(parent: Option<&CfInputInfo>, path: &Path) {
+    let src = match parent.and_then(|info| info.path.as_ref()) {
+        Some(p) => MutableRegions::sidecar_path(p),
+        None => return,
+    };
+    if src.is_file() {
+        let _ = fs::copy(&src, MutableRegions::sidecar_path(path));
+    }
+}
+
 impl State {
     pub fn init(&mut self, core: &dyn PluginInterface, extra_input_folder: &str) {
         // first scan the input directory
@@ -51,9 +63,9 @@ impl State {
                     Ok(i) => i,
                     _ => continue,
                 };
-                // Skip directories
+                // Skip directories and mutable regions sidecars
                 let path = item.path();
-                if path.is_dir() {
+                if path.is_dir() || path.extension() == Some(OsStr::new(REGIONS_EXT)) {
                     continue;
                 }
 
@@ -77,9 +89,9 @@ impl State {
                     Ok(i) => i,
                     _ => continue,
                 };
-                // Skip directories
+                // Skip directories and mutable regions sidecars
                 let path = item.path();
-                if path.is_dir() {
+                if path.is_dir() || path.extension() == Some(OsStr::new(REGIONS_EXT)) {
                     continue;
                 }
 
@@ -164,6 +176,15 @@ impl State {
                 continue;
             }
 
+            // New inputs keep the mutable regions of the input they come from
+            if write_to_queue {
+                let parent = match self.input_idx {
+                    Some(idx) => self.input_list.get(*idx),
+                    None => None,
+                };
+                copy_parent_regions(parent, cur_fpath.as_ref().unwrap());
+            }
+
             // Add file to input_list
             self.input_list.push(CfInputInfo {
                 uid: self.tmp_uid.to_vec(),
@@ -174,6 +195,13 @@ impl State {
 
             *self.num_inputs.val += 1;
             saved_one = true;
+
+            // The currently selected input is the parent of this new input
+            if let (Some(idx), Some(rewards)) = (self.input_idx, self.input_rewards.as_mut()) {
+                if let Some(r) = rewards.get_mut(*idx) {
+                    *r += 1;
+                }
+            }
         }
 
         saved_one
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use ::cflib::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    /// Bytes that are output as is
+    Term(Vec<u8>),
+    /// Index of a rule
+    NonTerm(usize),
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub name: String,
+    /// Every possible expansion of the rule
+    pub alts: Vec<Vec<Symbol>>,
+    /// Minimum tree depth needed to fully expand each alternative
+    pub alt_depth: Vec<usize>,
+}
+
+/// A context-free grammar
+#[derive(Debug)]
+pub struct Grammar {
+    pub rules: Vec<Rule>,
+    /// Rule used as the root of every tree
+    pub start: usize,
+}
+
+/// Raw rules before the nonterminal names are resolved
+type RawRules = Vec<(String, Vec<Vec<RawSymbol>>)>;
+
+enum RawSymbol {
+    Term(Vec<u8>),
+    NonTerm(String),
+}
+
+impl Grammar {
+    /// Loads a grammar from a JSON (.json extension) or BNF file
+    pub fn from_file(path: &Path, start: Option<&str>) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        match path.extension() {
+            Some(e) if e == "json" => Self::from_json(&contents, start),
+            _ => Self::from_bnf(&contents, start),
+        }
+    }
+
+    pub fn from_json(contents: &str, start: Option<&str>) -> Result<Self> {
+        Self::from_raw(parse_json(contents)?, start)
+    }
+
+    pub fn from_bnf(contents: &str, start: Option<&str>) -> Result<Self> {
+        Self::from_raw(parse_bnf(contents)?, start)
+    }
+
+    fn from_raw(raw: RawRules, start: Option<&str>) -> Result<Self> {
+        if raw.is_empty() {
+            return Err(From::from("Grammar has no rules".to_string()));
+        }
+
+        let mut ids = HashMap::new();
+        for (idx, (name, _)) in raw.iter().enumerate() {
+            if ids.insert(name.clone(), idx).is_some() {
+                return Err(From::from(format!("Rule '{}' defined twice", name)));
+            }
+        }
+
+        let mut rules = Vec::with_capacity(raw.len());
+        for (name, raw_alts) in raw.into_iter() {
+            let mut alts = Vec::with_capacity(raw_alts.len());
+            for raw_alt in raw_alts.into_iter() {
+                let mut alt = Vec::with_capacity(raw_alt.len());
+                for sym in raw_alt.into_iter() {
+                    alt.push(match sym {
+                        RawSymbol::Term(v) => Symbol::Term(v),
+                        RawSymbol::NonTerm(n) => match ids.get(&n) {
+                            Some(idx) => Symbol::NonTerm(*idx),
+                            None => {
+                                return Err(From::from(format!(
+                                    "Rule '{}' references undefined rule '{}'",
+                                    name, n
+                                )))
+                            }
+                        },
+                    });
+                }
+                alts.push(alt);
+            }
+            if alts.is_empty() {
+                return Err(From::from(format!("Rule '{}' has no alternatives", name)));
+            }
+            rules.push(Rule {
+                alt_depth: vec![usize::MAX; alts.len()],
+                name,
+                alts,
+            });
+        }
+
+        let start = match start {
+            Some(s) => match ids.get(s) {
+                Some(idx) => *idx,
+                None => return Err(From::from(format!("Unknown start rule '{}'", s))),
+            },
+            None => 0,
+        };
+
+        let mut grammar = Self { rules, start };
+        grammar.compute_depths()?;
+        Ok(grammar)
+    }
+
+    /// Finds the minimum depth of every alternative so generation can
+    /// always terminate
+    fn compute_depths(&mut self) -> Result<()> {
+        let mut rule_depth = vec![usize::MAX; self.rules.len()];
+        loop {
+            let mut changed = false;
+            for r in 0..self.rules.len() {
+                for a in 0..self.rules[r].alts.len() {
+                    let mut depth = 1;
+                    for sym in self.rules[r].alts[a].iter() {
+                        if let Symbol::NonTerm(idx) = sym {
+                            depth = std::cmp::max(depth, rule_depth[*idx].saturating_add(1));
+                        }
+                    }
+                    if depth < self.rules[r].alt_depth[a] {
+                        self.rules[r].alt_depth[a] = depth;
+                        changed = true;
+                    }
+                    rule_depth[r] = std::cmp::min(rule_depth[r], depth);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        match self.rules.iter().find(|r| r.alt_depth.iter().all(|d| *d == usize::MAX)) {
+            Some(r) => Err(From::from(format!("Rule '{}' can never terminate", r.name))),
+            None => Ok(()),
+        }
+    }
+
+    /// Minimum depth needed to expand the rule
+    pub fn min_depth(&self, rule: usize) -> usize {
+        *self.rules[rule].alt_depth.iter().min().unwrap()
+    }
+}
+
+/// Splits a Fuzzingbook style expansion ("<a> + <b>") into symbols
+fn split_expansion(expansion: &str, rules: &[&String]) -> Vec<RawSymbol> {
+    let mut syms = Vec::new();
+    let mut literal = String::new();
+    let mut rest = expansion;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            if let Some(end) = rest.find('>') {
+                let name = &rest[..=end];
+                if rules.iter().any(|r| r.as_str() == name) {
+                    if !literal.is_empty() {
+                        syms.push(RawSymbol::Term(literal.as_bytes().to_vec()));
+                        literal.clear();
+                    }
+                    syms.push(RawSymbol::NonTerm(name.to_string()));
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        literal.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !literal.is_empty() {
+        syms.push(RawSymbol::Term(literal.into_bytes()));
+    }
+    syms
+}
+
+/// Parses a JSON grammar : {"<rule>" : ["<a> literal", ["<b>", "literal"], ...], ...}
+fn parse_json(contents: &str) -> Result<RawRules> {
+    let root: serde_json::Value = match serde_json::from_str(contents) {
+        Ok(v) => v,
+        Err(e) => return Err(From::from(format!("Invalid JSON : {}", e))),
+    };
+    let obj = match root.as_object() {
+        Some(o) => o,
+        None => return Err(From::from("Grammar must be a JSON object".to_string())),
+    };
+    let names: Vec<&String> = obj.keys().collect();
+
+    let mut raw = Vec::with_capacity(obj.len());
+    for (name, alts_val) in obj.iter() {
+        let alts_val = match alts_val.as_array() {
+            Some(a) => a,
+            None => {
+                return Err(From::from(format!(
+                    "Rule '{}' must be a list of expansions",
+                    name
+                )))
+            }
+        };
+        let mut alts = Vec::with_capacity(alts_val.len());
+        for alt in alts_val.iter() {
+            if let Some(s) = alt.as_str() {
+                alts.push(split_expansion(s, &names));
+            } else if let Some(syms) = alt.as_array() {
+                let mut cur = Vec::with_capacity(syms.len());
+                for sym in syms.iter() {
+                    let s = match sym.as_str() {
+                        Some(s) => s,
+                        None => {
+                            return Err(From::from(format!(
+                                "Rule '{}' has a non-string symbol",
+                                name
+                            )))
+                        }
+                    };
+                    cur.push(if names.iter().any(|n| n.as_str() == s) {
+                        RawSymbol::NonTerm(s.to_string())
+                    } else {
+                        RawSymbol::Term(s.as_bytes().to_vec())
+                    });
+                }
+                alts.push(cur);
+            } else {
+                return Err(From::from(format!(
+                    "Rule '{}' has an invalid expansion",
+                    name
+                )));
+            }
+        }
+        raw.push((name.clone(), alts));
+    }
+
+    // Keep "<start>" first so it is the default start rule
+    if let Some(idx) = raw.iter().position(|(n, _)| n == "<start>") {
+        raw.swap(0, idx);
+    }
+
+    Ok(raw)
+}
+
+/// Parses a quoted literal with escapes. Returns the bytes and the rest of the line.
+fn parse_literal(line: &str) -> std::result::Result<(Vec<u8>, &str), String> {
+    let quote = line.as_bytes()[0];
+    let mut val = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b if b == quote => return Ok((val, &line[i + 1..])),
+            b'\\' => {
+                i += 1;
+                match bytes.get(i) {
+                    Some(b'n') => val.push(b'\n'),
+                    Some(b'r') => val.push(b'\r'),
+                    Some(b't') => val.push(b'\t'),
+                    Some(b'x') => {
+                        let hex = line.get(i + 1..i + 3).unwrap_or("");
+                        match u8::from_str_radix(hex, 16) {
+                            Ok(v) => val.push(v),
+                            Err(_) => return Err("Invalid escaping (not \\xNN)".to_string()),
+                        }
+                        i += 2;
+                    }
+                    Some(b) => val.push(*b),
+                    None => break,
+                }
+            }
+            b => val.push(b),
+        }
+        i += 1;
+    }
+    Err("Unterminated literal".to_string())
+}
+
+/// Parses a BNF grammar : <rule> ::= <a> "literal" | 'other' <b>
+/// Lines starting with '|' continue the previous rule.
+fn parse_bnf(contents: &str) -> Result<RawRules> {
+    let mut raw: RawRules = Vec::new();
+
+    for (line_num, line) in contents.lines().enumerate() {
+        let err = |e: &str| -> Result<RawRules> {
+            Err(From::from(format!("line {} : {}", line_num + 1, e)))
+        };
+        let mut rest = line.trim();
+        if rest.is_empty() || rest.starts_with('#') {
+            continue;
+        }
+
+        if rest.starts_with('|') {
+            // Continuation of the previous rule
+            match raw.last_mut() {
+                // "<rule> ::=" followed by alternatives on the next lines
+                Some((_, alts)) if alts.len() == 1 && alts[0].is_empty() => {}
+                Some((_, alts)) => alts.push(Vec::new()),
+                None => return err("Alternative without a rule"),
+            }
+            rest = &rest[1..];
+        } else {
+            let sep = match rest.find("::=") {
+                Some(s) => s,
+                None => return err("Expected <rule> ::= expansion"),
+            };
+            let name = rest[..sep].trim();
+            if !name.starts_with('<') || !name.ends_with('>') {
+                return err("Rule names must look like <name>");
+            }
+            raw.push((name.to_string(), vec![Vec::new()]));
+            rest = &rest[sep + 3..];
+        }
+
+        let alts = &mut raw.last_mut().unwrap().1;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+            match rest.as_bytes()[0] {
+                b'|' => {
+                    alts.push(Vec::new());
+                    rest = &rest[1..];
+                }
+                b'"' | b'\'' => {
+                    let (val, r) = match parse_literal(rest) {
+                        Ok(v) => v,
+                        Err(e) => return err(&e),
+                    };
+                    alts.last_mut().unwrap().push(RawSymbol::Term(val));
+                    rest = r;
+                }
+                b'<' => {
+                    let end = match rest.find('>') {
+                        Some(e) => e,
+                        None => return err("Unterminated rule name"),
+                    };
+                    alts.last_mut()
+                        .unwrap()
+                        .push(RawSymbol::NonTerm(rest[..=end].to_string()));
+                    rest = &rest[end + 1..];
+                }
+                _ => return err("Expected <rule>, \"literal\" or '|'"),
+            }
+        }
+    }
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bnf() {
+        let g = Grammar::from_bnf(
+            "# comment\n<start> ::= <a> 'x\\x41\\n' | \"\"\n<a> ::=\n  | \"1\" <a>\n  | \"2\"",
+            None,
+        )
+        .unwrap();
+        assert_eq!(g.rules.len(), 2);
+        assert_eq!(g.start, 0);
+        assert_eq!(
+            g.rules[0].alts,
+            vec![
+                vec![Symbol::NonTerm(1), Symbol::Term(b"xA\n".to_vec())],
+                vec![Symbol::Term(Vec::new())],
+            ]
+        );
+        assert_eq!(
+            g.rules[1].alts,
+            vec![
+                vec![Symbol::Term(b"1".to_vec()), Symbol::NonTerm(1)],
+                vec![Symbol::Term(b"2".to_vec())],
+            ]
+        );
+        assert_eq!(g.min_depth(0), 1);
+        assert_eq!(g.rules[0].alt_depth, vec![2, 1]);
+    }
+
+    #[test]
+    fn parses_json() {
+        let g = Grammar::from_json(
+            r#"{"<num>": ["<digit><num>", ["<digit>"]], "<start>": ["n=<num>;"], "<digit>": ["0", "1"]}"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(g.rules[g.start].name, "<start>");
+        let num = g.rules.iter().position(|r| r.name == "<num>").unwrap();
+        assert_eq!(
+            g.rules[g.start].alts[0],
+            vec![
+                Symbol::Term(b"n=".to_vec()),
+                Symbol::NonTerm(num),
+                Symbol::Term(b";".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_grammars() {
+        assert!(Grammar::from_bnf("<a> ::= <b>", None).is_err());
+        assert!(Grammar::from_bnf("<a> ::= <a> \"x\"", None).is_err());
+        assert!(Grammar::from_bnf("<a> ::= \"x\"\n<a> ::= \"y\"", None).is_err());
+        assert!(Grammar::from_bnf("<a> ::= \"x", None).is_err());
+        assert!(Grammar::from_bnf("a ::= \"x\"", None).is_err());
+        assert!(Grammar::from_bnf("<a> ::= \"x\"", Some("<b>")).is_err());
+        assert!(Grammar::from_json("[]", None).is_err());
+    }
+}
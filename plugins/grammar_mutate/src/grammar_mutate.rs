@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fs;
+use std::mem::MaybeUninit;
+use std::path::Path;
+
+use ::cflib::*;
+use ::rand::rngs::SmallRng;
+use ::rand::{Rng, SeedableRng};
+
+mod grammar;
+pub use grammar::*;
+mod tree;
+pub use tree::*;
+mod parse;
+pub use parse::*;
+
+cflib::register!(name, env!("CARGO_PKG_NAME"));
+cflib::register!(load, init);
+cflib::register!(pre_fuzz, validate);
+cflib::register!(fuzz, mutate_input);
+cflib::register!(unload, destroy);
+
+const DEFAULT_MAX_DEPTH: usize = 20;
+
+struct State {
+    /// fast/non-crypto grade random
+    rng: SmallRng,
+    grammar: Grammar,
+    /// Depth after which the trees stop growing
+    max_depth: usize,
+    /// Derivation tree of every input of INPUT_LIST (None for inputs that dont match the grammar)
+    trees: Vec<Option<Node>>,
+    /// Tree and contents of the last input we generated
+    last_tree: Option<Node>,
+    last_bytes: Vec<u8>,
+    /// Number of inputs that have a derivation tree
+    num_trees: StatNum,
+
+    no_mutate: &'static bool,
+    inputs: &'static Vec<CfInputInfo>,
+    cur_input_idx: &'static usize,
+    /// Reference to the currently selected input
+    cur_input: &'static mut CfInput,
+}
+
+// Initialize our plugin
+fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+
+    let grammar_file = match plugin_conf.get("grammar_file") {
+        Some(v) => v,
+        None => {
+            core.error("Missing 'grammar_file' in plugin_conf");
+            return Err(From::from("Invalid config".to_string()));
+        }
+    };
+    let grammar = match Grammar::from_file(
+        Path::new(grammar_file),
+        plugin_conf.get("grammar_start").map(|s| s.as_str()),
+    ) {
+        Ok(g) => g,
+        Err(e) => {
+            core.error(&format!(
+                "Failed to load grammar '{}' : {}",
+                grammar_file, e
+            ));
+            return Err(From::from("Invalid config".to_string()));
+        }
+    };
+    core.info(&format!(
+        "Loaded {} grammar rules, starting at {}",
+        grammar.rules.len(),
+        grammar.rules[grammar.start].name
+    ));
+
+    let max_depth = match plugin_conf.get("grammar_max_depth") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(num) if num > 0 => num,
+            _ => {
+                core.error(&format!(
+                    "grammar_max_depth must be a number above 0 : '{}'",
+                    v
+                ));
+                return Err(From::from("Invalid config".to_string()));
+            }
+        },
+        None => DEFAULT_MAX_DEPTH,
+    };
+
+    #[allow(invalid_value)]
+    let s = Box::new(unsafe {
+        State {
+            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+            grammar,
+            max_depth,
+            trees: Vec::new(),
+            last_tree: None,
+            last_bytes: Vec::new(),
+            num_trees: core.new_stat_num("num_trees", 0)?,
+            // core store values
+            no_mutate: store.as_mutref(STORE_NO_MUTATE, Some(core))?,
+            // Plugin store values
+            inputs: MaybeUninit::zeroed().assume_init(),
+            cur_input_idx: MaybeUninit::zeroed().assume_init(),
+            cur_input: MaybeUninit::zeroed().assume_init(),
+        }
+    });
+
+    Ok(Box::into_raw(s) as _)
+}
+
+// Make sure we have everything to fuzz properly
+fn validate(
+    core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    unsafe {
+        s.inputs = store.as_ref(STORE_INPUT_LIST, Some(core))?;
+        s.cur_input_idx = store.as_ref(STORE_INPUT_IDX, Some(core))?;
+        s.cur_input = store.as_mutref(STORE_INPUT_BYTES, Some(core))?;
+    }
+
+    Ok(())
+}
+
+// Perform our task in the fuzzing loop
+fn mutate_input(
+    _core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    if *s.no_mutate {
+        return Ok(());
+    }
+
+    // Keep the trees of the inputs that were added to the input list
+    s.track_new_inputs();
+
+    let grammar = &s.grammar;
+    let tree = match s.trees.get(*s.cur_input_idx) {
+        Some(Some(t)) => {
+            let mut tree = t.clone();
+            let mut spliced = false;
+            if s.rng.gen() {
+                // Splice with the tree of another input
+                let idx = s.rng.gen_range(0, s.trees.len());
+                if let Some(other) = &s.trees[idx] {
+                    spliced = splice_subtree(&mut tree, other, &mut s.rng);
+                }
+            }
+            if !spliced {
+                replace_subtree(grammar, &mut tree, s.max_depth, &mut s.rng);
+            }
+            tree
+        }
+        // We cant mutate the structure of this input, generate a new one
+        _ => Node::generate(grammar, grammar.start, 0, s.max_depth, &mut s.rng),
+    };
+
+    s.cur_input.clear();
+    tree.serialize(grammar, s.cur_input);
+
+    s.last_bytes.clear();
+    s.last_bytes.extend_from_slice(s.cur_input);
+    s.last_tree = Some(tree);
+
+    Ok(())
+}
+
+// Unload and free our resources
+fn destroy(
+    _core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let _state = box_take!(plugin_ctx, State);
+    Ok(())
+}
+
+impl State {
+    /// Finds the trees of the inputs that were added to the input list. Our
+    /// last tree is reused when it matches, other inputs (e.g. seeds) are parsed.
+    fn track_new_inputs(&mut self) {
+        while self.trees.len() < self.inputs.len() {
+            let info = unsafe { self.inputs.get_unchecked(self.trees.len()) };
+            let read;
+            let contents = match (&info.contents, &info.path) {
+                (Some(c), _) => Some(c),
+                (None, Some(p)) if info.len <= MAX_PARSE_LEN => {
+                    read = fs::read(p).ok();
+                    read.as_ref()
+                }
+                _ => None,
+            };
+            let tree = match contents {
+                Some(c) if self.last_tree.is_some() && c == &self.last_bytes => {
+                    self.last_tree.clone()
+                }
+                Some(c) => parse(&self.grammar, c),
+                None => None,
+            };
+            if tree.is_some() {
+                *self.num_trees.val += 1;
+            }
+            self.trees.push(tree);
+        }
+    }
+}
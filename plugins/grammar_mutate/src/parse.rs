@@ -0,0 +1,252 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+
+/// Inputs bigger than this are not parsed
+pub const MAX_PARSE_LEN: usize = 16 * 1024;
+
+/// Earley item : alternative of a rule with a dot before symbol `dot`
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    alt: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// Rebuilds derivation trees for inputs that were not generated by us
+struct Parser<'a> {
+    grammar: &'a Grammar,
+    input: &'a [u8],
+    /// (rule, start, end) of every complete expansion
+    done: HashSet<(usize, usize, usize)>,
+    /// Expansions currently being built, breaks cycles
+    active: HashSet<(usize, usize, usize)>,
+    built: HashMap<(usize, usize, usize), Option<Node>>,
+}
+
+/// Parses input into a derivation tree of the grammar's start rule
+pub fn parse(grammar: &Grammar, input: &[u8]) -> Option<Node> {
+    if input.len() > MAX_PARSE_LEN {
+        return None;
+    }
+    let mut p = Parser {
+        grammar,
+        input,
+        done: HashSet::new(),
+        active: HashSet::new(),
+        built: HashMap::new(),
+    };
+    p.recognize();
+    if !p.done.contains(&(grammar.start, 0, input.len())) {
+        return None;
+    }
+    p.build(grammar.start, 0, input.len())
+}
+
+impl<'a> Parser<'a> {
+    /// Runs the Earley recognizer, filling `done`
+    fn recognize(&mut self) {
+        let grammar = self.grammar;
+        let rules = &grammar.rules;
+        let mut sets: Vec<Vec<Item>> = vec![Vec::new(); self.input.len() + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); self.input.len() + 1];
+        let add = |sets: &mut Vec<Vec<Item>>, seen: &mut Vec<HashSet<Item>>, pos: usize, item| {
+            if seen[pos].insert(item) {
+                sets[pos].push(item);
+            }
+        };
+
+        for alt in 0..rules[grammar.start].alts.len() {
+            let item = Item {
+                rule: grammar.start,
+                alt,
+                dot: 0,
+                origin: 0,
+            };
+            add(&mut sets, &mut seen, 0, item);
+        }
+
+        for pos in 0..sets.len() {
+            let mut i = 0;
+            while i < sets[pos].len() {
+                let item = sets[pos][i];
+                i += 1;
+                let expansion = &rules[item.rule].alts[item.alt];
+                let next = Item {
+                    dot: item.dot + 1,
+                    ..item
+                };
+
+                match expansion.get(item.dot) {
+                    // Complete, advance the items waiting on this rule
+                    None => {
+                        self.done.insert((item.rule, item.origin, pos));
+                        let mut j = 0;
+                        while j < sets[item.origin].len() {
+                            let w = sets[item.origin][j];
+                            j += 1;
+                            if rules[w.rule].alts[w.alt].get(w.dot)
+                                == Some(&Symbol::NonTerm(item.rule))
+                            {
+                                add(&mut sets, &mut seen, pos, Item { dot: w.dot + 1, ..w });
+                            }
+                        }
+                    }
+                    // Scan
+                    Some(Symbol::Term(v)) => {
+                        if self.input[pos..].starts_with(v) {
+                            add(&mut sets, &mut seen, pos + v.len(), next);
+                        }
+                    }
+                    // Predict
+                    Some(Symbol::NonTerm(r)) => {
+                        for alt in 0..rules[*r].alts.len() {
+                            let item = Item {
+                                rule: *r,
+                                alt,
+                                dot: 0,
+                                origin: pos,
+                            };
+                            add(&mut sets, &mut seen, pos, item);
+                        }
+                        // The rule might already have matched nothing here
+                        if self.done.contains(&(*r, pos, pos)) {
+                            add(&mut sets, &mut seen, pos, next);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Builds a tree for rule spanning input[start..end]
+    fn build(&mut self, rule: usize, start: usize, end: usize) -> Option<Node> {
+        let key = (rule, start, end);
+        if let Some(n) = self.built.get(&key) {
+            return n.clone();
+        }
+        if !self.active.insert(key) {
+            return None;
+        }
+
+        let mut res = None;
+        for alt in 0..self.grammar.rules[rule].alts.len() {
+            let mut children = Vec::new();
+            if self.build_seq(rule, alt, 0, start, end, &mut children) {
+                res = Some(Node::NonTerm {
+                    rule,
+                    alt,
+                    children,
+                });
+                break;
+            }
+        }
+
+        self.active.remove(&key);
+        self.built.insert(key, res.clone());
+        res
+    }
+
+    /// Matches the symbols of an alternative from idx onwards against input[pos..end]
+    fn build_seq(
+        &mut self,
+        rule: usize,
+        alt: usize,
+        idx: usize,
+        pos: usize,
+        end: usize,
+        children: &mut Vec<Node>,
+    ) -> bool {
+        let grammar = self.grammar;
+        let sym = match grammar.rules[rule].alts[alt].get(idx) {
+            Some(s) => s,
+            None => return pos == end,
+        };
+        match sym {
+            Symbol::Term(v) => {
+                if pos + v.len() > end || !self.input[pos..].starts_with(v) {
+                    return false;
+                }
+                children.push(Node::Term(idx));
+                if self.build_seq(rule, alt, idx + 1, pos + v.len(), end, children) {
+                    return true;
+                }
+                children.pop();
+            }
+            Symbol::NonTerm(r) => {
+                for split in pos..=end {
+                    if !self.done.contains(&(*r, pos, split)) {
+                        continue;
+                    }
+                    let child = match self.build(*r, pos, split) {
+                        Some(c) => c,
+                        None => continue,
+                    };
+                    children.push(child);
+                    if self.build_seq(rule, alt, idx + 1, split, end, children) {
+                        return true;
+                    }
+                    children.pop();
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rand::rngs::SmallRng;
+    use ::rand::SeedableRng;
+
+    const EXPR: &str = r#"
+<start> ::= <expr>
+<expr> ::= <expr> "+" <term> | <term>
+<term> ::= "(" <expr> ")" | <num>
+<num> ::= <digit> <num> | <digit>
+<digit> ::= "0" | "1" | "2"
+"#;
+
+    fn serialize(grammar: &Grammar, tree: &Node) -> Vec<u8> {
+        let mut out = Vec::new();
+        tree.serialize(grammar, &mut out);
+        out
+    }
+
+    #[test]
+    fn parses_left_recursive_grammar() {
+        let g = Grammar::from_bnf(EXPR, None).unwrap();
+        let tree = parse(&g, b"(1+20)+2").unwrap();
+        assert_eq!(serialize(&g, &tree), b"(1+20)+2");
+        assert!(parse(&g, b"1+").is_none());
+        assert!(parse(&g, b"3").is_none());
+    }
+
+    #[test]
+    fn parses_empty_and_multibyte_terminals() {
+        let g = Grammar::from_bnf(
+            "<start> ::= <opt> \"ab\" <opt>\n<opt> ::= \"\" | \"xyz\"",
+            None,
+        )
+        .unwrap();
+        for input in [&b"ab"[..], b"xyzab", b"abxyz", b"xyzabxyz"] {
+            let tree = parse(&g, input).unwrap();
+            assert_eq!(serialize(&g, &tree), input);
+        }
+        assert!(parse(&g, b"xyab").is_none());
+    }
+
+    #[test]
+    fn generated_inputs_parse_back() {
+        let g = Grammar::from_bnf(EXPR, None).unwrap();
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let tree = Node::generate(&g, g.start, 0, 8, &mut rng);
+            let bytes = serialize(&g, &tree);
+            let parsed = parse(&g, &bytes).unwrap();
+            assert_eq!(serialize(&g, &parsed), bytes);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use ::rand::rngs::SmallRng;
+use ::rand::Rng;
+
+use crate::*;
+
+/// A derivation tree node
+#[derive(Debug, Clone)]
+pub enum Node {
+    /// Index of a terminal within the expansion of its parent
+    Term(usize),
+    /// Expansion of a rule using one of its alternatives
+    NonTerm {
+        rule: usize,
+        alt: usize,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    /// Generates a random tree for rule. Once depth reaches max_depth,
+    /// the alternatives that terminate the fastest are picked.
+    pub fn generate(
+        grammar: &Grammar,
+        rule: usize,
+        depth: usize,
+        max_depth: usize,
+        rng: &mut SmallRng,
+    ) -> Self {
+        let r = &grammar.rules[rule];
+        let budget = max_depth.saturating_sub(depth);
+        let candidates: Vec<usize> = (0..r.alts.len())
+            .filter(|a| r.alt_depth[*a] <= budget)
+            .collect();
+        let alt = if candidates.is_empty() {
+            // Out of depth, take the shortest path to the leaves
+            let min = grammar.min_depth(rule);
+            r.alt_depth.iter().position(|d| *d == min).unwrap()
+        } else {
+            candidates[rng.gen_range(0, candidates.len())]
+        };
+
+        let children = r.alts[alt]
+            .iter()
+            .enumerate()
+            .map(|(i, sym)| match sym {
+                Symbol::Term(_) => Node::Term(i),
+                Symbol::NonTerm(child) => {
+                    Node::generate(grammar, *child, depth + 1, max_depth, rng)
+                }
+            })
+            .collect();
+
+        Node::NonTerm {
+            rule,
+            alt,
+            children,
+        }
+    }
+
+    /// Appends the bytes this tree represents to dst
+    pub fn serialize(&self, grammar: &Grammar, dst: &mut Vec<u8>) {
+        if let Node::NonTerm {
+            rule,
+            alt,
+            children,
+        } = self
+        {
+            let expansion = &grammar.rules[*rule].alts[*alt];
+            for child in children.iter() {
+                match child {
+                    Node::Term(i) => {
+                        if let Symbol::Term(v) = &expansion[*i] {
+                            dst.extend_from_slice(v);
+                        }
+                    }
+                    n => n.serialize(grammar, dst),
+                }
+            }
+        }
+    }
+
+    /// Collects the path and rule of every nonterminal node
+    pub fn nonterms(&self, path: &mut Vec<usize>, dst: &mut Vec<(Vec<usize>, usize)>) {
+        if let Node::NonTerm { rule, children, .. } = self {
+            dst.push((path.clone(), *rule));
+            for (i, child) in children.iter().enumerate() {
+                path.push(i);
+                child.nonterms(path, dst);
+                path.pop();
+            }
+        }
+    }
+
+    pub fn rule(&self) -> Option<usize> {
+        match self {
+            Node::NonTerm { rule, .. } => Some(*rule),
+            Node::Term(_) => None,
+        }
+    }
+
+    pub fn get(&self, path: &[usize]) -> &Node {
+        let mut cur = self;
+        for i in path {
+            if let Node::NonTerm { children, .. } = cur {
+                cur = &children[*i];
+            }
+        }
+        cur
+    }
+
+    pub fn get_mut(&mut self, path: &[usize]) -> &mut Node {
+        let mut cur = self;
+        for i in path {
+            if let Node::NonTerm { children, .. } = cur {
+                cur = &mut children[*i];
+            }
+        }
+        cur
+    }
+}
+
+/// Replaces a random subtree with a freshly generated one
+pub fn replace_subtree(grammar: &Grammar, tree: &mut Node, max_depth: usize, rng: &mut SmallRng) {
+    let mut nodes = Vec::new();
+    tree.nonterms(&mut Vec::new(), &mut nodes);
+    let (path, rule) = &nodes[rng.gen_range(0, nodes.len())];
+    *tree.get_mut(path) = Node::generate(grammar, *rule, path.len(), max_depth, rng);
+}
+
+/// Replaces a random subtree with a subtree of the same rule from other.
+/// Returns false if the trees have no rule in common.
+pub fn splice_subtree(tree: &mut Node, other: &Node, rng: &mut SmallRng) -> bool {
+    let mut nodes = Vec::new();
+    tree.nonterms(&mut Vec::new(), &mut nodes);
+    let mut other_nodes = Vec::new();
+    other.nonterms(&mut Vec::new(), &mut other_nodes);
+
+    // Try a few random spots before giving up
+    for _ in 0..8 {
+        let (path, rule) = &nodes[rng.gen_range(0, nodes.len())];
+        let matching: Vec<&Vec<usize>> = other_nodes
+            .iter()
+            .filter(|(_, r)| r == rule)
+            .map(|(p, _)| p)
+            .collect();
+        if matching.is_empty() {
+            continue;
+        }
+        let src = matching[rng.gen_range(0, matching.len())];
+        *tree.get_mut(path) = other.get(src).clone();
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rand::SeedableRng;
+
+    const LIST: &str = r#"
+<start> ::= "[" <items> "]"
+<items> ::= <item> "," <items> | <item>
+<item> ::= "a" | "b" | <start>
+"#;
+
+    fn serialize(grammar: &Grammar, tree: &Node) -> Vec<u8> {
+        let mut out = Vec::new();
+        tree.serialize(grammar, &mut out);
+        out
+    }
+
+    #[test]
+    fn generation_respects_max_depth() {
+        let g = Grammar::from_bnf(LIST, None).unwrap();
+        let mut rng = SmallRng::seed_from_u64(1);
+        for _ in 0..50 {
+            let tree = Node::generate(&g, g.start, 0, 4, &mut rng);
+            let mut nodes = Vec::new();
+            tree.nonterms(&mut Vec::new(), &mut nodes);
+            // Out of depth nodes add their minimum depth at most
+            assert!(nodes.iter().all(|(path, _)| path.len() < 4 + 3));
+        }
+    }
+
+    #[test]
+    fn mutations_keep_trees_valid() {
+        let g = Grammar::from_bnf(LIST, None).unwrap();
+        let mut rng = SmallRng::seed_from_u64(2);
+        let other = Node::generate(&g, g.start, 0, 6, &mut rng);
+        for _ in 0..50 {
+            let mut tree = Node::generate(&g, g.start, 0, 6, &mut rng);
+            replace_subtree(&g, &mut tree, 6, &mut rng);
+            assert!(parse(&g, &serialize(&g, &tree)).is_some());
+            assert!(splice_subtree(&mut tree, &other, &mut rng));
+            assert!(parse(&g, &serialize(&g, &tree)).is_some());
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use ::cflib::*;
+
+/// Words longer than this are ignored
+pub const MAX_WORD_LEN: usize = 64;
+/// Maximum number of words in a dictionary
+pub const MAX_DICT_SIZE: usize = 1 << 14;
+
+/// A list of unique words
+#[derive(Default)]
+pub struct Dictionary {
+    pub words: Vec<Vec<u8>>,
+}
+
+impl Dictionary {
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn contains(&self, word: &[u8]) -> bool {
+        self.words.iter().any(|w| w.as_slice() == word)
+    }
+
+    /// Adds a word if it is new. Returns whether it was added.
+    pub fn add(&mut self, word: &[u8]) -> bool {
+        if word.is_empty()
+            || word.len() > MAX_WORD_LEN
+            || self.words.len() >= MAX_DICT_SIZE
+            || self.contains(word)
+        {
+            return false;
+        }
+        self.words.push(word.to_vec());
+        true
+    }
+
+    /// Loads a libFuzzer/AFL style dictionary file
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        for word in load_dict(path, 0)? {
+            self.add(&word);
+        }
+        Ok(())
+    }
+
+    /// Saves the words in the dictionary format
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut f = fs::File::create(path)?;
+        for word in self.words.iter() {
+            let mut line = String::with_capacity(word.len() + 3);
+            line.push('"');
+            for b in word.iter() {
+                match *b {
+                    b'\\' => line.push_str("\\\\"),
+                    b'"' => line.push_str("\\\""),
+                    32..=126 => line.push(*b as char),
+                    _ => line.push_str(&format!("\\x{:02X}", b)),
+                }
+            }
+            line.push_str("\"\n");
+            f.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+}
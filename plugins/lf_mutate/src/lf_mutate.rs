@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs;
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+
+use ::cflib::*;
+use ::rand::Rng;
+
+mod dict;
+pub use dict::*;
+mod mutators;
+pub use mutators::*;
+
+cflib::register!(name, env!("CARGO_PKG_NAME"));
+cflib::register!(load, init);
+cflib::register!(pre_fuzz, validate);
+cflib::register!(fuzz, mutate_input);
+cflib::register!(unload, destroy);
+
+const DEFAULT_MAX_LEN: usize = 4096;
+const DEFAULT_MUTATE_DEPTH: usize = 5;
+/// Number of new auto dictionary words before saving it to disk
+const SAVE_INTERVAL: usize = 64;
+
+struct State {
+    dispatcher: MutationDispatcher,
+    /// Maximum number of stacked mutations per execution
+    mutate_depth: usize,
+    /// Where the persistent auto dictionary is saved
+    auto_dict_path: PathBuf,
+    /// New auto dictionary words since the last save
+    num_unsaved: usize,
+    /// Input we mutated last and its reward count at the time
+    prev_input_idx: usize,
+    last_reward: u64,
+    /// Number of words in the persistent auto dictionary
+    stat_auto_dict: StatNum,
+
+    no_mutate: &'static bool,
+    inputs: &'static Vec<CfInputInfo>,
+    cur_input_idx: &'static usize,
+    /// Reference to the currently selected input
+    cur_input: &'static mut CfInput,
+    input_rewards: Option<&'static Vec<u64>>,
+}
+
+// Initialize our plugin
+fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
+    let plugin_conf: &HashMap<String, String>;
+    let state_dir: &String;
+    unsafe {
+        plugin_conf = store.as_ref(STORE_PLUGIN_CONF, Some(core))?;
+        state_dir = store.as_ref(STORE_STATE_DIR, Some(core))?;
+    }
+
+    #[allow(invalid_value)]
+    let mut s = Box::new(unsafe {
+        State {
+            dispatcher: MutationDispatcher::new(DEFAULT_MAX_LEN),
+            mutate_depth: DEFAULT_MUTATE_DEPTH,
+            auto_dict_path: Path::new(state_dir).join("lf_auto_dict"),
+            num_unsaved: 0,
+            prev_input_idx: 0,
+            last_reward: 0,
+            stat_auto_dict: core.new_stat_num("auto_dict_words", 0)?,
+            // core store values
+            no_mutate: store.as_mutref(STORE_NO_MUTATE, Some(core))?,
+            // Plugin store values
+            inputs: MaybeUninit::zeroed().assume_init(),
+            cur_input_idx: MaybeUninit::zeroed().assume_init(),
+            cur_input: MaybeUninit::zeroed().assume_init(),
+            input_rewards: None,
+        }
+    });
+    s.load_conf(core, plugin_conf)?;
+
+    // Reload the words that worked in a previous run
+    if s.auto_dict_path.is_file() {
+        if let Err(e) = s.dispatcher.auto_dict.load(&s.auto_dict_path) {
+            core.warn(&format!("Failed to load auto dictionary : {}", e));
+        }
+        core.info(&format!(
+            "Loaded {} auto dictionary words",
+            s.dispatcher.auto_dict.len()
+        ));
+    }
+    *s.stat_auto_dict.val = s.dispatcher.auto_dict.len() as u64;
+
+    Ok(Box::into_raw(s) as _)
+}
+
+// Make sure we have everything to fuzz properly
+fn validate(
+    core: &mut dyn PluginInterface,
+    store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    unsafe {
+        s.inputs = store.as_ref(STORE_INPUT_LIST, Some(core))?;
+        s.cur_input_idx = store.as_ref(STORE_INPUT_IDX, Some(core))?;
+        s.cur_input = store.as_mutref(STORE_INPUT_BYTES, Some(core))?;
+        s.input_rewards = store.as_ref(STORE_INPUT_REWARDS, None).ok();
+    }
+
+    if s.input_rewards.is_none() {
+        core.warn("Auto dictionary disabled without input rewards ! Is the `select_input` plugin running ?");
+    }
+
+    Ok(())
+}
+
+// Perform our task in the fuzzing loop
+fn mutate_input(
+    core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let s = box_ref!(plugin_ctx, State);
+
+    // Keep the words that were part of a successful mutation
+    if s.check_new_finds() {
+        for word in s.dispatcher.used_words.iter() {
+            if s.dispatcher.auto_dict.add(word) {
+                s.num_unsaved += 1;
+            }
+        }
+        *s.stat_auto_dict.val = s.dispatcher.auto_dict.len() as u64;
+        if s.num_unsaved >= SAVE_INTERVAL {
+            s.save_auto_dict(core);
+        }
+    }
+    s.dispatcher.used_words.clear();
+    s.prev_input_idx = *s.cur_input_idx;
+
+    if *s.no_mutate {
+        return Ok(());
+    }
+
+    s.pick_cross_over();
+
+    let num_mutations = s.dispatcher.rng().gen_range(1, s.mutate_depth + 1);
+    for _ in 0..num_mutations {
+        if !s.dispatcher.mutate(s.cur_input) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Unload and free our resources
+fn destroy(
+    core: &mut dyn PluginInterface,
+    _store: &mut CfStore,
+    plugin_ctx: *mut u8,
+) -> Result<()> {
+    let mut state = box_take!(plugin_ctx, State);
+    if state.num_unsaved > 0 {
+        state.save_auto_dict(core);
+    }
+    Ok(())
+}
+
+impl State {
+    /// Parse the plugin_conf for our values
+    pub fn load_conf(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        plugin_conf: &HashMap<String, String>,
+    ) -> Result<()> {
+        for (key, dst) in [
+            ("lf_max_len", &mut self.dispatcher.max_len),
+            ("lf_mutate_depth", &mut self.mutate_depth),
+        ] {
+            if let Some(v) = plugin_conf.get(key) {
+                match v.parse::<usize>() {
+                    Ok(num) if num > 0 => *dst = num,
+                    _ => {
+                        core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+        }
+
+        // Dictionaries are separated like the PATH env variable
+        if let Some(v) = plugin_conf.get("lf_dict") {
+            for path in std::env::split_paths(v) {
+                if let Err(e) = self.dispatcher.manual_dict.load(&path) {
+                    core.error(&format!(
+                        "Failed to load dictionary '{}' : {}",
+                        path.to_string_lossy(),
+                        e
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+            core.info(&format!(
+                "Loaded {} dictionary words",
+                self.dispatcher.manual_dict.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the last execution of the input we mutated found something
+    /// (new input or crash) according to the input rewards.
+    fn check_new_finds(&mut self) -> bool {
+        let rewards = match self.input_rewards {
+            Some(r) => r,
+            None => return false,
+        };
+        let found = match rewards.get(self.prev_input_idx) {
+            Some(r) => *r > self.last_reward,
+            None => false,
+        };
+        self.last_reward = rewards.get(*self.cur_input_idx).copied().unwrap_or(0);
+        found
+    }
+
+    /// Loads another random input for the CrossOver mutator
+    fn pick_cross_over(&mut self) {
+        self.dispatcher.cross_buf.clear();
+        if self.inputs.len() < 2 {
+            return;
+        }
+        let idx = self.dispatcher.rng().gen_range(0, self.inputs.len());
+        let info = unsafe { self.inputs.get_unchecked(idx) };
+        match (&info.contents, &info.path) {
+            (Some(c), _) => self.dispatcher.cross_buf.extend_from_slice(c),
+            (None, Some(p)) => {
+                if let Ok(c) = fs::read(p) {
+                    self.dispatcher.cross_buf = c;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn save_auto_dict(&mut self, core: &mut dyn PluginInterface) {
+        if let Err(e) = self.dispatcher.auto_dict.save(&self.auto_dict_path) {
+            core.warn(&format!(
+                "Failed to save auto dictionary to '{}' : {}",
+                self.auto_dict_path.to_string_lossy(),
+                e
+            ));
+        }
+        self.num_unsaved = 0;
+    }
+}
@@ -0,0 +1,486 @@
+use ::rand::rngs::SmallRng;
+use ::rand::seq::SliceRandom;
+use ::rand::{Rng, SeedableRng};
+
+use crate::*;
+
+/// Number of random mutators tried before giving up on an input
+const MAX_TRIES: usize = 100;
+const MIN_BYTES_TO_INSERT: usize = 3;
+const MAX_BYTES_TO_INSERT: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mutator {
+    EraseBytes,
+    InsertByte,
+    InsertRepeatedBytes,
+    ChangeBit,
+    ShuffleBytes,
+    ChangeAsciiInteger,
+    ChangeBinaryInteger,
+    CopyPart,
+    CrossOver,
+    ManualDict,
+    PersistentAutoDict,
+}
+
+const MUTATORS: &[Mutator] = &[
+    Mutator::EraseBytes,
+    Mutator::InsertByte,
+    Mutator::InsertRepeatedBytes,
+    Mutator::ChangeBit,
+    Mutator::ShuffleBytes,
+    Mutator::ChangeAsciiInteger,
+    Mutator::ChangeBinaryInteger,
+    Mutator::CopyPart,
+    Mutator::CrossOver,
+    Mutator::ManualDict,
+    Mutator::PersistentAutoDict,
+];
+
+/// Applies libFuzzer's mutations to inputs
+pub struct MutationDispatcher {
+    /// fast/non-crypto grade random
+    rng: SmallRng,
+    /// Inputs never grow past this length
+    pub max_len: usize,
+    /// Words from the user provided dictionaries
+    pub manual_dict: Dictionary,
+    /// Words that were part of successful mutations
+    pub auto_dict: Dictionary,
+    /// Words used by the mutations of the current input
+    pub used_words: Vec<Vec<u8>>,
+    /// Another input to cross over with (empty if none)
+    pub cross_buf: Vec<u8>,
+}
+
+impl MutationDispatcher {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            rng: SmallRng::from_rng(&mut ::rand::thread_rng()).unwrap(),
+            max_len,
+            manual_dict: Dictionary::default(),
+            auto_dict: Dictionary::default(),
+            used_words: Vec::new(),
+            cross_buf: Vec::new(),
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut SmallRng {
+        &mut self.rng
+    }
+
+    /// Applies one random mutation. Returns false if no mutation could be applied.
+    pub fn mutate(&mut self, data: &mut Vec<u8>) -> bool {
+        if data.len() > self.max_len {
+            data.truncate(self.max_len);
+        }
+
+        for _ in 0..MAX_TRIES {
+            let m = MUTATORS[self.rng.gen_range(0, MUTATORS.len())];
+            let applied = match m {
+                Mutator::EraseBytes => self.erase_bytes(data),
+                Mutator::InsertByte => self.insert_byte(data),
+                Mutator::InsertRepeatedBytes => self.insert_repeated_bytes(data),
+                Mutator::ChangeBit => self.change_bit(data),
+                Mutator::ShuffleBytes => self.shuffle_bytes(data),
+                Mutator::ChangeAsciiInteger => self.change_ascii_integer(data),
+                Mutator::ChangeBinaryInteger => self.change_binary_integer(data),
+                Mutator::CopyPart => self.copy_part(data),
+                Mutator::CrossOver => self.cross_over(data),
+                Mutator::ManualDict => self.add_word(data, false),
+                Mutator::PersistentAutoDict => self.add_word(data, true),
+            };
+            if applied {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn erase_bytes(&mut self, data: &mut Vec<u8>) -> bool {
+        if data.len() <= 1 {
+            return false;
+        }
+        let n = self.rng.gen_range(0, data.len() / 2) + 1;
+        let idx = self.rng.gen_range(0, data.len() - n + 1);
+        data.drain(idx..idx + n);
+        true
+    }
+
+    fn insert_byte(&mut self, data: &mut Vec<u8>) -> bool {
+        if data.len() >= self.max_len {
+            return false;
+        }
+        let idx = self.rng.gen_range(0, data.len() + 1);
+        data.insert(idx, self.rng.gen());
+        true
+    }
+
+    fn insert_repeated_bytes(&mut self, data: &mut Vec<u8>) -> bool {
+        if data.len() + MIN_BYTES_TO_INSERT >= self.max_len {
+            return false;
+        }
+        let max_insert = std::cmp::min(self.max_len - data.len(), MAX_BYTES_TO_INSERT);
+        let n = self.rng.gen_range(MIN_BYTES_TO_INSERT, max_insert + 1);
+        let idx = self.rng.gen_range(0, data.len() + 1);
+        let val = if self.rng.gen() {
+            self.rng.gen()
+        } else if self.rng.gen() {
+            0
+        } else {
+            0xFF
+        };
+        data.splice(idx..idx, vec![val; n]);
+        true
+    }
+
+    fn change_bit(&mut self, data: &mut [u8]) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        let idx = self.rng.gen_range(0, data.len());
+        data[idx] ^= 1 << self.rng.gen_range(0, 8);
+        true
+    }
+
+    fn shuffle_bytes(&mut self, data: &mut [u8]) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        let amount = self.rng.gen_range(0, std::cmp::min(data.len(), 8)) + 1;
+        let start = self.rng.gen_range(0, data.len() - amount + 1);
+        data[start..start + amount].shuffle(&mut self.rng);
+        true
+    }
+
+    /// Finds an ASCII number and changes its value without changing its width
+    fn change_ascii_integer(&mut self, data: &mut [u8]) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        let start = self.rng.gen_range(0, data.len());
+        let b = match data[start..].iter().position(|c| c.is_ascii_digit()) {
+            Some(p) => start + p,
+            None => return false,
+        };
+        let e = data[b..]
+            .iter()
+            .position(|c| !c.is_ascii_digit())
+            .map_or(data.len(), |p| b + p);
+
+        let mut val: u64 = 0;
+        for c in data[b..e].iter() {
+            val = val.wrapping_mul(10).wrapping_add((c - b'0') as u64);
+        }
+        val = match self.rng.gen_range(0, 5) {
+            0 => val.wrapping_add(1),
+            1 => val.wrapping_sub(1),
+            2 => val / 2,
+            3 => val.wrapping_mul(2),
+            _ => self.rng.gen_range(0, val.saturating_mul(val).saturating_add(1)),
+        };
+
+        // Write it back right aligned and padded with zeros
+        let digits = val.to_string().into_bytes();
+        for (i, c) in data[b..e].iter_mut().rev().enumerate() {
+            *c = if i < digits.len() {
+                digits[digits.len() - i - 1]
+            } else {
+                b'0'
+            };
+        }
+        true
+    }
+
+    fn change_binary_integer(&mut self, data: &mut [u8]) -> bool {
+        let width = 1 << self.rng.gen_range(0, 4);
+        if data.len() < width {
+            return false;
+        }
+        let off = self.rng.gen_range(0, data.len() - width + 1);
+        let mask = if width == 8 {
+            u64::MAX
+        } else {
+            (1u64 << (width * 8)) - 1
+        };
+        let swap = |v: u64| v.swap_bytes() >> (64 - width * 8);
+
+        let mut buf = [0u8; 8];
+        let val = if off < 64 && self.rng.gen_range(0, 4) == 0 {
+            // Use the input size as the value
+            let v = data.len() as u64 & mask;
+            if self.rng.gen() {
+                swap(v)
+            } else {
+                v
+            }
+        } else {
+            buf[..width].copy_from_slice(&data[off..off + width]);
+            let mut v = u64::from_le_bytes(buf);
+            let add = self.rng.gen_range(0, 21) as i64 - 10;
+            if self.rng.gen() {
+                v = swap(swap(v).wrapping_add(add as u64) & mask);
+            } else {
+                v = v.wrapping_add(add as u64);
+            }
+            if add == 0 || self.rng.gen() {
+                v = v.wrapping_neg();
+            }
+            v & mask
+        };
+        data[off..off + width].copy_from_slice(&val.to_le_bytes()[..width]);
+        true
+    }
+
+    /// Overwrites part of to with a part of from
+    fn copy_part_of(&mut self, from: &[u8], to: &mut [u8]) -> Option<(usize, usize)> {
+        if from.is_empty() || to.is_empty() {
+            return None;
+        }
+        let to_beg = self.rng.gen_range(0, to.len());
+        let mut copy_size = self.rng.gen_range(0, to.len() - to_beg) + 1;
+        copy_size = std::cmp::min(copy_size, from.len());
+        let from_beg = self.rng.gen_range(0, from.len() - copy_size + 1);
+        to[to_beg..to_beg + copy_size].copy_from_slice(&from[from_beg..from_beg + copy_size]);
+        Some((to_beg, copy_size))
+    }
+
+    /// Inserts a part of from into to
+    fn insert_part_of(&mut self, from: &[u8], to: &mut Vec<u8>) -> Option<(usize, usize)> {
+        if from.is_empty() || to.len() >= self.max_len {
+            return None;
+        }
+        let max_copy = std::cmp::min(self.max_len - to.len(), from.len());
+        let copy_size = self.rng.gen_range(0, max_copy) + 1;
+        let from_beg = self.rng.gen_range(0, from.len() - copy_size + 1);
+        let to_pos = self.rng.gen_range(0, to.len() + 1);
+        to.splice(
+            to_pos..to_pos,
+            from[from_beg..from_beg + copy_size].iter().copied(),
+        );
+        Some((to_pos, copy_size))
+    }
+
+    fn copy_part(&mut self, data: &mut Vec<u8>) -> bool {
+        let from = data.clone();
+        if self.rng.gen() {
+            self.copy_part_of(&from, data).is_some()
+        } else {
+            self.insert_part_of(&from, data).is_some()
+        }
+    }
+
+    fn cross_over(&mut self, data: &mut Vec<u8>) -> bool {
+        if self.cross_buf.is_empty() {
+            return false;
+        }
+        let other = std::mem::take(&mut self.cross_buf);
+        let (applied, res) = match self.rng.gen_range(0, 3) {
+            0 => {
+                // Interleave chunks of both inputs
+                let mut out = Vec::with_capacity(std::cmp::min(self.max_len, data.len() + other.len()));
+                let mut pos = [0, 0];
+                let mut cur = 0;
+                while out.len() < self.max_len && (pos[0] < data.len() || pos[1] < other.len()) {
+                    let src: &[u8] = if cur == 0 { data } else { &other };
+                    let avail = std::cmp::min(src.len() - pos[cur], self.max_len - out.len());
+                    if avail > 0 {
+                        let n = self.rng.gen_range(0, avail) + 1;
+                        out.extend_from_slice(&src[pos[cur]..pos[cur] + n]);
+                        pos[cur] += n;
+                    }
+                    cur ^= 1;
+                }
+                *data = out;
+                (true, None)
+            }
+            1 => {
+                let res = self.insert_part_of(&other, data);
+                (res.is_some(), res)
+            }
+            _ => {
+                let res = self.copy_part_of(&other, data);
+                (res.is_some(), res)
+            }
+        };
+        // Small chunks of other inputs are candidate words
+        if let Some((pos, len)) = res {
+            if len > 1 && len <= MAX_WORD_LEN {
+                self.used_words.push(data[pos..pos + len].to_vec());
+            }
+        }
+        self.cross_buf = other;
+        applied
+    }
+
+    /// Inserts or overwrites a random word from a dictionary
+    fn add_word(&mut self, data: &mut Vec<u8>, auto: bool) -> bool {
+        let dict = if auto {
+            &self.auto_dict
+        } else {
+            &self.manual_dict
+        };
+        if dict.is_empty() {
+            return false;
+        }
+        let word = dict.words[self.rng.gen_range(0, dict.len())].clone();
+
+        if self.rng.gen() {
+            if data.len() + word.len() > self.max_len {
+                return false;
+            }
+            let idx = self.rng.gen_range(0, data.len() + 1);
+            data.splice(idx..idx, word.iter().copied());
+        } else {
+            if word.len() > data.len() {
+                return false;
+            }
+            let idx = self.rng.gen_range(0, data.len() - word.len() + 1);
+            data[idx..idx + word.len()].copy_from_slice(&word);
+        }
+        self.used_words.push(word);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_LEN: usize = 32;
+
+    /// Runs a mutator many times on copies of data
+    fn run<F: FnMut(&mut MutationDispatcher, &mut Vec<u8>) -> bool>(
+        data: &[u8],
+        mut f: F,
+    ) -> Vec<Vec<u8>> {
+        let mut md = MutationDispatcher::new(MAX_LEN);
+        (0..200)
+            .map(|_| {
+                let mut d = data.to_vec();
+                assert!(f(&mut md, &mut d));
+                assert!(d.len() <= MAX_LEN);
+                d
+            })
+            .collect()
+    }
+
+    fn sorted(data: &[u8]) -> Vec<u8> {
+        let mut d = data.to_vec();
+        d.sort_unstable();
+        d
+    }
+
+    #[test]
+    fn erase_bytes_shrinks() {
+        for d in run(b"0123456789", |md, d| md.erase_bytes(d)) {
+            assert!(!d.is_empty() && d.len() < 10);
+        }
+        assert!(!MutationDispatcher::new(MAX_LEN).erase_bytes(&mut vec![0]));
+    }
+
+    #[test]
+    fn insert_byte_adds_one() {
+        for d in run(b"0123", |md, d| md.insert_byte(d)) {
+            assert_eq!(d.len(), 5);
+        }
+        assert!(!MutationDispatcher::new(MAX_LEN).insert_byte(&mut vec![0; MAX_LEN]));
+    }
+
+    #[test]
+    fn insert_repeated_bytes_adds_a_run() {
+        for d in run(b"0123", |md, d| md.insert_repeated_bytes(d)) {
+            assert!(d.len() >= 4 + MIN_BYTES_TO_INSERT);
+        }
+        let mut md = MutationDispatcher::new(MAX_LEN);
+        assert!(!md.insert_repeated_bytes(&mut vec![0; MAX_LEN - MIN_BYTES_TO_INSERT]));
+    }
+
+    #[test]
+    fn change_bit_flips_one_bit() {
+        for d in run(&[0; 8], |md, d| md.change_bit(d)) {
+            assert_eq!(d.iter().map(|b| b.count_ones()).sum::<u32>(), 1);
+        }
+        assert!(!MutationDispatcher::new(MAX_LEN).change_bit(&mut []));
+    }
+
+    #[test]
+    fn shuffle_bytes_keeps_the_bytes() {
+        for d in run(b"0123456789", |md, d| md.shuffle_bytes(d)) {
+            assert_eq!(sorted(&d), b"0123456789");
+        }
+    }
+
+    #[test]
+    fn change_ascii_integer_keeps_the_width() {
+        for d in run(b"ab1234", |md, d| md.change_ascii_integer(d)) {
+            assert_eq!(d.len(), 6);
+            assert_eq!(&d[..2], b"ab");
+            assert!(d[2..].iter().all(|c| c.is_ascii_digit()));
+        }
+        assert!(!MutationDispatcher::new(MAX_LEN).change_ascii_integer(&mut b"abc".to_vec()));
+    }
+
+    #[test]
+    fn change_binary_integer_keeps_the_size() {
+        for d in run(&[0; 16], |md, d| md.change_binary_integer(d)) {
+            assert_eq!(d.len(), 16);
+        }
+    }
+
+    #[test]
+    fn copy_part_uses_own_bytes() {
+        for d in run(b"aaaabbbb", |md, d| md.copy_part(d)) {
+            assert!(d.len() >= 8);
+            assert!(d.iter().all(|c| *c == b'a' || *c == b'b'));
+        }
+    }
+
+    #[test]
+    fn cross_over_mixes_both_inputs() {
+        for d in run(b"aaaa", |md, d| {
+            md.cross_buf = b"bbbb".to_vec();
+            md.cross_over(d)
+        }) {
+            assert!(d.iter().all(|c| *c == b'a' || *c == b'b'));
+        }
+        assert!(!MutationDispatcher::new(MAX_LEN).cross_over(&mut b"aaaa".to_vec()));
+    }
+
+    #[test]
+    fn cross_over_reports_failed_copies() {
+        let mut md = MutationDispatcher::new(MAX_LEN);
+        for _ in 0..200 {
+            md.cross_buf = b"bbbb".to_vec();
+            let mut d = Vec::new();
+            // Copying into an empty input does nothing
+            if md.cross_over(&mut d) {
+                assert!(!d.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn add_word_uses_the_dictionary() {
+        for d in run(b"0123", |md, d| {
+            md.manual_dict.add(b"xy");
+            md.add_word(d, false)
+        }) {
+            assert!(d.windows(2).any(|w| w == b"xy"));
+        }
+        assert!(!MutationDispatcher::new(MAX_LEN).add_word(&mut vec![0], true));
+    }
+
+    #[test]
+    fn mutate_respects_max_len() {
+        let mut md = MutationDispatcher::new(MAX_LEN);
+        md.manual_dict.add(b"word");
+        md.cross_buf = vec![b'x'; MAX_LEN];
+        let mut d = vec![0; MAX_LEN * 2];
+        for _ in 0..1000 {
+            md.mutate(&mut d);
+            assert!(d.len() <= MAX_LEN);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result};
+use std::process::Stdio;
+
+use ::cflib::TargetOutput;
+
+use crate::os;
+
+/// Size of the reads done on the pipe
+const READ_LEN: usize = 64 * 1024;
+
+/// Target stream redirected to a pipe. Only the first max_len bytes of a run
+/// are kept, the rest is read and dropped so the target never blocks.
+pub struct Capture {
+    /// Non blocking end we read from
+    reader: File,
+    /// End given to the target
+    writer: File,
+    max_len: usize,
+    buf: Vec<u8>,
+    pub output: TargetOutput,
+}
+
+impl Capture {
+    pub fn create(max_len: usize) -> Result<Self> {
+        let (reader, writer) = os::create_pipe()?;
+        Ok(Self {
+            reader,
+            writer,
+            max_len,
+            buf: vec![0; READ_LEN],
+            output: TargetOutput::default(),
+        })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.writer
+    }
+
+    pub fn reader(&self) -> &File {
+        &self.reader
+    }
+
+    pub fn stdio(&self) -> Result<Stdio> {
+        Ok(Stdio::from(self.writer.try_clone()?))
+    }
+
+    /// Drops what was written since the last run (e.g. by leftover child processes)
+    pub fn reset(&mut self) -> Result<()> {
+        self.drain()?;
+        self.output.data.clear();
+        self.output.truncated = false;
+        Ok(())
+    }
+
+    /// Reads everything that is currently in the pipe
+    pub fn drain(&mut self) -> Result<()> {
+        loop {
+            let len = match self.reader.read(&mut self.buf) {
+                Ok(0) => return Ok(()),
+                Ok(len) => len,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let keep = std::cmp::min(len, self.max_len - self.output.data.len());
+            self.output.data.extend_from_slice(&self.buf[..keep]);
+            if keep < len {
+                self.output.truncated = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use ::cflib::SanitizerReport;
+
+/// How the failed allocations of a target under RLIMIT_AS show up in its
+/// stderr : strerror(ENOMEM) and the sanitizers' mmap failures
+const ENOMEM_MARKERS: &[&str] = &["Cannot allocate memory", "(error code: 12)"];
+
+/// Resource limits applied to the target before it executes
+#[derive(Clone, Copy, Default)]
+pub struct ResourceLimits {
+    /// Address space in MB. Linux does not enforce RLIMIT_RSS.
+    pub mem_mb: Option<u64>,
+    /// CPU time in seconds
+    pub cpu_s: Option<u64>,
+    /// Size of the files the target writes in MB
+    pub fsize_mb: Option<u64>,
+    /// Number of open file descriptors
+    pub nofile: Option<u64>,
+    pub no_core: bool,
+}
+
+impl ResourceLimits {
+    pub fn is_set(&self) -> bool {
+        self.mem_mb.is_some()
+            || self.cpu_s.is_some()
+            || self.fsize_mb.is_some()
+            || self.nofile.is_some()
+            || self.no_core
+    }
+
+    /// Whether a crash was caused by the memory limit. Messages like "out of memory"
+    /// are not trusted, the target could print them for other reasons.
+    pub fn hit_mem_limit(
+        &self,
+        peak_rss_kb: u64,
+        report: Option<&SanitizerReport>,
+        stderr: Option<&[u8]>,
+    ) -> bool {
+        let limit_kb = match self.mem_mb {
+            Some(mb) => mb * 1024,
+            None => return false,
+        };
+        // Mappings count towards the limit so the RSS can stay lower
+        if peak_rss_kb >= limit_kb * 9 / 10 {
+            return true;
+        }
+        if matches!(report, Some(r) if r.bug_type == "out-of-memory") {
+            return true;
+        }
+        match stderr {
+            Some(stderr) => {
+                let stderr = String::from_utf8_lossy(stderr);
+                ENOMEM_MARKERS.iter().any(|m| stderr.contains(m))
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_trusts_enomem_and_sanitizer_reports() {
+        let limits = ResourceLimits {
+            mem_mb: Some(100),
+            ..ResourceLimits::default()
+        };
+        let oom = SanitizerReport {
+            bug_type: "out-of-memory".to_string(),
+            ..SanitizerReport::default()
+        };
+        assert!(limits.hit_mem_limit(95 * 1024, None, None));
+        assert!(limits.hit_mem_limit(0, Some(&oom), None));
+        assert!(limits.hit_mem_limit(0, None, Some(b"mmap: Cannot allocate memory")));
+        assert!(!limits.hit_mem_limit(0, None, Some(b"error: out of memory")));
+        assert!(!ResourceLimits::default().hit_mem_limit(u64::MAX, Some(&oom), None));
+    }
+}
@@ -1,6 +1,446 @@
-use std::os::unix::process::ExitStatusExt;
-use std::process::ExitStatus;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+use crate::{Capture, ResourceLimits};
 
 pub fn get_exception(status: &ExitStatus) -> Option<i32> {
     status.signal()
 }
+
+/// Crash reported for sanitizer findings that did not abort the target
+pub const SANITIZER_CRASH: i32 = libc::SIGABRT;
+
+/// Whether the signal was sent because the target used up its CPU time
+pub fn is_cpu_limit(signal: i32) -> bool {
+    signal == libc::SIGXCPU
+}
+
+/// Whether the signal was sent because the target wrote past the file size limit
+pub fn is_fsize_limit(signal: i32) -> bool {
+    signal == libc::SIGXFSZ
+}
+
+/// Creates a shared memory segment that child processes can attach to
+pub fn create_shmem(size: usize) -> Result<(i32, *mut u8), String> {
+    unsafe {
+        let id = libc::shmget(
+            libc::IPC_PRIVATE,
+            size,
+            libc::IPC_CREAT | libc::IPC_EXCL | 0o600,
+        );
+        if id < 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let ptr = libc::shmat(id, std::ptr::null(), 0);
+        if ptr as isize == -1 {
+            let e = std::io::Error::last_os_error().to_string();
+            libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut());
+            return Err(e);
+        }
+        Ok((id, ptr as _))
+    }
+}
+
+pub fn destroy_shmem(id: i32, ptr: *mut u8) {
+    unsafe {
+        libc::shmdt(ptr as _);
+        libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut());
+    }
+}
+
+type AddChdirFn =
+    unsafe extern "C" fn(*mut libc::posix_spawn_file_actions_t, *const libc::c_char) -> libc::c_int;
+
+fn to_cstring(bytes: Vec<u8>) -> Result<CString, String> {
+    CString::new(bytes).map_err(|e| e.to_string())
+}
+
+fn check(ret: libc::c_int) -> std::io::Result<()> {
+    if ret != 0 {
+        return Err(std::io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: libc::pid_t) -> libc::c_int {
+    unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) as _ }
+}
+#[cfg(not(target_os = "linux"))]
+fn pidfd_open(_pid: libc::pid_t) -> libc::c_int {
+    -1
+}
+
+/// Waits for a child and returns its raw wait status
+fn wait_pid(
+    pid: libc::pid_t,
+    flags: libc::c_int,
+    usage: &mut libc::rusage,
+) -> std::io::Result<Option<libc::c_int>> {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::wait4(pid, &mut status, flags, usage) } {
+            0 => return Ok(None),
+            r if r > 0 => return Ok(Some(status)),
+            _ => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// Waits for events on fds, forever when timeout is None
+fn poll(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> std::io::Result<()> {
+    let ms = match timeout {
+        // Round up so we never give up before the deadline
+        Some(t) => {
+            let ms = (t + Duration::from_nanos(999_999)).as_millis();
+            std::cmp::min(ms, i32::MAX as u128) as libc::c_int
+        }
+        None => -1,
+    };
+    for fd in fds.iter_mut() {
+        fd.revents = 0;
+    }
+    if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, ms) } < 0 {
+        let e = std::io::Error::last_os_error();
+        // The caller checks the deadline again
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the target output until it exits, None if it is still running after timeout
+fn wait_draining(
+    pid: libc::pid_t,
+    pidfd: libc::c_int,
+    deadline: Option<Instant>,
+    usage: &mut libc::rusage,
+    captures: &mut [&mut Capture],
+) -> std::io::Result<Option<libc::c_int>> {
+    let mut fds: Vec<libc::pollfd> = captures
+        .iter()
+        .map(|c| c.reader().as_raw_fd())
+        .chain(if pidfd >= 0 { Some(pidfd) } else { None })
+        .map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    let mut delay = Duration::from_micros(50);
+    loop {
+        // Old kernels cant poll the child, check its state in between
+        if pidfd < 0 {
+            if let Some(status) = wait_pid(pid, libc::WNOHANG, usage)? {
+                return Ok(Some(status));
+            }
+        }
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::from_secs(0)) {
+            return Ok(None);
+        }
+        let wait = if pidfd >= 0 {
+            remaining
+        } else {
+            let d = delay;
+            delay = std::cmp::min(delay * 2, Duration::from_millis(1));
+            Some(remaining.map_or(d, |r| std::cmp::min(r, d)))
+        };
+
+        poll(&mut fds, wait)?;
+        for (c, fd) in captures.iter_mut().zip(fds.iter()) {
+            if fd.revents != 0 {
+                c.drain()?;
+            }
+        }
+        if pidfd >= 0 && fds[fds.len() - 1].revents != 0 {
+            return wait_pid(pid, 0, usage);
+        }
+    }
+}
+
+/// Waits for the target and kills it after timeout. Returns None when it timed out.
+fn wait_target(
+    pid: libc::pid_t,
+    timeout: Option<Duration>,
+    peak_rss_kb: &mut u64,
+    captures: &mut [&mut Capture],
+) -> std::io::Result<Option<ExitStatus>> {
+    #[allow(invalid_value)]
+    let mut usage: libc::rusage = unsafe { MaybeUninit::zeroed().assume_init() };
+    let status = if timeout.is_none() && captures.is_empty() {
+        wait_pid(pid, 0, &mut usage)?
+    } else {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let pidfd = pidfd_open(pid);
+        let res = wait_draining(pid, pidfd, deadline, &mut usage, captures);
+        if pidfd >= 0 {
+            unsafe { libc::close(pidfd) };
+        }
+        res?
+    };
+    let result = match status {
+        Some(s) => Some(ExitStatus::from_raw(s)),
+        None => {
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            wait_pid(pid, 0, &mut usage)?;
+            None
+        }
+    };
+    *peak_rss_kb = usage.ru_maxrss as _;
+
+    // Everything the target wrote is in the pipes by now
+    for c in captures.iter_mut() {
+        c.drain()?;
+    }
+    Ok(result)
+}
+
+/// Waits for a target started through Command
+pub fn wait_child(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    peak_rss_kb: &mut u64,
+    captures: &mut [&mut Capture],
+) -> std::io::Result<Option<ExitStatus>> {
+    // Let the target see the end of its input
+    drop(child.stdin.take());
+    wait_target(child.id() as _, timeout, peak_rss_kb, captures)
+}
+
+/// Creates a pipe whose read end does not block
+pub fn create_pipe() -> std::io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let (reader, writer) = (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]));
+        for fd in fds.iter() {
+            if libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if libc::fcntl(fds[0], libc::F_SETFL, libc::O_NONBLOCK) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok((reader, writer))
+    }
+}
+
+/// Applies the limits in the child before it executes the target
+pub fn apply_limits(cmd: &mut Command, limits: ResourceLimits) -> Result<(), String> {
+    let set = |resource, val: u64, max: u64| -> std::io::Result<()> {
+        let lim = libc::rlimit {
+            rlim_cur: val as _,
+            rlim_max: max as _,
+        };
+        if unsafe { libc::setrlimit(resource, &lim) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(mb) = limits.mem_mb {
+                set(libc::RLIMIT_AS, mb << 20, mb << 20)?;
+            }
+            if let Some(s) = limits.cpu_s {
+                // SIGXCPU comes first, SIGKILL a second later if it is ignored
+                set(libc::RLIMIT_CPU, s, s + 1)?;
+            }
+            if let Some(mb) = limits.fsize_mb {
+                set(libc::RLIMIT_FSIZE, mb << 20, mb << 20)?;
+            }
+            if let Some(num) = limits.nofile {
+                set(libc::RLIMIT_NOFILE, num, num)?;
+            }
+            if limits.no_core {
+                set(libc::RLIMIT_CORE, 0, 0)?;
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+/// Runs the target with posix_spawn. The arguments, environment and file
+/// descriptors are prepared once instead of on every execution.
+pub struct Spawner {
+    path: CString,
+    _args: Vec<CString>,
+    argv: Vec<*mut libc::c_char>,
+    _env: Vec<CString>,
+    envp: Vec<*mut libc::c_char>,
+    actions: libc::posix_spawn_file_actions_t,
+    attr: libc::posix_spawnattr_t,
+    _dev_null: File,
+    /// Files used as stdin, stdout and stderr instead of /dev/null
+    stdio: [Option<RawFd>; 3],
+}
+
+impl Spawner {
+    pub fn new(
+        bin: &str,
+        args: &[&String],
+        working_dir: Option<&String>,
+        env: &[(&str, String)],
+        stdio: [Option<&File>; 3],
+    ) -> Result<Self, String> {
+        // Resolve the binary as posix_spawn doesnt search PATH
+        let path = match fs::canonicalize(bin) {
+            Ok(p) => to_cstring(p.into_os_string().into_vec())?,
+            Err(e) => return Err(format!("Failed to resolve '{}' : {}", bin, e)),
+        };
+
+        let mut c_args = vec![to_cstring(bin.as_bytes().to_vec())?];
+        for arg in args {
+            c_args.push(to_cstring(arg.as_bytes().to_vec())?);
+        }
+        let mut c_env = Vec::new();
+        for (key, val) in std::env::vars_os() {
+            if env.iter().any(|(k, _)| key.as_bytes() == k.as_bytes()) {
+                continue;
+            }
+            let mut var = key.into_vec();
+            var.push(b'=');
+            var.extend_from_slice(val.as_bytes());
+            c_env.push(to_cstring(var)?);
+        }
+        for (key, val) in env {
+            c_env.push(to_cstring(format!("{}={}", key, val).into_bytes())?);
+        }
+        let as_ptrs = |v: &[CString]| -> Vec<*mut libc::c_char> {
+            v.iter()
+                .map(|s| s.as_ptr() as *mut _)
+                .chain(std::iter::once(std::ptr::null_mut()))
+                .collect()
+        };
+
+        let dev_null = match fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/null")
+        {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Failed to open /dev/null : {}", e)),
+        };
+
+        #[allow(invalid_value)]
+        let mut s = unsafe {
+            Self {
+                argv: as_ptrs(&c_args),
+                envp: as_ptrs(&c_env),
+                path,
+                _args: c_args,
+                _env: c_env,
+                actions: MaybeUninit::zeroed().assume_init(),
+                attr: MaybeUninit::zeroed().assume_init(),
+                stdio: [
+                    stdio[0].map(|f| f.as_raw_fd()),
+                    stdio[1].map(|f| f.as_raw_fd()),
+                    stdio[2].map(|f| f.as_raw_fd()),
+                ],
+                _dev_null: dev_null,
+            }
+        };
+        if let Err(e) = s.init_attrs(working_dir) {
+            return Err(format!("Failed to setup spawn attributes : {}", e));
+        }
+        Ok(s)
+    }
+
+    fn init_attrs(&mut self, working_dir: Option<&String>) -> std::io::Result<()> {
+        let dev_null = self._dev_null.as_raw_fd();
+        unsafe {
+            check(libc::posix_spawn_file_actions_init(&mut self.actions))?;
+            check(libc::posix_spawnattr_init(&mut self.attr))?;
+
+            // Redirect stdin, stdout and stderr
+            for (target_fd, fd) in self.stdio.iter().enumerate() {
+                check(libc::posix_spawn_file_actions_adddup2(
+                    &mut self.actions,
+                    fd.unwrap_or(dev_null),
+                    target_fd as _,
+                ))?;
+            }
+
+            if let Some(wd) = working_dir {
+                // Only recent libcs have this function
+                let sym = libc::dlsym(
+                    libc::RTLD_DEFAULT,
+                    b"posix_spawn_file_actions_addchdir_np\0".as_ptr() as _,
+                );
+                if sym.is_null() {
+                    return Err(std::io::Error::from_raw_os_error(libc::ENOSYS));
+                }
+                let add_chdir: AddChdirFn = std::mem::transmute(sym);
+                let wd = CString::new(wd.as_bytes())?;
+                check(add_chdir(&mut self.actions, wd.as_ptr()))?;
+            }
+
+            // Like Command, restore SIGPIPE and the signal mask in the child
+            let mut set: libc::sigset_t = MaybeUninit::zeroed().assume_init();
+            libc::sigemptyset(&mut set);
+            check(libc::posix_spawnattr_setsigmask(&mut self.attr, &set))?;
+            libc::sigaddset(&mut set, libc::SIGPIPE);
+            check(libc::posix_spawnattr_setsigdefault(&mut self.attr, &set))?;
+            check(libc::posix_spawnattr_setflags(
+                &mut self.attr,
+                (libc::POSIX_SPAWN_SETSIGDEF | libc::POSIX_SPAWN_SETSIGMASK) as _,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Runs the target once. Returns None when it timed out.
+    pub fn run(
+        &mut self,
+        timeout: Option<Duration>,
+        peak_rss_kb: &mut u64,
+        captures: &mut [&mut Capture],
+    ) -> std::io::Result<Option<ExitStatus>> {
+        // The child shares our file offset
+        if let Some(fd) = self.stdio[0] {
+            if unsafe { libc::lseek(fd, 0, libc::SEEK_SET) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        let mut pid = 0;
+        check(unsafe {
+            libc::posix_spawn(
+                &mut pid,
+                self.path.as_ptr(),
+                &self.actions,
+                &self.attr,
+                self.argv.as_ptr(),
+                self.envp.as_ptr(),
+            )
+        })?;
+
+        wait_target(pid, timeout, peak_rss_kb, captures)
+    }
+}
+
+impl Drop for Spawner {
+    fn drop(&mut self) {
+        unsafe {
+            libc::posix_spawn_file_actions_destroy(&mut self.actions);
+            libc::posix_spawnattr_destroy(&mut self.attr);
+        }
+    }
+}
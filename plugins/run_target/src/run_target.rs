@@ -1,13 +1,24 @@
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{Seek, Write};
 use std::mem::MaybeUninit;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Command, ExitStatus, Stdio};
 use std::time::{Duration, Instant};
 
+use ::afl_lib::{
+    CmpMap, CMPLOG_SHM_ENV_VAR, STORE_AFL_GLOBALS, STORE_CMPLOG_MAP, STORE_CMPLOG_RUN,
+};
 use ::cflib::*;
-use ::wait_timeout::ChildExt;
+
+mod capture;
+use capture::*;
+mod sanitizer;
+use sanitizer::*;
+mod timeout;
+use timeout::*;
+mod limits;
+use limits::*;
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "windows")] {
@@ -25,18 +36,65 @@ cflib::register!(pre_fuzz, validate);
 cflib::register!(fuzz, run_target);
 cflib::register!(unload, destroy);
 
+const DEFAULT_CAPTURE_MAX_LEN: usize = 64 * 1024;
+
 struct State {
     /// Reference to the currently selected input
     input_file: Option<File>,
     exec_time: u64,
     avg_exec_time: StatNum,
     cur_input: &'static CfInput,
+    /// Set when the current input should not be run
+    skip_exec: Option<&'static bool>,
     avg_denominator: &'static u64,
     exit_status: TargetExitStatus,
     cmd: Command,
     target_input_path: Option<String>,
     target_working_dir: Option<String>,
     target_timeout_ms: Option<Duration>,
+    /// Longer timeout used to confirm that the target hangs, off by default
+    hang_timeout_ms: Option<Duration>,
+    /// Number of timeouts that did not reproduce
+    num_transient_timeouts: StatNum,
+    /// Time spent confirming hangs, kept out of exec_time
+    hang_rerun_time: StatNum,
+    /// Derive target_timeout_ms from the exec times
+    auto_timeout: Option<AutoTimeout>,
+    /// Timeout currently in use
+    cur_timeout: StatNum,
+    /// Target built with cmplog instrumentation
+    cmplog_cmd: Option<Command>,
+    /// Shared memory the cmplog target logs its comparisons to
+    cmplog_shm_id: i32,
+    cmplog_map: *mut CmpMap,
+    /// Whether the cmplog target should run before the next execution
+    cmplog_run: bool,
+    /// Run the targets with posix_spawn instead of Command
+    fast_spawn: bool,
+    spawner: Option<os::Spawner>,
+    cmplog_spawner: Option<os::Spawner>,
+    /// Whether the target output is published in the store
+    capture_stdout: bool,
+    capture_stderr: bool,
+    /// Maximum number of bytes kept for each stream
+    capture_max_len: usize,
+    /// Whether the input file is given as stdin
+    stdin_file: bool,
+    stdout: Option<Capture>,
+    stderr: Option<Capture>,
+    /// Look for sanitizer reports in the target stderr
+    sanitizers: bool,
+    /// Environment variables set for the target
+    target_env: Vec<(&'static str, String)>,
+    sanitizer_report: Option<SanitizerReport>,
+    /// Limits applied to the target processes
+    limits: ResourceLimits,
+    peak_rss_kb: u64,
+    limit_hit: Option<TargetLimit>,
+    num_mem_limit_hits: StatNum,
+    num_fsize_limit_hits: StatNum,
+    /// Biggest peak RSS seen so far
+    max_peak_rss: StatNum,
 }
 
 // Initialize our plugin
@@ -60,19 +118,48 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
             target_input_path: None,
             target_working_dir: None,
             target_timeout_ms: None,
+            hang_timeout_ms: None,
+            auto_timeout: None,
             cmd: Command::new(target_bin_path),
+            cmplog_cmd: None,
+            cmplog_shm_id: 0,
+            cmplog_map: std::ptr::null_mut(),
+            cmplog_run: false,
+            fast_spawn: false,
+            spawner: None,
+            cmplog_spawner: None,
+            capture_stdout: false,
+            capture_stderr: false,
+            capture_max_len: DEFAULT_CAPTURE_MAX_LEN,
+            stdin_file: false,
+            stdout: None,
+            stderr: None,
+            sanitizers: false,
+            target_env: Vec::new(),
+            sanitizer_report: None,
+            limits: ResourceLimits::default(),
+            peak_rss_kb: 0,
+            limit_hit: None,
             // Stats
             avg_exec_time: core.new_stat_num(STAT_TARGET_EXEC_TIME, 0)?,
+            num_transient_timeouts: core
+                .new_stat_num(&format!("{}transient_timeouts", TAG_PREFIX_TOTAL), 0)?,
+            hang_rerun_time: core
+                .new_stat_num(&format!("{}hang_rerun_time_ns", TAG_PREFIX_TOTAL), 0)?,
+            cur_timeout: core.new_stat_num(&format!("target_timeout{}", TAG_POSTFIX_MS), 0)?,
+            num_mem_limit_hits: core
+                .new_stat_num(&format!("{}mem_limit_hits", TAG_PREFIX_TOTAL), 0)?,
+            num_fsize_limit_hits: core
+                .new_stat_num(&format!("{}fsize_limit_hits", TAG_PREFIX_TOTAL), 0)?,
+            max_peak_rss: core.new_stat_num("target_peak_rss_kb", 0)?,
             // Core store values
             avg_denominator: store.as_ref(STORE_AVG_DENOMINATOR, Some(core))?,
             // Plugin store values
             cur_input: MaybeUninit::zeroed().assume_init(),
+            skip_exec: None,
         }
     });
 
-    //close stdout and stderr
-    s.cmd.stdout(Stdio::null()).stderr(Stdio::null());
-
     // Insert our store values
     // EXIT_STATUS
     store.insert_exclusive(STORE_EXIT_STATUS, &s.exit_status, Some(core))?;
@@ -84,6 +171,10 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
         s.avg_exec_time.val,
         Some(core),
     )?;
+    // TARGET_PEAK_RSS
+    store.insert_exclusive(STORE_TARGET_PEAK_RSS, &s.peak_rss_kb, Some(core))?;
+    // TARGET_MEM_LIMIT_HIT
+    store.insert_exclusive(STORE_TARGET_LIMIT_HIT, &s.limit_hit, Some(core))?;
 
     // Get reference to core store values
     let plugin_conf: &HashMap<String, String>;
@@ -97,6 +188,9 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
 
     // Parse our config values
     s.load_config(core, plugin_conf)?;
+    if let Some(t) = s.target_timeout_ms {
+        *s.cur_timeout.val = t.as_millis() as _;
+    }
 
     // Create potential input file name
     let mut input_path = PathBuf::new();
@@ -134,21 +228,68 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
         }
     }
 
-    // set command args
-    if !target_args.is_empty() {
-        s.cmd.args(&target_args);
+    if s.capture_stdout || s.capture_stderr {
+        s.create_captures(core, store)?;
+    }
+    // posix_spawn cant feed a pipe and the target could block on its
+    // output while we write its input
+    if s.input_file.is_none() && (s.fast_spawn || s.stdout.is_some() || s.stderr.is_some()) {
+        s.create_stdin_file(core, &input_path)?;
+    }
+    if s.sanitizers {
+        // Dont override the user's options
+        for (key, val) in SANITIZER_ENV {
+            if std::env::var_os(key).is_none() {
+                s.target_env.push((key, val.to_string()));
+            }
+        }
+        store.insert_exclusive(STORE_SANITIZER_REPORT, &s.sanitizer_report, Some(core))?;
     }
 
-    // set command working directory
-    if let Some(ref target_wd) = s.target_working_dir {
-        s.cmd.current_dir(target_wd);
+    // The cmplog target is run the same way as the normal one
+    if s.cmplog_cmd.is_some() {
+        s.create_cmplog_map(core, store)?;
     }
+    for cmd in std::iter::once(&mut s.cmd).chain(s.cmplog_cmd.as_mut()) {
+        // Redirect stdout and stderr to our captures or close them
+        cmd.stdout(match s.stdout {
+            Some(ref c) => c.stdio()?,
+            None => Stdio::null(),
+        });
+        cmd.stderr(match s.stderr {
+            Some(ref c) => c.stdio()?,
+            None => Stdio::null(),
+        });
+
+        // set command args
+        if !target_args.is_empty() {
+            cmd.args(&target_args);
+        }
 
-    // Set input method
-    if s.input_file.is_some() {
-        s.cmd.stdin(Stdio::null());
-    } else {
-        s.cmd.stdin(Stdio::piped());
+        // set command working directory
+        if let Some(ref target_wd) = s.target_working_dir {
+            cmd.current_dir(target_wd);
+        }
+
+        // Set input method
+        match s.input_file {
+            Some(ref f) if s.stdin_file => cmd.stdin(Stdio::from(f.try_clone()?)),
+            Some(_) => cmd.stdin(Stdio::null()),
+            None => cmd.stdin(Stdio::piped()),
+        };
+
+        cmd.envs(s.target_env.iter().map(|(k, v)| (k, v)));
+
+        if s.limits.is_set() {
+            if let Err(e) = os::apply_limits(cmd, s.limits) {
+                core.error(&e);
+                return Err(From::from("Invalid config".to_string()));
+            }
+        }
+    }
+
+    if s.fast_spawn {
+        s.create_spawners(core, target_bin_path, &target_args)?;
     }
 
     core.info(&format!("Running '{}' {:?}", target_bin_path, target_args));
@@ -164,8 +305,22 @@ fn validate(
 ) -> Result<()> {
     let s = box_ref!(plugin_ctx, State);
 
-    // Make sure someone is providing us input bytes
-    s.cur_input = unsafe { store.as_ref(STORE_INPUT_BYTES, Some(core))? };
+    // Make sure someone is providing us input bytes, prefer the ones meant for execution
+    s.cur_input = unsafe {
+        match store.as_ref(STORE_EXEC_BYTES, None) {
+            Ok(v) => v,
+            Err(_) => store.as_ref(STORE_INPUT_BYTES, Some(core))?,
+        }
+    };
+    s.skip_exec = unsafe { store.as_ref(STORE_SKIP_EXEC, None).ok() };
+
+    // Prefer the calibration results of the afl plugins
+    if let Some(ref mut auto) = s.auto_timeout {
+        unsafe {
+            auto.afl = store.as_ref(STORE_AFL_GLOBALS, None).ok();
+            auto.inputs = store.as_ref(STORE_INPUT_LIST, None).ok();
+        }
+    }
 
     Ok(())
 }
@@ -178,6 +333,20 @@ fn run_target(
 ) -> Result<()> {
     let s = box_ref!(plugin_ctx, State);
 
+    // Report a clean run without executing rejected inputs
+    if matches!(s.skip_exec, Some(true)) {
+        s.exit_status = TargetExitStatus::Normal(0);
+        s.exec_time = 0;
+        s.peak_rss_kb = 0;
+        s.limit_hit = None;
+        s.sanitizer_report = None;
+        for c in s.stdout.iter_mut().chain(s.stderr.iter_mut()) {
+            c.output.data.clear();
+            c.output.truncated = false;
+        }
+        return Ok(());
+    }
+
     // Update file on disk
     if let Some(ref mut f) = s.input_file {
         let _ = (f.set_len(0), f.seek(std::io::SeekFrom::Start(0)));
@@ -188,44 +357,47 @@ fn run_target(
         let _ = f.flush();
     }
 
-    let child_start: Instant = Instant::now();
-    let mut child = match s.cmd.spawn() {
-        Ok(c) => c,
-        Err(e) => {
-            core.error(&format!("Failed to spawn child process : {}", e));
-            return Err(From::from("Failed to spawn target".to_string()));
-        }
-    };
+    // The cmplog run only fills the cmplog map, the normal run that follows
+    // is the one published to the other plugins
+    if s.cmplog_run && s.cmplog_cmd.is_some() {
+        s.cmplog_run = false;
+        unsafe { (*s.cmplog_map).clear() };
+        s.execute(core, true, s.target_timeout_ms)?;
+    }
 
-    // Feed input in stdin if required
-    if s.input_file.is_none() {
-        let stdin = child.stdin.as_mut().unwrap();
-        if let Err(e) = stdin.write_all(&s.cur_input) {
-            core.error(&format!("Failed to write target stdin : {}", e));
-            return Err(From::from("Failed to write target stdin".to_string()));
+    let mut result = s.execute(core, false, s.target_timeout_ms)?;
+    let timed_out = result.is_none();
+    // Load spikes can slow the target down, make sure it really hangs.
+    // exec_time stays the one of the first run.
+    if timed_out && s.hang_timeout_ms.is_some() {
+        let exec_time = s.exec_time;
+        result = s.execute(core, false, s.hang_timeout_ms)?;
+        *s.hang_rerun_time.val += s.exec_time;
+        s.exec_time = exec_time;
+        if result.is_some() {
+            *s.num_transient_timeouts.val += 1;
         }
     }
 
-    // Wait for child
-    let result = if let Some(timeout) = s.target_timeout_ms {
-        child.wait_timeout(timeout).unwrap()
-    } else {
-        Some(child.wait().unwrap())
-    };
-
-    s.exec_time = child_start.elapsed().as_nanos() as u64;
-
     update_average(
         s.avg_exec_time.val,
         s.exec_time,
         *s.avg_denominator,
     );
 
-    match result {
-        None => {
-            let _ = child.kill();
-            s.exit_status = TargetExitStatus::Timeout
+    if let Some(ref mut auto) = s.auto_timeout {
+        let exec_ns = if timed_out { None } else { Some(s.exec_time) };
+        if let Some(timeout) = auto.update(exec_ns) {
+            if s.target_timeout_ms != Some(timeout) {
+                core.info(&format!("Target timeout set to {} ms", timeout.as_millis()));
+                s.target_timeout_ms = Some(timeout);
+                *s.cur_timeout.val = timeout.as_millis() as _;
+            }
         }
+    }
+
+    match result {
+        None => s.exit_status = TargetExitStatus::Timeout,
         Some(ref r) => {
             match os::get_exception(&r) {
                 Some(exception) => {
@@ -238,6 +410,47 @@ fn run_target(
         }
     };
 
+    // The kernel signals targets that go over their CPU time or file size
+    s.limit_hit = None;
+    if let TargetExitStatus::Crash(signal) = s.exit_status {
+        if os::is_cpu_limit(signal) {
+            s.exit_status = TargetExitStatus::Timeout;
+        } else if os::is_fsize_limit(signal) {
+            s.limit_hit = Some(TargetLimit::FileSize);
+        }
+    }
+
+    // Sanitizers can report bugs and exit normally, the exit code is kept in the report
+    if s.sanitizers {
+        s.sanitizer_report = s.stderr.as_ref().and_then(|c| parse_report(&c.output.data));
+        if let Some(ref mut r) = s.sanitizer_report {
+            if let TargetExitStatus::Normal(code) = s.exit_status {
+                r.exit_code = Some(code);
+                s.exit_status = TargetExitStatus::Crash(os::SANITIZER_CRASH);
+            }
+        }
+    }
+
+    // Failed allocations make the target crash or get killed
+    if s.limit_hit.is_none()
+        && matches!(s.exit_status, TargetExitStatus::Crash(_))
+        && s.limits.hit_mem_limit(
+            s.peak_rss_kb,
+            s.sanitizer_report.as_ref(),
+            s.stderr.as_ref().map(|c| c.output.data.as_slice()),
+        )
+    {
+        s.limit_hit = Some(TargetLimit::Memory);
+    }
+    match s.limit_hit {
+        Some(TargetLimit::Memory) => *s.num_mem_limit_hits.val += 1,
+        Some(TargetLimit::FileSize) => *s.num_fsize_limit_hits.val += 1,
+        None => {}
+    }
+    if s.peak_rss_kb > *s.max_peak_rss.val {
+        *s.max_peak_rss.val = s.peak_rss_kb;
+    }
+
     Ok(())
 }
 
@@ -247,15 +460,41 @@ fn destroy(
     store: &mut CfStore,
     plugin_ctx: *mut u8,
 ) -> Result<()> {
-    let _state = box_take!(plugin_ctx, State);
+    let state = box_take!(plugin_ctx, State);
 
-    store.remove(STORE_EXIT_STATUS)?;
-    store.remove(STORE_TARGET_EXEC_TIME)?;
-    store.remove(STORE_AVG_TARGET_EXEC_TIME)?;
+    remove_value(store, STORE_EXIT_STATUS)?;
+    remove_value(store, STORE_TARGET_EXEC_TIME)?;
+    remove_value(store, STORE_AVG_TARGET_EXEC_TIME)?;
+    remove_value(store, STORE_TARGET_PEAK_RSS)?;
+    remove_value(store, STORE_TARGET_LIMIT_HIT)?;
+
+    if state.stdout.is_some() {
+        remove_value(store, STORE_TARGET_STDOUT)?;
+    }
+    if state.stderr.is_some() {
+        remove_value(store, STORE_TARGET_STDERR)?;
+    }
+    if state.sanitizers {
+        remove_value(store, STORE_SANITIZER_REPORT)?;
+    }
+
+    if !state.cmplog_map.is_null() {
+        os::destroy_shmem(state.cmplog_shm_id, state.cmplog_map as _);
+        remove_value(store, STORE_CMPLOG_MAP)?;
+        remove_value(store, STORE_CMPLOG_RUN)?;
+    }
 
     Ok(())
 }
 
+/// Removes one of our values, failing if someone else already did
+fn remove_value(store: &mut CfStore, key: &str) -> Result<()> {
+    match store.remove(key) {
+        Some(_) => Ok(()),
+        None => Err(From::from(format!("Store value '{}' is missing", key))),
+    }
+}
+
 impl State {
     /// Parse the plugin_conf for our values
     pub fn load_config(
@@ -269,6 +508,8 @@ impl State {
 
         if let Some(v) = conf.get("target_timeout_ms") {
             match v.parse::<usize>() {
+                // Use the upper bound until we know how fast the target is
+                Err(_) if v == "auto" => self.auto_timeout = Some(AutoTimeout::default()),
                 Ok(num) => self.target_timeout_ms = Some(Duration::from_millis(num as _)),
                 Err(e) => {
                     core.error(&format!(
@@ -280,6 +521,61 @@ impl State {
             }
         }
 
+        for (key, is_min) in [
+            ("target_timeout_min_ms", true),
+            ("target_timeout_max_ms", false),
+        ] {
+            if let Some(v) = conf.get(key) {
+                let auto = match self.auto_timeout {
+                    Some(ref mut a) => a,
+                    None => {
+                        core.error(&format!("{} requires target_timeout_ms to be 'auto'", key));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                };
+                let dst = if is_min { &mut auto.min } else { &mut auto.max };
+                match v.parse::<u64>() {
+                    Ok(num) if num > 0 => *dst = Duration::from_millis(num),
+                    _ => {
+                        core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+        }
+        if let Some(ref auto) = self.auto_timeout {
+            if auto.min > auto.max {
+                core.error("target_timeout_min_ms cant be bigger than target_timeout_max_ms");
+                return Err(From::from("Invalid config".to_string()));
+            }
+            self.target_timeout_ms = Some(auto.max);
+        }
+
+        if let Some(v) = conf.get("target_hang_timeout_ms") {
+            match v.parse::<usize>() {
+                Ok(num) => self.hang_timeout_ms = Some(Duration::from_millis(num as _)),
+                Err(e) => {
+                    core.error(&format!(
+                        "Failed to parse number in target_hang_timeout_ms config '{}' : {}",
+                        v, e
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+        match (self.target_timeout_ms, self.hang_timeout_ms) {
+            (Some(t), Some(h)) if h <= t => {
+                core.error("target_hang_timeout_ms must be bigger than target_timeout_ms");
+                return Err(From::from("Invalid config".to_string()));
+            }
+            (None, Some(_)) => {
+                core.error("target_hang_timeout_ms requires target_timeout_ms");
+                return Err(From::from("Invalid config".to_string()));
+            }
+            // Timeouts are not confirmed unless requested
+            _ => {}
+        }
+
         if let Some(v) = conf.get("target_wd") {
             // Make sure its a valid directory
             if !Path::new(v.as_str()).is_dir() {
@@ -288,6 +584,274 @@ impl State {
             }
             self.target_working_dir = Some(v.clone());
         }
+
+        if conf.get("target_fast_spawn").is_some() {
+            self.fast_spawn = true;
+        }
+
+        // Streams are separated by ','
+        if let Some(v) = conf.get("target_capture") {
+            for name in v.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                match name {
+                    "stdout" => self.capture_stdout = true,
+                    "stderr" => self.capture_stderr = true,
+                    _ => {
+                        core.error(&format!(
+                            "target_capture only accepts 'stdout' and 'stderr' : '{}'",
+                            name
+                        ));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+        }
+
+        // Reports are parsed from stderr
+        if conf.get("target_sanitizers").is_some() {
+            self.sanitizers = true;
+            self.capture_stderr = true;
+        }
+
+        if let Some(v) = conf.get("target_capture_max_len") {
+            match v.parse::<usize>() {
+                Ok(num) if num > 0 => self.capture_max_len = num,
+                _ => {
+                    core.error(&format!(
+                        "target_capture_max_len must be a number above 0 : '{}'",
+                        v
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        if let Some(v) = conf.get("cmplog_target_bin") {
+            if !Path::new(v.as_str()).is_file() {
+                core.error(&format!("Failed to find cmplog target binary '{}'", v));
+                return Err(From::from("Invalid config".to_string()));
+            }
+            self.cmplog_cmd = Some(Command::new(v));
+        }
+
+        for (key, dst) in [
+            ("target_mem_limit_mb", &mut self.limits.mem_mb),
+            ("target_cpu_limit_s", &mut self.limits.cpu_s),
+            ("target_fsize_limit_mb", &mut self.limits.fsize_mb),
+            ("target_nofile_limit", &mut self.limits.nofile),
+        ] {
+            if let Some(v) = conf.get(key) {
+                match v.parse::<u64>() {
+                    Ok(num) if num > 0 => *dst = Some(num),
+                    _ => {
+                        core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                        return Err(From::from("Invalid config".to_string()));
+                    }
+                }
+            }
+        }
+        if conf.get("target_no_core").is_some() {
+            self.limits.no_core = true;
+        }
+        if self.limits.is_set() && self.fast_spawn {
+            // posix_spawn cant run code in the child before exec
+            core.warn("target_fast_spawn does not support resource limits, using Command instead");
+            self.fast_spawn = false;
+        }
+        if self.limits.mem_mb.is_some() && self.sanitizers {
+            core.warn("Sanitizers reserve a lot of address space, target_mem_limit_mb might prevent the target from starting");
+        }
+        Ok(())
+    }
+
+    /// Runs the target once and sets exec_time. Returns None when it timed out.
+    fn execute(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        use_cmplog: bool,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ExitStatus>> {
+        for c in self.stdout.iter_mut().chain(self.stderr.iter_mut()) {
+            if let Err(e) = c.reset() {
+                core.error(&format!("Failed to reset target output : {}", e));
+                return Err(From::from(e));
+            }
+        }
+
+        let child_start: Instant = Instant::now();
+        let spawner = if use_cmplog {
+            self.cmplog_spawner.as_mut()
+        } else {
+            self.spawner.as_mut()
+        };
+        let mut captures: Vec<&mut Capture> =
+            self.stdout.iter_mut().chain(self.stderr.iter_mut()).collect();
+        let result = match spawner {
+            Some(spawner) => match spawner.run(timeout, &mut self.peak_rss_kb, &mut captures) {
+                Ok(r) => r,
+                Err(e) => {
+                    core.error(&format!("Failed to spawn child process : {}", e));
+                    return Err(From::from("Failed to spawn target".to_string()));
+                }
+            },
+            None => self.run_cmd(core, use_cmplog, timeout)?,
+        };
+        self.exec_time = child_start.elapsed().as_nanos() as u64;
+
+        Ok(result)
+    }
+
+    /// Runs the target through Command. Returns None when it timed out.
+    fn run_cmd(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        use_cmplog: bool,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ExitStatus>> {
+        let cmd = match self.cmplog_cmd {
+            Some(ref mut c) if use_cmplog => c,
+            _ => &mut self.cmd,
+        };
+        // The target shares our offset in its stdin
+        if let (true, Some(f)) = (self.stdin_file, self.input_file.as_mut()) {
+            f.seek(std::io::SeekFrom::Start(0))?;
+        }
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                core.error(&format!("Failed to spawn child process : {}", e));
+                return Err(From::from("Failed to spawn target".to_string()));
+            }
+        };
+
+        // Feed input in stdin if required
+        if self.input_file.is_none() {
+            let stdin = child.stdin.as_mut().unwrap();
+            if let Err(e) = stdin.write_all(self.cur_input) {
+                core.error(&format!("Failed to write target stdin : {}", e));
+                return Err(From::from("Failed to write target stdin".to_string()));
+            }
+        }
+
+        // Wait for child
+        let mut captures: Vec<&mut Capture> =
+            self.stdout.iter_mut().chain(self.stderr.iter_mut()).collect();
+        match os::wait_child(&mut child, timeout, &mut self.peak_rss_kb, &mut captures) {
+            Ok(r) => Ok(r),
+            Err(e) => {
+                core.error(&format!("Failed to wait for child process : {}", e));
+                Err(From::from(e))
+            }
+        }
+    }
+
+    /// Creates the input file given to targets that read stdin
+    fn create_stdin_file(&mut self, core: &mut dyn PluginInterface, input_path: &str) -> Result<()> {
+        self.input_file = Some(
+            match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(input_path)
+            {
+                Ok(f) => f,
+                Err(e) => {
+                    core.error(&format!(
+                        "Failed to create input file {} : {}",
+                        input_path, e
+                    ));
+                    return Err(From::from(
+                        "Failed to create input file for target".to_string(),
+                    ));
+                }
+            },
+        );
+        self.stdin_file = true;
+        Ok(())
+    }
+
+    /// Prepares posix_spawn for the target and the cmplog target
+    fn create_spawners(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        target_bin: &str,
+        target_args: &[&String],
+    ) -> Result<()> {
+        let stdio = [
+            if self.stdin_file {
+                self.input_file.as_ref()
+            } else {
+                None
+            },
+            self.stdout.as_ref().map(|c| c.file()),
+            self.stderr.as_ref().map(|c| c.file()),
+        ];
+
+        let wd = self.target_working_dir.as_ref();
+        let spawner = os::Spawner::new(target_bin, target_args, wd, &self.target_env, stdio);
+        let cmplog_spawner = self.cmplog_cmd.as_ref().map(|cmd| {
+            let mut env = self.target_env.clone();
+            env.push((CMPLOG_SHM_ENV_VAR, self.cmplog_shm_id.to_string()));
+            let bin = cmd.get_program().to_string_lossy();
+            os::Spawner::new(&bin, target_args, wd, &env, stdio)
+        });
+        match (spawner, cmplog_spawner.transpose()) {
+            (Ok(v), Ok(cmplog)) => {
+                self.spawner = Some(v);
+                self.cmplog_spawner = cmplog;
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                core.error(&format!("Failed to setup fast spawn : {}", e));
+                return Err(From::from("Invalid config".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Redirects the target output to pipes that are read while it runs
+    fn create_captures(&mut self, core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<()> {
+        let max_len = self.capture_max_len;
+        let open = |core: &mut dyn PluginInterface, name: &str| {
+            Capture::create(max_len).map_err(|e| {
+                core.error(&format!("Failed to capture target {} : {}", name, e));
+                e
+            })
+        };
+
+        if self.capture_stdout {
+            let c = self.stdout.insert(open(core, "stdout")?);
+            store.insert_exclusive(STORE_TARGET_STDOUT, &c.output, Some(core))?;
+        }
+        if self.capture_stderr {
+            let c = self.stderr.insert(open(core, "stderr")?);
+            store.insert_exclusive(STORE_TARGET_STDERR, &c.output, Some(core))?;
+        }
+        Ok(())
+    }
+
+    /// Creates the shared map the cmplog target writes its comparisons to
+    fn create_cmplog_map(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        store: &mut CfStore,
+    ) -> Result<()> {
+        let (id, ptr) = match os::create_shmem(std::mem::size_of::<CmpMap>()) {
+            Ok(v) => v,
+            Err(e) => {
+                core.error(&format!("Failed to create cmplog shared memory : {}", e));
+                return Err(From::from("Failed to create cmplog map".to_string()));
+            }
+        };
+        self.cmplog_shm_id = id;
+        self.cmplog_map = ptr as _;
+        if let Some(ref mut cmd) = self.cmplog_cmd {
+            cmd.env(CMPLOG_SHM_ENV_VAR, id.to_string());
+        }
+
+        unsafe {
+            store.insert_exclusive(STORE_CMPLOG_MAP, &*self.cmplog_map, Some(core))?;
+        }
+        store.insert_exclusive(STORE_CMPLOG_RUN, &self.cmplog_run, Some(core))?;
         Ok(())
     }
 }
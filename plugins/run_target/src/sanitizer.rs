@@ -0,0 +1,193 @@
+use ::cflib::SanitizerReport;
+
+/// Number of stack frames kept in reports
+const MAX_FRAMES: usize = 5;
+
+/// Options given to sanitized targets when they are not already set
+pub const SANITIZER_ENV: &[(&str, &str)] = &[
+    (
+        "ASAN_OPTIONS",
+        "abort_on_error=1:detect_leaks=0:symbolize=1:allocator_may_return_null=1",
+    ),
+    (
+        "UBSAN_OPTIONS",
+        "halt_on_error=1:abort_on_error=1:print_stacktrace=1:report_error_type=1",
+    ),
+    ("MSAN_OPTIONS", "abort_on_error=1:symbolize=1"),
+];
+
+/// Splits "...<marker>XxxSanitizer: rest" into (XxxSanitizer, rest)
+fn split_sanitizer<'a>(line: &'a str, marker: &str) -> Option<(&'a str, &'a str)> {
+    let (_, rest) = line.split_once(marker)?;
+    let (name, rest) = rest.split_once(": ")?;
+    if !name.ends_with("Sanitizer") {
+        return None;
+    }
+    Some((name, rest.trim()))
+}
+
+/// Matches UBSan's "file:line:col: runtime error: ..." lines
+fn is_ubsan_error(line: &str) -> bool {
+    let location = match line.split_once(": runtime error: ") {
+        Some((location, _)) => location,
+        None => return false,
+    };
+    let is_num = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match location.rsplitn(3, ':').collect::<Vec<&str>>()[..] {
+        [col, line, file] => is_num(col) && is_num(line) && !file.is_empty(),
+        _ => false,
+    }
+}
+
+fn first_word(s: &str) -> &str {
+    s.split_whitespace().next().unwrap_or("")
+}
+
+/// Looks for a sanitizer report in the target output
+pub fn parse_report(output: &[u8]) -> Option<SanitizerReport> {
+    let output = String::from_utf8_lossy(output);
+    let mut report: Option<SanitizerReport> = None;
+    let mut stack_done = false;
+
+    for line in output.lines().map(|l| l.trim()) {
+        let r = match report {
+            Some(ref mut r) => r,
+            None => {
+                if let Some((name, rest)) =
+                    split_sanitizer(line, "ERROR: ").or_else(|| split_sanitizer(line, "WARNING: "))
+                {
+                    let bug_type = if rest.starts_with("detected memory leaks") {
+                        "memory-leak"
+                    } else {
+                        first_word(rest)
+                    };
+                    report = Some(SanitizerReport {
+                        sanitizer: name.to_string(),
+                        bug_type: bug_type.to_string(),
+                        ..SanitizerReport::default()
+                    });
+                } else if is_ubsan_error(line) {
+                    report = Some(SanitizerReport {
+                        sanitizer: "UndefinedBehaviorSanitizer".to_string(),
+                        bug_type: "undefined-behavior".to_string(),
+                        ..SanitizerReport::default()
+                    });
+                }
+                continue;
+            }
+        };
+
+        // The summary ends the report and has the most precise bug type
+        if let Some((_, rest)) = split_sanitizer(line, "SUMMARY: ") {
+            let bug_type = first_word(rest);
+            // Leak summaries start with the number of bytes
+            if !bug_type.starts_with(|c: char| c.is_ascii_digit()) {
+                r.bug_type = bug_type.to_string();
+            }
+            break;
+        }
+
+        if r.access.is_none() {
+            if line.starts_with("READ of size") || line.starts_with("WRITE of size") {
+                let access = line.split(" at ").next().unwrap_or(line);
+                r.access = Some(access.to_string());
+            } else if let Some((_, rest)) = line.split_once("The signal is caused by a ") {
+                r.access = Some(first_word(rest).to_string());
+            }
+        }
+
+        // Only keep the first stack trace
+        if stack_done {
+            continue;
+        }
+        if line.starts_with('#') {
+            if r.frames.len() < MAX_FRAMES {
+                // "#0 0x4c5b1e in func file.c:12:3"
+                let mut parts = line.split_whitespace().skip(2).peekable();
+                if parts.peek() == Some(&"in") {
+                    parts.next();
+                }
+                r.frames.push(parts.collect::<Vec<&str>>().join(" "));
+            }
+        } else if !r.frames.is_empty() {
+            stack_done = true;
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASAN: &str = "\
+=================================================================
+==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011 at pc 0x4c5b1e bp 0x7ffd sp 0x7ffd
+READ of size 1 at 0x602000000011 thread T0
+    #0 0x4c5b1e in parse_chunk /src/target.c:12:3
+    #1 0x4c5c2f in main /src/target.c:40:5
+    #2 0x7f0a in __libc_start_main
+0x602000000011 is located 0 bytes to the right of 1-byte region
+allocated by thread T0 here:
+    #0 0x49a0 in malloc
+SUMMARY: AddressSanitizer: heap-buffer-overflow /src/target.c:12:3 in parse_chunk
+";
+
+    #[test]
+    fn parses_asan_report() {
+        let r = parse_report(ASAN.as_bytes()).unwrap();
+        assert_eq!(r.sanitizer, "AddressSanitizer");
+        assert_eq!(r.bug_type, "heap-buffer-overflow");
+        assert_eq!(r.access.as_deref(), Some("READ of size 1"));
+        assert_eq!(
+            r.frames,
+            vec![
+                "parse_chunk /src/target.c:12:3",
+                "main /src/target.c:40:5",
+                "__libc_start_main"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_segv_and_leak_reports() {
+        let segv = "==1==ERROR: AddressSanitizer: SEGV on unknown address 0x000000000000\n\
+                    ==1==The signal is caused by a READ memory access.\n\
+                    \x20   #0 0x4c5b1e in f a.c:1:1\n";
+        let r = parse_report(segv.as_bytes()).unwrap();
+        assert_eq!(r.bug_type, "SEGV");
+        assert_eq!(r.access.as_deref(), Some("READ"));
+        assert_eq!(r.frames, vec!["f a.c:1:1"]);
+
+        let leak = "==1==ERROR: LeakSanitizer: detected memory leaks\n\
+                    SUMMARY: AddressSanitizer: 8 byte(s) leaked in 1 allocation(s).\n";
+        let r = parse_report(leak.as_bytes()).unwrap();
+        assert_eq!(r.sanitizer, "LeakSanitizer");
+        assert_eq!(r.bug_type, "memory-leak");
+    }
+
+    #[test]
+    fn parses_ubsan_errors() {
+        let ubsan = "src/a.c:10:5: runtime error: signed integer overflow\n\
+                     \x20   #0 0x4c5b1e in f src/a.c:10:5\n\
+                     SUMMARY: UndefinedBehaviorSanitizer: signed-integer-overflow src/a.c:10:5\n";
+        let r = parse_report(ubsan.as_bytes()).unwrap();
+        assert_eq!(r.sanitizer, "UndefinedBehaviorSanitizer");
+        assert_eq!(r.bug_type, "signed-integer-overflow");
+        assert_eq!(r.frames, vec!["f src/a.c:10:5"]);
+    }
+
+    #[test]
+    fn ignores_regular_output() {
+        for out in [
+            "",
+            "all good\n",
+            "error: runtime error: bad input\n",
+            "a.c:x:5: runtime error: nope\n",
+            "ERROR: Something: else\n",
+        ] {
+            assert!(parse_report(out.as_bytes()).is_none(), "{}", out);
+        }
+    }
+}
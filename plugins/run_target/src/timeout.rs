@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use ::afl_lib::AflGlobals;
+use ::cflib::*;
+
+/// Timeouts are rounded up to a multiple of this many ms
+const EXEC_TM_ROUND: u64 = 20;
+pub const DEFAULT_MIN_TIMEOUT_MS: u64 = EXEC_TM_ROUND;
+pub const DEFAULT_MAX_TIMEOUT_MS: u64 = 1000;
+/// Corpus growth (in %) that triggers a new evaluation
+const REEVAL_GROWTH: usize = 10;
+
+/// Derives the target timeout from the average exec time like AFL does
+pub struct AutoTimeout {
+    pub min: Duration,
+    pub max: Duration,
+    /// Corpus size the timeout was last computed for
+    num_inputs: usize,
+    evaluated: bool,
+    /// Executions done since the plugin started
+    num_execs: u64,
+    /// Executions that did not time out and their total time
+    num_completed: u64,
+    total_exec_ns: u64,
+    /// Calibration results when running with the afl plugins
+    pub afl: Option<&'static AflGlobals>,
+    pub inputs: Option<&'static Vec<CfInputInfo>>,
+}
+
+impl Default for AutoTimeout {
+    fn default() -> Self {
+        Self {
+            min: Duration::from_millis(DEFAULT_MIN_TIMEOUT_MS),
+            max: Duration::from_millis(DEFAULT_MAX_TIMEOUT_MS),
+            num_inputs: 0,
+            evaluated: false,
+            num_execs: 0,
+            num_completed: 0,
+            total_exec_ns: 0,
+            afl: None,
+            inputs: None,
+        }
+    }
+}
+
+impl AutoTimeout {
+    /// Returns a new timeout when the corpus changed enough since the last evaluation.
+    /// exec_ns is None when the run timed out, those are left out of the average.
+    pub fn update(&mut self, exec_ns: Option<u64>) -> Option<Duration> {
+        self.num_execs += 1;
+        if let Some(ns) = exec_ns {
+            self.num_completed += 1;
+            self.total_exec_ns += ns;
+        }
+        let num_inputs = self.inputs.map(|i| i.len()).unwrap_or(0);
+        let growth = std::cmp::max(self.num_inputs * REEVAL_GROWTH / 100, 1);
+        if self.evaluated && num_inputs < self.num_inputs + growth {
+            return None;
+        }
+
+        // Wait until every input ran at least once
+        let avg_us = match self.afl {
+            Some(afl) if afl.total_cal_cycles >= num_inputs as u64 && afl.total_cal_cycles > 0 => {
+                afl.total_cal_us / afl.total_cal_cycles
+            }
+            None if self.num_execs > num_inputs as u64 && self.num_completed > 0 => {
+                self.total_exec_ns / self.num_completed / 1000
+            }
+            _ => return None,
+        };
+
+        self.evaluated = true;
+        self.num_inputs = num_inputs;
+        Some(self.derive(avg_us))
+    }
+
+    /// Slow targets get smaller multipliers
+    fn derive(&self, avg_us: u64) -> Duration {
+        let ms = if avg_us > 50_000 {
+            avg_us * 2 / 1000
+        } else if avg_us > 10_000 {
+            avg_us * 3 / 1000
+        } else {
+            avg_us * 5 / 1000
+        };
+        let ms = (ms + EXEC_TM_ROUND) / EXEC_TM_ROUND * EXEC_TM_ROUND;
+        std::cmp::min(std::cmp::max(Duration::from_millis(ms), self.min), self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_ignores_timeouts() {
+        let mut auto = AutoTimeout::default();
+        assert_eq!(auto.update(None), None);
+        // 100ms average gets a 2x multiplier, rounded up
+        assert_eq!(auto.update(Some(100_000_000)), Some(Duration::from_millis(220)));
+    }
+
+    #[test]
+    fn timeout_stays_within_bounds() {
+        let auto = AutoTimeout::default();
+        assert_eq!(auto.derive(1), auto.min);
+        assert_eq!(auto.derive(10_000_000), auto.max);
+    }
+}
@@ -2,7 +2,13 @@
 //      Either the target returns normaly setting its exit status to whatever arbitrary value it picks
 //      or the target crashes and windows sets the exception code in the exit status.
 //      Therefore, in rare case, target applications could emulate crashing by setting the exit status themselves...
-use std::process::ExitStatus;
+use std::fs::File;
+use std::process::{Child, Command, ExitStatus};
+use std::time::Duration;
+
+use ::wait_timeout::ChildExt;
+
+use crate::{Capture, ResourceLimits};
 
 pub fn get_exception(status: &ExitStatus) -> Option<i32> {
     match status.code() {
@@ -16,3 +22,73 @@ pub fn get_exception(status: &ExitStatus) -> Option<i32> {
         None => None,
     }
 }
+
+/// Crash reported for sanitizer findings that did not abort the target (STATUS_STACK_BUFFER_OVERRUN)
+pub const SANITIZER_CRASH: i32 = 0xC000_0409_u32 as i32;
+
+/// Resource limits are not supported
+pub fn is_cpu_limit(_signal: i32) -> bool {
+    false
+}
+
+pub fn is_fsize_limit(_signal: i32) -> bool {
+    false
+}
+
+pub fn create_shmem(_size: usize) -> Result<(i32, *mut u8), String> {
+    Err("Shared memory is not supported on Windows".to_string())
+}
+
+pub fn destroy_shmem(_id: i32, _ptr: *mut u8) {}
+
+/// Waits for a target started through Command. Peak RSS is not tracked.
+pub fn wait_child(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    _peak_rss_kb: &mut u64,
+    _captures: &mut [&mut Capture],
+) -> std::io::Result<Option<ExitStatus>> {
+    let result = match timeout {
+        Some(t) => child.wait_timeout(t)?,
+        None => Some(child.wait()?),
+    };
+    if result.is_none() {
+        let _ = child.kill();
+    }
+    Ok(result)
+}
+
+pub fn create_pipe() -> std::io::Result<(File, File)> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Capturing the target output is only supported on Linux",
+    ))
+}
+
+pub fn apply_limits(_cmd: &mut Command, _limits: ResourceLimits) -> Result<(), String> {
+    Err("Resource limits are only supported on Linux".to_string())
+}
+
+/// posix_spawn is not available on Windows
+pub struct Spawner;
+
+impl Spawner {
+    pub fn new(
+        _bin: &str,
+        _args: &[&String],
+        _working_dir: Option<&String>,
+        _env: &[(&str, String)],
+        _stdio: [Option<&File>; 3],
+    ) -> Result<Self, String> {
+        Err("target_fast_spawn is only supported on Linux".to_string())
+    }
+
+    pub fn run(
+        &mut self,
+        _timeout: Option<Duration>,
+        _peak_rss_kb: &mut u64,
+        _captures: &mut [&mut Capture],
+    ) -> std::io::Result<Option<ExitStatus>> {
+        unreachable!()
+    }
+}
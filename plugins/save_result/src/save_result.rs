@@ -1,11 +1,16 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
 use std::io::prelude::*;
 use std::mem::MaybeUninit;
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use ::afl_lib::*;
 use ::cflib::*;
 use ::crypto::{digest::Digest, sha1::Sha1};
+use ::serde_json::json;
 
 cflib::register!(name, env!("CARGO_PKG_NAME"));
 cflib::register!(load, init);
@@ -13,20 +18,51 @@ cflib::register!(pre_fuzz, validate);
 cflib::register!(fuzz, save_result);
 cflib::register!(unload, destroy);
 
+const DEFAULT_BUCKET_FRAMES: usize = 3;
+const DEFAULT_BUCKET_SAMPLES: usize = 1;
+
 struct State {
     hasher: Sha1,
     tmp_uid: [u8; 20],
 
+    /// Describes the fuzzer and target in result metadata
+    fuzzer_name: &'static String,
+    fuzzer_id: &'static usize,
+    target_bin: &'static String,
+    target_args: &'static Vec<String>,
+    target_crc: &'static u32,
+    exec_time: Option<&'static u64>,
+
     /// Reference to the currently selected input
     exit_status: &'static TargetExitStatus,
     cur_input: &'static CfInput,
+    /// Bytes the target actually ran if they differ from cur_input
+    exec_input: Option<&'static CfInput>,
     cur_input_idx: &'static usize,
     input_list: &'static Vec<CfInputInfo>,
+    input_rewards: Option<&'static mut Vec<u64>>,
+    /// Target output captured during the run
+    target_stdout: Option<&'static TargetOutput>,
+    target_stderr: Option<&'static TargetOutput>,
+    sanitizer_report: Option<&'static Option<SanitizerReport>>,
+    peak_rss_kb: Option<&'static u64>,
+    limit_hit: Option<&'static Option<TargetLimit>>,
+    afl: Option<&'static mut AflGlobals>,
+    trace_bits: Option<&'static Vec<u8>>,
+
+    /// Number of samples saved for each crash bucket
+    crash_buckets: HashMap<u64, usize>,
+    /// Number of stack frames that identify a crash
+    bucket_frames: usize,
+    /// Number of inputs saved for each bucket
+    bucket_samples: usize,
+    simple_trace: Vec<u8>,
 
     tmp_str: String,
     crash_dir: PathBuf,
     timeout_dir: PathBuf,
     num_crashes: StatNum,
+    num_unique_crashes: StatNum,
     num_timeouts: StatNum,
     stat_crash_dir: StatStr,
     stat_timeout_dir: StatStr,
@@ -45,15 +81,37 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
 
             // Stats
             num_crashes: MaybeUninit::zeroed().assume_init(),
+            num_unique_crashes: MaybeUninit::zeroed().assume_init(),
             num_timeouts: MaybeUninit::zeroed().assume_init(),
             stat_crash_dir: MaybeUninit::zeroed().assume_init(),
             stat_timeout_dir: MaybeUninit::zeroed().assume_init(),
 
+            // Core store values
+            fuzzer_name: store.as_ref(STORE_FUZZER_NAME, Some(core))?,
+            fuzzer_id: store.as_ref(STORE_FUZZER_ID, Some(core))?,
+            target_bin: store.as_ref(STORE_TARGET_BIN, Some(core))?,
+            target_args: store.as_ref(STORE_TARGET_ARGS, Some(core))?,
+            target_crc: store.as_ref(STORE_TARGET_CRC, Some(core))?,
+            exec_time: None,
+
             // Plugin store values
             exit_status: MaybeUninit::zeroed().assume_init(),
             cur_input: MaybeUninit::zeroed().assume_init(),
+            exec_input: None,
             input_list: MaybeUninit::zeroed().assume_init(),
             cur_input_idx: MaybeUninit::zeroed().assume_init(),
+            input_rewards: None,
+            target_stdout: None,
+            target_stderr: None,
+            sanitizer_report: None,
+            peak_rss_kb: None,
+            limit_hit: None,
+            afl: None,
+            trace_bits: None,
+            crash_buckets: HashMap::new(),
+            bucket_frames: DEFAULT_BUCKET_FRAMES,
+            bucket_samples: DEFAULT_BUCKET_SAMPLES,
+            simple_trace: Vec::new(),
         }
     });
 
@@ -109,7 +167,23 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
     s.stat_timeout_dir =
         core.new_stat_str(&format!("timeouts_dir{}", TAG_POSTFIX_PATH), tmp.len(), tmp)?;
 
+    for (key, dst) in [
+        ("crash_bucket_frames", &mut s.bucket_frames),
+        ("crash_bucket_samples", &mut s.bucket_samples),
+    ] {
+        if let Some(v) = plugin_conf.get(key) {
+            match v.parse::<usize>() {
+                Ok(num) if num > 0 => *dst = num,
+                _ => {
+                    core.error(&format!("{} must be a number above 0 : '{}'", key, v));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+    }
+
     s.num_crashes = core.new_stat_num(STAT_NUM_CRASHES, 0)?;
+    s.num_unique_crashes = core.new_stat_num(STAT_NUM_UNIQUE_CRASHES, 0)?;
     s.num_timeouts = core.new_stat_num(STAT_NUM_TIMEOUTS, 0)?;
 
     Ok(Box::into_raw(s) as _)
@@ -126,9 +200,20 @@ fn validate(
     // Get all the plugin store values we need
     unsafe {
         s.exit_status = store.as_ref(STORE_EXIT_STATUS, Some(core))?;
+        s.exec_time = store.as_ref(STORE_TARGET_EXEC_TIME, None).ok();
         s.cur_input = store.as_ref(STORE_INPUT_BYTES, Some(core))?;
+        s.exec_input = store.as_ref(STORE_EXEC_BYTES, None).ok();
         s.cur_input_idx = store.as_ref(STORE_INPUT_IDX, Some(core))?;
         s.input_list = store.as_ref(STORE_INPUT_LIST, Some(core))?;
+        s.input_rewards = store.as_mutref(STORE_INPUT_REWARDS, None).ok();
+        s.target_stdout = store.as_ref(STORE_TARGET_STDOUT, None).ok();
+        s.target_stderr = store.as_ref(STORE_TARGET_STDERR, None).ok();
+        s.sanitizer_report = store.as_ref(STORE_SANITIZER_REPORT, None).ok();
+        s.peak_rss_kb = store.as_ref(STORE_TARGET_PEAK_RSS, None).ok();
+        s.limit_hit = store.as_ref(STORE_TARGET_LIMIT_HIT, None).ok();
+        // Used to dedup crashes without a stack trace
+        s.afl = store.as_mutref(STORE_AFL_GLOBALS, None).ok();
+        s.trace_bits = store.as_ref(STORE_AFL_TRACE_BITS, None).ok();
     }
 
     Ok(())
@@ -136,14 +221,14 @@ fn validate(
 
 // Perform our task in the fuzzing loop
 fn save_result(
-    _core: &mut dyn PluginInterface,
+    core: &mut dyn PluginInterface,
     _store: &mut CfStore,
     plugin_ctx: *mut u8,
 ) -> Result<()> {
     let s = box_ref!(plugin_ctx, State);
 
     // Save input if interesting exit_status
-    s.save_input()?;
+    s.save_input(core)?;
 
     Ok(())
 }
@@ -160,35 +245,86 @@ fn destroy(
 
 impl State {
     /// Saves the current input if exit_status was interesting
-    pub fn save_input(&mut self) -> Result<bool> {
+    pub fn save_input(&mut self, core: &mut dyn PluginInterface) -> Result<bool> {
         // Likely path first
         if let TargetExitStatus::Normal(_) = self.exit_status {
             return Ok(false);
         }
 
-        let dst: &mut PathBuf = match self.exit_status {
-            TargetExitStatus::Crash(_) => {
-                *self.num_crashes.val += 1;
-                &mut self.crash_dir
+        // The target did nothing wrong when it ran out of resources
+        if matches!(self.limit_hit, Some(Some(_))) {
+            return Ok(false);
+        }
+
+        if let TargetExitStatus::Crash(_) = self.exit_status {
+            *self.num_crashes.val += 1;
+            if !self.keep_crash() {
+                return Ok(false);
             }
+        }
+
+        // Credit the input that lead to this result
+        let cur_input_idx = *self.cur_input_idx;
+        if let Some(r) = self
+            .input_rewards
+            .as_mut()
+            .and_then(|rewards| rewards.get_mut(cur_input_idx))
+        {
+            *r += 1;
+        }
+
+        // Gather what is needed to triage the result
+        let mut metadata = self.metadata();
+
+        let dst: &mut PathBuf = match self.exit_status {
+            TargetExitStatus::Crash(_) => &mut self.crash_dir,
             TargetExitStatus::Timeout => {
                 *self.num_timeouts.val += 1;
-                &mut self.crash_dir
+                &mut self.timeout_dir
             }
             TargetExitStatus::Normal(_) => unreachable!(),
         };
 
+        // Sanitizer crashes are grouped by bug type
+        let bug_type = match self.sanitizer_report {
+            Some(Some(r)) if matches!(self.exit_status, TargetExitStatus::Crash(_)) => {
+                Some(r.bug_type.as_str())
+            }
+            _ => None,
+        };
+        let mut bucket_dir;
+        let dst = match bug_type {
+            Some(bug_type) => {
+                let name: String = bug_type
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                bucket_dir = dst.join(name);
+                if !bucket_dir.is_dir() {
+                    fs::create_dir_all(&bucket_dir)?;
+                }
+                &mut bucket_dir
+            }
+            _ => dst,
+        };
+
+        // The result is reproduced with the bytes the target ran
+        let exec_input = self.exec_input.unwrap_or(self.cur_input);
+
         // calculate sha1 of input
         self.hasher.reset();
-        self.hasher.input(&self.cur_input);
+        self.hasher.input(exec_input);
         self.hasher.result(&mut self.tmp_uid);
 
         // Build hexstr from file uid
         self.tmp_str.clear();
-        for b in &self.tmp_uid {
-            use std::fmt::Write;
-            let _ = write!(&mut self.tmp_str, "{:02X}", *b);
-        }
+        push_hex(&mut self.tmp_str, &self.tmp_uid);
 
         // Create out file
         dst.push(&self.tmp_str);
@@ -202,11 +338,160 @@ impl State {
         let _ = dst.pop();
 
         // Write file contents
-        if let Err(e) = file.write_all(&self.cur_input) {
+        if let Err(e) = file.write_all(exec_input) {
             let _ = fs::remove_file(&dst);
             return Err(From::from(e));
         }
-        
+
+        // Keep the bytes the mutators produced next to it
+        if self.exec_input.is_some() {
+            if let Err(e) = Self::write_sidecar(dst, &self.tmp_str, "mutated", self.cur_input) {
+                core.warn(&format!(
+                    "Failed to save mutated input of {} : {}",
+                    self.tmp_str, e
+                ));
+            }
+        }
+
+        // Keep what the target printed
+        for (ext, output) in [
+            ("stdout", self.target_stdout),
+            ("stderr", self.target_stderr),
+        ] {
+            if let Some(o) = output.filter(|o| !o.data.is_empty()) {
+                if let Err(e) = Self::write_sidecar(dst, &self.tmp_str, ext, &o.data) {
+                    core.warn(&format!(
+                        "Failed to save {} of {} : {}",
+                        ext, self.tmp_str, e
+                    ));
+                }
+            }
+        }
+
+        metadata["uid"] = json!(self.tmp_str);
+        let metadata = serde_json::to_string_pretty(&metadata)?;
+        Self::write_sidecar(dst, &self.tmp_str, "json", metadata.as_bytes())?;
+
         Ok(true)
     }
+
+    /// Describes the result, the input it came from and the fuzzer that found it
+    fn metadata(&self) -> serde_json::Value {
+        // Sanitizer reports keep the exit code of targets that did not abort
+        let report_exit_code = match self.sanitizer_report {
+            Some(Some(r)) => r.exit_code,
+            _ => None,
+        };
+        let (status, exit_code, signal) = match self.exit_status {
+            TargetExitStatus::Normal(c) => ("normal", Some(*c), None),
+            TargetExitStatus::Timeout => ("timeout", None, None),
+            TargetExitStatus::Crash(_) if report_exit_code.is_some() => {
+                ("crash", report_exit_code, None)
+            }
+            TargetExitStatus::Crash(s) => ("crash", None, Some(*s)),
+        };
+        let idx = *self.cur_input_idx;
+        let parent = self.input_list.get(idx).map(|info| {
+            json!({
+                "idx": idx,
+                "uid": to_hex(&info.uid),
+                "path": info.path.as_ref().map(|p| p.to_string_lossy()),
+            })
+        });
+        let sanitizer = match self.sanitizer_report {
+            Some(Some(r)) => Some(json!({
+                "sanitizer": r.sanitizer,
+                "bug_type": r.bug_type,
+                "access": r.access,
+                "frames": r.frames,
+            })),
+            _ => None,
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        json!({
+            "exit_status": status,
+            "exit_code": exit_code,
+            "signal": signal,
+            "exec_time_ns": self.exec_time,
+            "parent": parent,
+            "fuzzer_name": self.fuzzer_name,
+            "fuzzer_id": self.fuzzer_id,
+            "target_bin": self.target_bin,
+            "target_crc_hex": to_hex(&self.target_crc.to_le_bytes()),
+            "cmd_line": std::iter::once(self.target_bin)
+                .chain(self.target_args.iter())
+                .collect::<Vec<&String>>(),
+            "timestamp": timestamp,
+            "sanitizer": sanitizer,
+            "peak_rss_kb": self.peak_rss_kb,
+        })
+    }
+
+    /// Whether the crash belongs to a bucket that still needs samples
+    fn keep_crash(&mut self) -> bool {
+        let (key, is_new) = if let Some(key) = self.stack_key() {
+            (key, !self.crash_buckets.contains_key(&key))
+        } else if let (Some(afl), Some(trace_bits)) = (self.afl.as_mut(), self.trace_bits) {
+            // AFL style, only crashes with new coverage create buckets
+            simplify_trace(trace_bits, &mut self.simple_trace);
+            let novel = has_new_bits(&mut afl.virgin_crash, &self.simple_trace) != 0;
+            (hash32(&self.simple_trace, HASH_CONST) as u64, novel)
+        } else {
+            // Nothing to bucket with, every crash is unique
+            *self.num_unique_crashes.val += 1;
+            return true;
+        };
+
+        if is_new && !self.crash_buckets.contains_key(&key) {
+            self.crash_buckets.insert(key, 0);
+            *self.num_unique_crashes.val += 1;
+        }
+        match self.crash_buckets.get_mut(&key) {
+            Some(num) if *num < self.bucket_samples => {
+                *num += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Hash of the bug type and top frames of the sanitizer stack trace
+    fn stack_key(&self) -> Option<u64> {
+        let report = self.sanitizer_report?.as_ref()?;
+        if report.frames.is_empty() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        report.bug_type.hash(&mut hasher);
+        for frame in report.frames.iter().take(self.bucket_frames) {
+            frame.hash(&mut hasher);
+        }
+        Some(hasher.finish())
+    }
+
+    /// Writes `<uid>.<ext>` in dir
+    fn write_sidecar(dir: &mut PathBuf, uid: &str, ext: &str, contents: &[u8]) -> Result<()> {
+        dir.push(format!("{}.{}", uid, ext));
+        let res = fs::write(&dir, contents);
+        let _ = dir.pop();
+        res?;
+        Ok(())
+    }
+}
+
+fn push_hex(dst: &mut String, bytes: &[u8]) {
+    use std::fmt::Write;
+    for b in bytes {
+        let _ = write!(dst, "{:02X}", *b);
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len() * 2);
+    push_hex(&mut res, bytes);
+    res
 }
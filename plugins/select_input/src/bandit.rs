@@ -0,0 +1,127 @@
+/// Bookkeeping for a single input (arm)
+#[derive(Clone, Copy, Default)]
+struct Arm {
+    /// (Decayed) number of times this input was selected
+    pulls: f64,
+    /// (Decayed) sum of rewards credited to this input
+    reward: f64,
+    /// Last raw reward count seen in INPUT_REWARDS
+    seen: u64,
+}
+
+/// Discounted UCB1 scheduler where every input is an arm
+pub struct UcbScheduler {
+    arms: Vec<Arm>,
+    /// Applied to every arm's pulls/rewards on each selection (1.0 disables decay)
+    pub decay: f64,
+    /// Weight given to the exploration term
+    pub explore: f64,
+}
+
+impl Default for UcbScheduler {
+    fn default() -> Self {
+        Self {
+            arms: Vec::new(),
+            decay: 1.0,
+            explore: std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+impl UcbScheduler {
+    /// Picks the index of the next input to fuzz based on the rewards
+    /// credited to each input so far.
+    pub fn select(&mut self, rewards: &[u64]) -> usize {
+        // No inputs yet, same as the sequential scheduler
+        if rewards.is_empty() {
+            return 0;
+        }
+        if self.arms.len() < rewards.len() {
+            self.arms.resize(rewards.len(), Arm::default());
+        }
+
+        let mut total_pulls: f64 = 0.0;
+        let mut unplayed = None;
+
+        // Decay previous results and pick up new rewards
+        for (idx, (arm, raw_reward)) in self.arms.iter_mut().zip(rewards).enumerate() {
+            arm.pulls *= self.decay;
+            arm.reward *= self.decay;
+            if *raw_reward > arm.seen {
+                arm.reward += (*raw_reward - arm.seen) as f64;
+                arm.seen = *raw_reward;
+            }
+            total_pulls += arm.pulls;
+
+            if unplayed.is_none() && arm.pulls == 0.0 {
+                unplayed = Some(idx);
+            }
+        }
+
+        // Inputs that were never picked get played first
+        let idx = match unplayed {
+            Some(idx) => idx,
+            None => {
+                let log_total = total_pulls.max(1.0).ln();
+                let mut best_idx = 0;
+                let mut best_score = f64::MIN;
+                for (idx, arm) in self.arms.iter().take(rewards.len()).enumerate() {
+                    let score = (arm.reward / arm.pulls)
+                        + self.explore * (log_total / arm.pulls).sqrt();
+                    if score > best_score {
+                        best_score = score;
+                        best_idx = idx;
+                    }
+                }
+                best_idx
+            }
+        };
+
+        self.arms[idx].pulls += 1.0;
+        idx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_unplayed_inputs_first() {
+        let mut ucb = UcbScheduler::default();
+        assert_eq!(ucb.select(&[]), 0);
+        assert_eq!(ucb.select(&[0, 0]), 0);
+        assert_eq!(ucb.select(&[0, 0]), 1);
+        // New inputs get their turn before the bandit kicks in
+        assert_eq!(ucb.select(&[0, 0, 0]), 2);
+    }
+
+    #[test]
+    fn picks_up_new_rewards() {
+        let mut ucb = UcbScheduler::default();
+        for _ in 0..3 {
+            ucb.select(&[0, 0, 0]);
+        }
+        assert_eq!(ucb.select(&[0, 5, 0]), 1);
+        assert_eq!(ucb.arms[1].reward, 5.0);
+        // Rewards that were already seen are not counted twice
+        ucb.select(&[0, 5, 0]);
+        assert_eq!(ucb.arms[1].reward, 5.0);
+        assert_eq!(ucb.arms[1].seen, 5);
+    }
+
+    #[test]
+    fn decays_old_results() {
+        let mut ucb = UcbScheduler {
+            decay: 0.5,
+            ..UcbScheduler::default()
+        };
+        ucb.select(&[0]);
+        ucb.select(&[4]);
+        assert_eq!(ucb.arms[0].pulls, 1.5);
+        assert_eq!(ucb.arms[0].reward, 4.0);
+        ucb.select(&[4]);
+        assert_eq!(ucb.arms[0].pulls, 1.75);
+        assert_eq!(ucb.arms[0].reward, 2.0);
+    }
+}
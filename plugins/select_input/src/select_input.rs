@@ -1,4 +1,4 @@
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
 
 use std::fs::File;
 use std::io::prelude::*;
@@ -6,12 +6,23 @@ use std::mem::MaybeUninit;
 
 use ::cflib::*;
 
+mod bandit;
+use bandit::*;
+
 cflib::register!(name, env!("CARGO_PKG_NAME"));
 cflib::register!(load, init);
 cflib::register!(pre_fuzz, validate);
 cflib::register!(fuzz, select_input);
 cflib::register!(unload, destroy);
 
+/// How inputs get picked when the priority list is empty
+enum Scheduler {
+    /// Go through INPUT_LIST in order
+    Sequential,
+    /// Favor inputs that were credited with new inputs/results
+    Ucb(UcbScheduler),
+}
+
 struct State {
     orig_buf: Vec<u8>,
     cur_input: CfInput,
@@ -21,6 +32,8 @@ struct State {
     input_list: &'static Vec<CfInputInfo>,
     no_select: &'static bool,
     priority_list: BinaryHeap<InputPriority>,
+    rewards: Vec<u64>,
+    scheduler: Scheduler,
     num_priority_inputs: StatNum,
     num_old_inputs: usize,
     num_new_inputs: StatNum,
@@ -29,13 +42,15 @@ struct State {
 // Initialize our plugin
 fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8> {
     #[allow(invalid_value)]
-    let s = Box::new(unsafe {
+    let mut s = Box::new(unsafe {
         State {
             cur_input_idx: 0,
             seq_input_idx: 0,
             orig_buf: Vec::new(),
             cur_input: CfInput::default(),
             priority_list: BinaryHeap::new(),
+            rewards: Vec::new(),
+            scheduler: Scheduler::Sequential,
             restore_input: false,
             num_old_inputs: 0,
             // Stats
@@ -55,6 +70,11 @@ fn init(core: &mut dyn PluginInterface, store: &mut CfStore) -> Result<*mut u8>
     store.insert_exclusive(STORE_INPUT_BYTES, &s.cur_input, Some(core))?;
     store.insert_exclusive(STORE_RESTORE_INPUT, &s.restore_input, Some(core))?;
     store.insert_exclusive(STORE_INPUT_PRIORITY, &s.priority_list, Some(core))?;
+    store.insert_exclusive(STORE_INPUT_REWARDS, &s.rewards, Some(core))?;
+
+    let plugin_conf: &HashMap<String, String> =
+        unsafe { store.as_ref(STORE_PLUGIN_CONF, Some(core))? };
+    s.load_config(core, plugin_conf)?;
 
     Ok(Box::into_raw(s) as _)
 }
@@ -91,6 +111,11 @@ fn select_input(
     *s.num_priority_inputs.val = s.priority_list.len() as u64;
     *s.num_new_inputs.val = (s.num_old_inputs - s.input_list.len()) as u64;
 
+    // Keep a reward slot for every input
+    if s.rewards.len() < s.input_list.len() {
+        s.rewards.resize(s.input_list.len(), 0);
+    }
+
     // Input selection currently disabled
     if *s.no_select {
         //core.trace("No select !");
@@ -105,16 +130,21 @@ fn select_input(
                 s.cur_input_idx = v.idx;
             }
             None => {
-                // Just get the next input
-                s.seq_input_idx += 1;
-                if s.seq_input_idx == s.input_list.len() {
-                    s.seq_input_idx = 0;
+                if let Scheduler::Ucb(ref mut ucb) = s.scheduler {
+                    // Let the bandit pick based on the inputs' rewards
+                    s.cur_input_idx = ucb.select(&s.rewards);
+                } else {
+                    // Just get the next input
+                    s.seq_input_idx += 1;
+                    if s.seq_input_idx == s.input_list.len() {
+                        s.seq_input_idx = 0;
+                    }
+                    s.cur_input_idx = s.seq_input_idx;
                 }
 
-                if s.seq_input_idx >= s.num_old_inputs {
-                    s.num_old_inputs = s.seq_input_idx + 1;
+                if s.cur_input_idx >= s.num_old_inputs {
+                    s.num_old_inputs = s.cur_input_idx + 1;
                 }
-                s.cur_input_idx = s.seq_input_idx;
             }
         };
 
@@ -172,6 +202,61 @@ fn destroy(
     store.remove(STORE_INPUT_BYTES).unwrap();
     store.remove(STORE_RESTORE_INPUT).unwrap();
     store.remove(STORE_INPUT_PRIORITY).unwrap();
+    store.remove(STORE_INPUT_REWARDS).unwrap();
 
     Ok(())
 }
+
+impl State {
+    /// Parse the plugin_conf for our values
+    pub fn load_config(
+        &mut self,
+        core: &mut dyn PluginInterface,
+        conf: &HashMap<String, String>,
+    ) -> Result<()> {
+        let mut ucb = match conf.get("select_scheduler").map(|v| v.as_str()) {
+            None | Some("sequential") => return Ok(()),
+            Some("ucb") => UcbScheduler::default(),
+            Some(v) => {
+                core.error(&format!(
+                    "Unknown select_scheduler '{}' (expected sequential or ucb)",
+                    v
+                ));
+                return Err(From::from("Invalid config".to_string()));
+            }
+        };
+
+        if let Some(v) = conf.get("select_ucb_decay") {
+            match v.parse::<f64>() {
+                Ok(num) if num > 0.0 && num <= 1.0 => ucb.decay = num,
+                _ => {
+                    core.error(&format!(
+                        "select_ucb_decay must be a number in ]0, 1] : '{}'",
+                        v
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        if let Some(v) = conf.get("select_ucb_explore") {
+            match v.parse::<f64>() {
+                Ok(num) if num >= 0.0 => ucb.explore = num,
+                _ => {
+                    core.error(&format!(
+                        "select_ucb_explore must be a number >= 0 : '{}'",
+                        v
+                    ));
+                    return Err(From::from("Invalid config".to_string()));
+                }
+            }
+        }
+
+        core.info(&format!(
+            "Using UCB input scheduler (decay {}, explore {})",
+            ucb.decay, ucb.explore
+        ));
+        self.scheduler = Scheduler::Ucb(ucb);
+        Ok(())
+    }
+}